@@ -0,0 +1,25 @@
+#version 430 core
+
+in PARTICLE_OUT {
+    vec2 uv;
+    vec4 color;
+} particle_out;
+
+// same targets as oit_transparent.frag
+layout(location = 0) out vec4 accumColor;
+layout(location = 1) out float revealage;
+
+void main() {
+    // soft round dot, no texture yet
+    float distanceFromCenter = length(particle_out.uv - 0.5) * 2.0;
+    float alpha = particle_out.color.a * (1.0 - smoothstep(0.5, 1.0, distanceFromCenter));
+    if (alpha < 0.001) {
+        discard;
+    }
+
+    float z = gl_FragCoord.z;
+    float weight = alpha * max(0.01, min(3000.0, 10.0 / (0.00001 + pow(z / 200.0, 4.0))));
+
+    accumColor = vec4(particle_out.color.rgb * alpha, alpha) * weight;
+    revealage = alpha;
+}
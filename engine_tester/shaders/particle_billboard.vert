@@ -0,0 +1,25 @@
+#version 430 core
+
+// one quad corner per vertex, everything else comes per instance from ParticleSystem::instances
+layout(location = 0) in vec2 corner;
+layout(location = 1) in vec4 positionSize; // xyz world position, w is the width of the quad
+layout(location = 2) in vec4 color;
+
+out PARTICLE_OUT {
+    vec2 uv;
+    vec4 color;
+} particle_out;
+
+uniform mat4 projection;
+uniform mat4 view;
+
+void main() {
+    // the cameras right and up in world space are the first two rows of the view rotation
+    vec3 right = vec3(view[0][0], view[1][0], view[2][0]);
+    vec3 up = vec3(view[0][1], view[1][1], view[2][1]);
+    vec3 world = positionSize.xyz + (right * corner.x + up * corner.y) * positionSize.w;
+    gl_Position = projection * view * vec4(world, 1.0);
+
+    particle_out.uv = corner + 0.5;
+    particle_out.color = color;
+}
@@ -10,6 +10,7 @@ use acrust::model::transform::WorldCoords;
 use cgmath::One;
 use cgmath::Vector3;
 use acrust::graphics::materials::Material;
+use acrust::ecs::water::WaterSurface;
 //use cgmath::{Matrix4, Vector3, Deg, Point3};
 
 pub struct WaterRender {
@@ -98,15 +99,17 @@ impl WaterRender {
         }
     }
 
-    pub fn render(&mut self, smanager: &mut ShaderManager, time: f32, camera: &Camera) {
+    //the waves come from the physics water so floating stuff bobs with what gets drawn, it moves the water along every step
+    pub fn render(&mut self, smanager: &mut ShaderManager, water: &WaterSurface, camera: &Camera) {
         self.vao.bind();
+        self.position.position.y = water.base_height;
         
         // Set uniform values
         self.material.set_uniform("waterColor", &UniformValue::Vector4(vec4(0.0, 0.5, 1.0, 0.5)));
-        self.material.set_uniform("waveSpeed", &UniformValue::Float(1.0));
-        self.material.set_uniform("waveScale", &UniformValue::Float(0.1));
-        self.material.set_uniform("timeFactor", &UniformValue::Float(time * 0.1));
-        self.material.set_uniform("waveHeight", &UniformValue::Float(0.5));
+        self.material.set_uniform("waveSpeed", &UniformValue::Float(water.wave_speed));
+        self.material.set_uniform("waveScale", &UniformValue::Float(water.wave_scale));
+        self.material.set_uniform("timeFactor", &UniformValue::Float(water.time));
+        self.material.set_uniform("waveHeight", &UniformValue::Float(water.wave_height));
         self.material.set_uniform("lightPosition", &UniformValue::Vector4(vec4(0.0, 10.0, 0.0, 1.0)));
         self.material.set_uniform("lightColor", &UniformValue::Vector4(vec4(0.0, 1.0, 1.0, 1.0)));
         //self.material.set_uniform("model", &UniformValue::Matrix4(self.position.get_model_matrix()));
@@ -0,0 +1,82 @@
+use cgmath::{Quaternion, Rotation, Vector3};
+
+use super::collision_system::{compound_children, CollisionShape};
+
+//axis aligned boxes around colliders for the broad phase, two colliders whose boxes dont touch cant be colliding
+//so the real (slow) narrow phase check only runs for pairs that pass this
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Self {
+        Self { min, max }
+    }
+
+    pub fn from_center(center: Vector3<f32>, half_extents: Vector3<f32>) -> Self {
+        Self { min: center - half_extents, max: center + half_extents }
+    }
+
+    /// Box around `shape` at `position`/`rotation` (the full world rotation, same as check_collision gets)
+    pub fn of_shape(shape: &CollisionShape, position: Vector3<f32>, rotation: Quaternion<f32>) -> Self {
+        match shape {
+            CollisionShape::Sphere { radius } => Self::from_center(position, Vector3::new(*radius, *radius, *radius)),
+            CollisionShape::Box { width, height, depth } => {
+                Self::from_center(position, rotated_extents(Vector3::new(width / 2.0, height / 2.0, depth / 2.0), rotation))
+            },
+            CollisionShape::OBB { half_extents, .. } => Self::from_center(position, rotated_extents(*half_extents, rotation)),
+            CollisionShape::Capsule { radius, half_height } => {
+                let axis = rotation.rotate_vector(Vector3::new(0.0, *half_height, 0.0));
+                let reach = Vector3::new(axis.x.abs(), axis.y.abs(), axis.z.abs()) + Vector3::new(*radius, *radius, *radius);
+                Self::from_center(position, reach)
+            },
+            // the 2d checks dont look at z at all so neither can this
+            CollisionShape::Circle { radius } => Self::from_center(position, Vector3::new(*radius, *radius, f32::INFINITY)),
+            CollisionShape::Rectangle { width, height } => {
+                let reach = (width * width + height * height).sqrt() / 2.0;
+                Self::from_center(position, Vector3::new(reach, reach, f32::INFINITY))
+            },
+            CollisionShape::Heightfield(heightfield) => {
+                let bounds = heightfield.local_bounds();
+                let center = (bounds.min + bounds.max) / 2.0;
+                Self::from_center(position + rotation.rotate_vector(center), rotated_extents((bounds.max - bounds.min) / 2.0, rotation))
+            },
+            CollisionShape::ConvexHull(hull) => {
+                let corners: Vec<Vector3<f32>> = hull.vertices().iter().map(|vertex| position + rotation.rotate_vector(*vertex)).collect();
+                corners.iter().fold(Self::new(corners[0], corners[0]), |bounds, corner| bounds.merge(Self::new(*corner, *corner)))
+            },
+            CollisionShape::Compound(children) => {
+                compound_children(children, position, rotation)
+                    .map(|(_, child_position, child_rotation, child)| Self::of_shape(&child.shape, child_position, child_rotation))
+                    .reduce(Self::merge)
+                    .unwrap_or(Self::from_center(position, Vector3::new(0.0, 0.0, 0.0)))
+            },
+        }
+    }
+
+    pub fn overlaps(&self, other: &Aabb) -> bool {
+        self.min.x <= other.max.x && self.max.x >= other.min.x
+            && self.min.y <= other.max.y && self.max.y >= other.min.y
+            && self.min.z <= other.max.z && self.max.z >= other.min.z
+    }
+
+    pub fn merge(self, other: Aabb) -> Self {
+        Self {
+            min: Vector3::new(self.min.x.min(other.min.x), self.min.y.min(other.min.y), self.min.z.min(other.min.z)),
+            max: Vector3::new(self.max.x.max(other.max.x), self.max.y.max(other.max.y), self.max.z.max(other.max.z)),
+        }
+    }
+}
+
+//half size of the world aligned box that fits around a rotated box
+fn rotated_extents(half_extents: Vector3<f32>, rotation: Quaternion<f32>) -> Vector3<f32> {
+    let axes = [
+        rotation.rotate_vector(Vector3::new(half_extents.x, 0.0, 0.0)),
+        rotation.rotate_vector(Vector3::new(0.0, half_extents.y, 0.0)),
+        rotation.rotate_vector(Vector3::new(0.0, 0.0, half_extents.z)),
+    ];
+    axes.iter().fold(Vector3::new(0.0, 0.0, 0.0), |reach, axis| reach + Vector3::new(axis.x.abs(), axis.y.abs(), axis.z.abs()))
+}
@@ -0,0 +1,211 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
+
+use super::collision_system::CollisionShape;
+use super::convex_hull::ConvexHull;
+use super::heightfield::Heightfield;
+
+//continuous collision for fast bodies, the discrete check only looks at where things end up so anything moving
+//more than its own size in a step can skip right over a thin wall
+//the moving body gets swept as the biggest sphere that fits inside it, if that sphere hits something the real shape did too
+
+/// How far past the time of impact a body gets left so the discrete pass still sees a contact and stops it
+pub const CCD_SKIN: f32 = 0.01;
+
+/// Radius of the sphere swept for a shape, None for the 2d shapes since ccd is only done in 3d
+pub fn sweep_radius(shape: &CollisionShape) -> Option<f32> {
+    match shape {
+        CollisionShape::Sphere { radius } => Some(*radius),
+        CollisionShape::Box { width, height, depth } => Some(width.min(*height).min(*depth) / 2.0),
+        CollisionShape::OBB { half_extents, .. } => Some(half_extents.x.min(half_extents.y).min(half_extents.z)),
+        CollisionShape::Capsule { radius, .. } => Some(*radius),
+        // hulls that dont have the entitys origin inside them have nothing to sweep from
+        CollisionShape::ConvexHull(hull) => Some(hull.inner_radius()).filter(|radius| *radius > 0.0),
+        // compounds get swept child by child
+        CollisionShape::Compound(..) | CollisionShape::Heightfield(..) | CollisionShape::Circle { .. } | CollisionShape::Rectangle { .. } => None,
+    }
+}
+
+/// How much of `motion` (0 to 1) a sphere starting at `start` gets through before it touches `target`
+/// returns None if it never does or if its already overlapping at the start (the discrete pass deals with that)
+pub fn time_of_impact(
+    start: Vector3<f32>,
+    motion: Vector3<f32>,
+    radius: f32,
+    target: &CollisionShape,
+    target_position: Vector3<f32>,
+    target_rotation: Quaternion<f32>,
+) -> Option<f32> {
+    match target {
+        CollisionShape::Sphere { radius: target_radius } => {
+            sweep_sphere(start - target_position, motion, radius + target_radius)
+        },
+        CollisionShape::Box { width, height, depth } => {
+            let half_extents = Vector3::new(width / 2.0, height / 2.0, depth / 2.0);
+            sweep_box(start, motion, radius, half_extents, target_position, target_rotation)
+        },
+        CollisionShape::OBB { half_extents, .. } => {
+            sweep_box(start, motion, radius, *half_extents, target_position, target_rotation)
+        },
+        // capsules are character controllers, they move themselves and dont get swept against
+        CollisionShape::Capsule { .. } | CollisionShape::Circle { .. } | CollisionShape::Rectangle { .. } => None,
+        CollisionShape::Compound(..) => None, // same as above, the children get swept against one at a time
+        CollisionShape::Heightfield(heightfield) => sweep_terrain(start, motion, radius, heightfield, target_position, target_rotation),
+        CollisionShape::ConvexHull(hull) => sweep_hull(start, motion, radius, hull, target_position, target_rotation),
+    }
+}
+
+//ray against the hull with every face pushed out by the radius, same square corners as sweep_box
+fn sweep_hull(
+    start: Vector3<f32>,
+    motion: Vector3<f32>,
+    radius: f32,
+    hull: &ConvexHull,
+    hull_position: Vector3<f32>,
+    hull_rotation: Quaternion<f32>,
+) -> Option<f32> {
+    let length = motion.magnitude();
+    if length <= 0.0 {
+        return None;
+    }
+    let to_local = hull_rotation.invert();
+    let origin = to_local.rotate_vector(start - hull_position);
+    let direction = to_local.rotate_vector(motion / length);
+    let (distance, _) = hull.clip_ray(origin, direction, length, radius)?;
+    Some(distance / length)
+}
+
+//ray from the center against the terrain, then backed off so the sphere stops touching the surface instead of its center
+//(exact for a flat bit of ground, on bumpy ground its near enough for the discrete pass to take over)
+fn sweep_terrain(
+    start: Vector3<f32>,
+    motion: Vector3<f32>,
+    radius: f32,
+    heightfield: &Heightfield,
+    terrain_position: Vector3<f32>,
+    terrain_rotation: Quaternion<f32>,
+) -> Option<f32> {
+    let length = motion.magnitude();
+    if length <= 0.0 {
+        return None;
+    }
+    let to_local = terrain_rotation.invert();
+    let direction = to_local.rotate_vector(motion / length);
+    let origin = to_local.rotate_vector(start - terrain_position);
+    let (distance, normal) = heightfield.raycast(origin, direction, length + radius * 10.0)?;
+    let facing = -direction.dot(normal);
+    let touch = distance - radius / facing.max(0.1);
+    (0.0..=length).contains(&touch).then_some(touch / length)
+}
+
+//ray against a sphere of the two radii added together, `start` is relative to the targets center
+fn sweep_sphere(start: Vector3<f32>, motion: Vector3<f32>, radius: f32) -> Option<f32> {
+    let c = start.magnitude2() - radius * radius;
+    if c <= 0.0 {
+        return None;
+    }
+    let a = motion.magnitude2();
+    let b = start.dot(motion);
+    if a <= 0.0 || b >= 0.0 {
+        return None;
+    }
+    let discriminant = b * b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let t = (-b - discriminant.sqrt()) / a;
+    (0.0..=1.0).contains(&t).then_some(t)
+}
+
+//ray against the box grown by the radius on every side, done in the boxes own space
+//the grown box has square corners where the real sweep would be rounded so near edges it stops a little early, which is fine
+fn sweep_box(
+    start: Vector3<f32>,
+    motion: Vector3<f32>,
+    radius: f32,
+    half_extents: Vector3<f32>,
+    box_position: Vector3<f32>,
+    box_rotation: Quaternion<f32>,
+) -> Option<f32> {
+    let to_local = box_rotation.invert();
+    let start = to_local.rotate_vector(start - box_position);
+    let motion = to_local.rotate_vector(motion);
+    let half_extents = half_extents + Vector3::new(radius, radius, radius);
+
+    let mut enter: f32 = 0.0;
+    let mut exit: f32 = 1.0;
+    let mut started_inside = true;
+    for axis in 0..3 {
+        if start[axis].abs() > half_extents[axis] {
+            started_inside = false;
+        }
+        if motion[axis].abs() < 1e-8 {
+            if start[axis].abs() > half_extents[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (-half_extents[axis] - start[axis]) / motion[axis];
+        let t2 = (half_extents[axis] - start[axis]) / motion[axis];
+        enter = enter.max(t1.min(t2));
+        exit = exit.min(t1.max(t2));
+        if enter > exit {
+            return None;
+        }
+    }
+
+    if started_inside {
+        None
+    } else {
+        Some(enter)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::ecs::collision_system::{Collider, CollisionSystem};
+    use crate::ecs::components::Velocity;
+    use crate::ecs::physics::{PhysicsEntity, PhysicsSystem};
+    use crate::ecs::world::MovementSystem;
+    use crate::model::transform::WorldCoords;
+
+    // a 0.1m thick wall at x = 5 and a bullet fired at it from the origin, returns how far the bullet got
+    fn fire_bullet(ccd: bool) -> (f32, f32) {
+        let mut movement = MovementSystem::new();
+        let mut physics = PhysicsSystem::new().with_gravity(Vector3::new(0.0, 0.0, 0.0));
+        let mut collision = CollisionSystem::new();
+
+        movement.add_coords(0, WorldCoords::new(5.0, 0.0, 0.0, 0.0));
+        collision.add_collider(0, Collider::bounding_box(0.1, 2.0, 2.0));
+        physics.add_rigidbody(0, PhysicsEntity::static_body());
+
+        movement.add_coords(1, WorldCoords::new(0.0, 0.0, 0.0, 0.0));
+        movement.add_velocity(1, Velocity { direction: Vector3::new(1.0, 0.0, 0.0), speed: 500.0 });
+        collision.add_collider(1, Collider::sphere(0.02));
+        physics.add_rigidbody(1, PhysicsEntity::sphere(0.01, 0.02).with_restitution(0.0).with_ccd(ccd));
+
+        let delta_time = 1.0 / 60.0;
+        let mut furthest: f32 = 0.0;
+        for _ in 0..30 {
+            physics.update(&mut movement, delta_time);
+            movement.update(delta_time);
+            collision.update(&mut movement, &mut physics, delta_time);
+            furthest = furthest.max(movement.get_coords(1).unwrap().position.x);
+        }
+        (furthest, movement.get_velocity(1).unwrap().speed)
+    }
+
+    #[test]
+    fn bullet_stops_at_thin_wall() {
+        let (furthest, speed) = fire_bullet(true);
+        // wall face is at 4.95, the bullet is allowed to sink in by the skin but never past the middle
+        assert!(furthest < 5.0 - 0.02, "bullet got to {}", furthest);
+        assert!(furthest > 4.9, "bullet stopped early at {}", furthest);
+        assert!(speed < 1.0, "bullet is still going {}", speed);
+
+        // and without ccd it goes straight through
+        let (furthest, _) = fire_bullet(false);
+        assert!(furthest > 5.05, "bullet without ccd should have tunneled, got to {}", furthest);
+    }
+}
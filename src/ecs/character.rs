@@ -0,0 +1,516 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Zero};
+
+use super::ccd::sweep_radius;
+use super::collision_system::{Collider, CollisionEvent, CollisionShape, CollisionSystem};
+use super::components::Velocity;
+use super::gjk::{SupportMap, WorldShape};
+use super::physics::PhysicsSystem;
+use super::world::{ComponentStorage, MovementSystem};
+
+//characters move themselves instead of getting pushed around by the solver, every step the controller works out
+//where it can get to against the collision world (collide and slide) and hands that to the movement system as
+//its velocity, so to everything else it just looks like a kinematic body moving
+//the entity needs a collider (a capsule works best) and a kinematic rigidbody, World::spawn_character sets all that up
+
+const MAX_RESOLVE_ITERATIONS: usize = 4;
+const GROUND_PROBE: f32 = 0.05; // how far under the feet something can be and still count as standing on it
+
+#[derive(Debug, Clone)]
+pub struct CharacterController {
+    pub step_height: f32,   // ledges up to this high get walked up instead of blocking
+    pub max_slope: f32,     // radians, anything steeper is a wall
+    pub snap_distance: f32, // how far it gets pulled down to stay on the ground going down slopes and stairs
+    pub jump_speed: f32,
+    pub gravity: f32,
+
+    move_velocity: Vector3<f32>,
+    jump_requested: bool,
+    vertical_speed: f32,
+    grounded: bool,
+    ground_normal: Vector3<f32>,
+    ground_entity: Option<u32>,
+}
+
+impl Default for CharacterController {
+    fn default() -> Self {
+        Self {
+            step_height: 0.3,
+            max_slope: 45f32.to_radians(),
+            snap_distance: 0.2,
+            jump_speed: 5.0,
+            gravity: 9.81,
+            move_velocity: Vector3::zero(),
+            jump_requested: false,
+            vertical_speed: 0.0,
+            grounded: false,
+            ground_normal: Vector3::unit_y(),
+            ground_entity: None,
+        }
+    }
+}
+
+impl CharacterController {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_step_height(mut self, step_height: f32) -> Self {
+        self.step_height = step_height;
+        self
+    }
+
+    pub fn with_max_slope(mut self, degrees: f32) -> Self {
+        self.max_slope = degrees.to_radians();
+        self
+    }
+
+    pub fn with_snap_distance(mut self, snap_distance: f32) -> Self {
+        self.snap_distance = snap_distance;
+        self
+    }
+
+    pub fn with_jump_speed(mut self, jump_speed: f32) -> Self {
+        self.jump_speed = jump_speed;
+        self
+    }
+
+    pub fn with_gravity(mut self, gravity: f32) -> Self {
+        self.gravity = gravity;
+        self
+    }
+
+    /// How fast it should walk, only the horizontal part is used (gravity and jumping handle up and down)
+    pub fn set_move(&mut self, velocity: Vector3<f32>) {
+        self.move_velocity = Vector3::new(velocity.x, 0.0, velocity.z);
+    }
+
+    /// Jumps on the next update if its standing on something
+    pub fn jump(&mut self) {
+        self.jump_requested = true;
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        self.grounded
+    }
+
+    pub fn ground_normal(&self) -> Option<Vector3<f32>> {
+        self.grounded.then_some(self.ground_normal)
+    }
+
+    /// What its standing on, platforms it stands on carry it along with them
+    pub fn ground_entity(&self) -> Option<u32> {
+        self.ground_entity
+    }
+
+    pub fn vertical_speed(&self) -> f32 {
+        self.vertical_speed
+    }
+
+    //works out where the character ends up this step
+    fn plan(&mut self, mover: &Mover, start: Vector3<f32>, delta_time: f32) -> Vector3<f32> {
+        let up = Vector3::unit_y();
+        let was_grounded = self.grounded;
+
+        if self.jump_requested && was_grounded {
+            self.vertical_speed = self.jump_speed;
+        }
+        self.jump_requested = false;
+        let jumping = self.vertical_speed > 0.0;
+        let on_ground = was_grounded && !jumping;
+
+        if on_ground {
+            self.vertical_speed = 0.0;
+        } else {
+            self.vertical_speed -= self.gravity * delta_time;
+        }
+
+        // whatever its standing on moves by this much in the same step so it just gets added on at the end
+        let carried = match self.ground_entity {
+            Some(ground) if on_ground => mover.movement_system.get_velocity(ground).map_or(Vector3::zero(), |v| v.linear()) * delta_time,
+            _ => Vector3::zero(),
+        };
+
+        // up by the step height first so small ledges get walked onto instead of treated like walls
+        // edges up to here count as ground so the lip of a stair gets stood on instead of slid off
+        let step_top = on_ground.then_some(start.y - mover.foot_offset + self.step_height);
+        let mut position = start;
+        let mut raised = 0.0;
+        if on_ground && self.step_height > 0.0 {
+            let (stepped_up, _) = mover.slide(position, up * self.step_height, step_top);
+            raised = stepped_up.y - position.y;
+            position = stepped_up;
+        }
+
+        let (moved, _) = mover.slide(position, self.move_velocity * delta_time, step_top);
+        position = moved;
+
+        // then back down by the step plus this steps fall, and a bit more to stick to the ground going downhill
+        let fall = self.vertical_speed * delta_time - raised;
+        let snap = if on_ground { self.snap_distance } else { 0.0 };
+        let (mut landed, mut sweep) = mover.slide(position, up * (fall - snap), step_top);
+        if snap > 0.0 && sweep.ground.is_none() {
+            // nothing close enough underneath, walked off an edge so it just falls from here
+            (landed, sweep) = mover.slide(position, up * fall, step_top);
+        }
+        if sweep.ceiling && self.vertical_speed > 0.0 {
+            self.vertical_speed = 0.0;
+        }
+
+        let ground = if self.vertical_speed <= 0.0 { mover.find_ground(landed) } else { None };
+        self.grounded = ground.is_some();
+        self.ground_entity = ground.map(|(_, entity_id)| entity_id);
+        if let Some((normal, _)) = ground {
+            self.ground_normal = normal;
+            self.vertical_speed = 0.0;
+        }
+
+        landed + carried
+    }
+}
+
+//what a slide bumped into on the way
+#[derive(Default)]
+struct Sweep {
+    ground: Option<(Vector3<f32>, u32)>,
+    ceiling: bool,
+}
+
+//everything a controller needs to test moves against the world
+struct Mover<'a> {
+    collision_system: &'a CollisionSystem,
+    movement_system: &'a MovementSystem,
+    physics_system: &'a PhysicsSystem,
+    entity_id: u32,
+    collider: &'a Collider,
+    rotation: Quaternion<f32>,
+    walkable: f32, // cos of the max slope, normals with more y than this are ground
+    radius: f32,
+    foot_offset: f32, // how far below the position the bottom of the collider is
+}
+
+impl Mover<'_> {
+    //moves in steps no bigger than half the radius (so it cant skip through anything), pushing back out of whatever
+    //it ends up in after each one and dropping the part of the move that goes into walls so it slides along them
+    fn slide(&self, from: Vector3<f32>, displacement: Vector3<f32>, step_top: Option<f32>) -> (Vector3<f32>, Sweep) {
+        let mut sweep = Sweep::default();
+        let steps = (displacement.magnitude() / (self.radius * 0.5)).ceil().max(1.0) as usize;
+        let mut step = displacement / steps as f32;
+        let mut position = from;
+
+        for _ in 0..steps {
+            position += step;
+            for _ in 0..MAX_RESOLVE_ITERATIONS {
+                let contacts = self.collision_system.contacts_at(self.movement_system, self.entity_id, self.collider, position, self.rotation);
+                if contacts.is_empty() {
+                    break;
+                }
+                for contact in contacts {
+                    let normal = contact.normal;
+                    let pushable = self.physics_system.get_rigidbody(contact.entity_b)
+                        .is_some_and(|rb| !rb.is_static() && !rb.is_kinematic);
+                    if pushable && normal.y < self.walkable {
+                        // dynamic things can be stood on but walking into them is left to the solver, which shoves them
+                        continue;
+                    }
+
+                    if let Some(ground_normal) = self.ground_normal(&contact, step_top) {
+                        // ground pushes straight up so standing on a slope doesnt slowly slide down it
+                        position.y += contact.penetration / normal.y.max(0.1);
+                        step.y = step.y.max(0.0);
+                        if sweep.ground.is_none_or(|(best, _)| ground_normal.y > best.y) {
+                            sweep.ground = Some((ground_normal, contact.entity_b));
+                        }
+                        continue;
+                    }
+
+                    if normal.y >= 0.0 {
+                        // walls and too steep slopes only push sideways, otherwise walking into them climbs them
+                        let sideways = Vector3::new(normal.x, 0.0, normal.z);
+                        let length = sideways.magnitude();
+                        if length > 0.0001 {
+                            position += sideways / length * (contact.penetration / length.max(0.25));
+                        } else {
+                            position += normal * contact.penetration;
+                        }
+                    } else {
+                        sweep.ceiling = true;
+                        position += normal * contact.penetration;
+                    }
+
+                    let into = step.dot(normal);
+                    if into < 0.0 {
+                        step -= normal * into;
+                    }
+                }
+            }
+        }
+
+        (position, sweep)
+    }
+
+    //the most level walkable thing just under the feet, if theres anything
+    fn find_ground(&self, position: Vector3<f32>) -> Option<(Vector3<f32>, u32)> {
+        let probe = position - Vector3::unit_y() * GROUND_PROBE;
+        let step_top = Some(probe.y - self.foot_offset + GROUND_PROBE * 2.0);
+        self.collision_system.contacts_at(self.movement_system, self.entity_id, self.collider, probe, self.rotation)
+            .iter()
+            .filter_map(|contact| self.ground_normal(contact, step_top).map(|normal| (normal, contact.entity_b)))
+            .max_by(|(a, _), (b, _)| a.y.total_cmp(&b.y))
+    }
+
+    //Some(normal of whats being stood on) if the contact counts as ground. Either the contact itself is level enough,
+    //or its low enough (under `step_top`) and the face it landed on is, that second one is for the rounded bottom
+    //hitting the edge of a stair, where the contact normal comes out way steeper than the stair really is
+    fn ground_normal(&self, contact: &CollisionEvent, step_top: Option<f32>) -> Option<Vector3<f32>> {
+        if contact.normal.y >= self.walkable {
+            return Some(contact.normal);
+        }
+        if step_top.is_some_and(|top| contact.collision_point.y <= top) {
+            let face = self.face_normal(contact);
+            if face.y >= self.walkable {
+                return Some(face);
+            }
+        }
+        None
+    }
+
+    //the normal of the face that was actually touched, on a box edge its the most level of the faces meeting there
+    //anything that isnt a box just gives back the contact normal
+    fn face_normal(&self, contact: &CollisionEvent) -> Vector3<f32> {
+        let (Some(other), Some(coords)) = (
+            self.collision_system.get_collider(contact.entity_b),
+            self.movement_system.get_coords(contact.entity_b),
+        ) else {
+            return contact.normal;
+        };
+        let half_extents = match &other.shape {
+            CollisionShape::Box { width, height, depth } => Vector3::new(width / 2.0, height / 2.0, depth / 2.0),
+            CollisionShape::OBB { half_extents, .. } => *half_extents,
+            _ => return contact.normal,
+        };
+
+        let (position, rotation) = CollisionSystem::world_pose(other, coords);
+        let local = rotation.invert().rotate_vector(contact.collision_point - position);
+        let tolerance = contact.penetration + 0.01;
+
+        let mut best: Option<Vector3<f32>> = None;
+        for axis in 0..3 {
+            for sign in [-1.0, 1.0] {
+                if (local[axis] * sign - half_extents[axis]).abs() <= tolerance {
+                    let mut face = Vector3::zero();
+                    face[axis] = sign;
+                    let face = rotation.rotate_vector(face);
+                    if best.is_none_or(|best| face.y > best.y) {
+                        best = Some(face);
+                    }
+                }
+            }
+        }
+        best.unwrap_or(contact.normal)
+    }
+}
+
+pub struct CharacterSystem {
+    controllers: ComponentStorage<CharacterController>,
+}
+
+impl Default for CharacterSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CharacterSystem {
+    pub fn new() -> Self {
+        Self {
+            controllers: ComponentStorage::new(),
+        }
+    }
+
+    pub fn add_controller(&mut self, entity_id: u32, controller: CharacterController) {
+        self.controllers.insert(entity_id, controller);
+    }
+
+    pub fn get_controller(&self, entity_id: u32) -> Option<&CharacterController> {
+        self.controllers.get(entity_id)
+    }
+
+    pub fn get_controller_mut(&mut self, entity_id: u32) -> Option<&mut CharacterController> {
+        self.controllers.get_mut(entity_id)
+    }
+
+    pub fn remove_controller(&mut self, entity_id: u32) {
+        self.controllers.remove(entity_id);
+    }
+
+    /// Plans every characters move for the step, goes after the physics update and before the movement update
+    /// (the movement update is what actually moves them, same as everything else)
+    pub fn update(&mut self, movement_system: &mut MovementSystem, collision_system: &CollisionSystem, physics_system: &PhysicsSystem, delta_time: f32) {
+        if delta_time <= 0.0 {
+            return;
+        }
+
+        let mut entity_ids: Vec<u32> = self.controllers.iter().map(|(entity_id, _)| *entity_id).collect();
+        entity_ids.sort();
+
+        for entity_id in entity_ids {
+            let Some(collider) = collision_system.get_collider(entity_id) else { continue };
+            let Some(coords) = movement_system.get_coords(entity_id) else { continue };
+            let (start, rotation) = (coords.position, coords.rotation);
+            let (center, world_rotation) = CollisionSystem::world_pose(collider, coords);
+            let lowest = WorldShape::new(&collider.shape, center, world_rotation).support(-Vector3::unit_y());
+            let Some(controller) = self.controllers.get_mut(entity_id) else { continue };
+
+            let mover = Mover {
+                collision_system,
+                movement_system,
+                physics_system,
+                entity_id,
+                collider,
+                rotation,
+                walkable: controller.max_slope.cos(),
+                radius: sweep_radius(&collider.shape).unwrap_or(0.1).max(0.01),
+                foot_offset: start.y - lowest.y,
+            };
+            let target = controller.plan(&mover, start, delta_time);
+
+            let velocity = (target - start) / delta_time;
+            match movement_system.get_velocity_mut(entity_id) {
+                Some(current) => current.set_linear(velocity),
+                None => {
+                    let mut current = Velocity { direction: Vector3::zero(), speed: 0.0 };
+                    current.set_linear(velocity);
+                    movement_system.add_velocity(entity_id, current);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, Rotation3};
+
+    use super::*;
+    use crate::ecs::physics::PhysicsEntity;
+    use crate::model::transform::WorldCoords;
+
+    const CHARACTER: u32 = 1;
+
+    // a floor with its top at y = 0 and a 1.8 tall capsule standing on it at the origin, `extra` gets to add more
+    fn level(controller: CharacterController, extra: impl FnOnce(&mut MovementSystem, &mut CollisionSystem, &mut PhysicsSystem)) -> (MovementSystem, CollisionSystem, PhysicsSystem, CharacterSystem) {
+        let mut movement = MovementSystem::new();
+        let mut collision = CollisionSystem::new();
+        let mut physics = PhysicsSystem::new();
+        let mut characters = CharacterSystem::new();
+
+        movement.add_coords(0, WorldCoords::new(0.0, -0.5, 0.0, 0.0));
+        collision.add_collider(0, Collider::bounding_box(40.0, 1.0, 40.0));
+        physics.add_rigidbody(0, PhysicsEntity::static_body());
+
+        movement.add_coords(CHARACTER, WorldCoords::new(0.0, 0.9, 0.0, 0.0));
+        movement.add_velocity(CHARACTER, Velocity { direction: Vector3::zero(), speed: 0.0 });
+        collision.add_collider(CHARACTER, Collider::capsule(0.3, 1.8));
+        physics.add_rigidbody(CHARACTER, PhysicsEntity::kinematic());
+        characters.add_controller(CHARACTER, controller);
+
+        extra(&mut movement, &mut collision, &mut physics);
+        (movement, collision, physics, characters)
+    }
+
+    fn block(movement: &mut MovementSystem, collision: &mut CollisionSystem, physics: &mut PhysicsSystem, entity_id: u32, center: Vector3<f32>, size: Vector3<f32>) {
+        movement.add_coords(entity_id, WorldCoords::new(center.x, center.y, center.z, 0.0));
+        collision.add_collider(entity_id, Collider::bounding_box(size.x, size.y, size.z));
+        physics.add_rigidbody(entity_id, PhysicsEntity::static_body());
+    }
+
+    // walks at `walk` for `steps` frames, same order the world steps them in
+    fn walk(systems: &mut (MovementSystem, CollisionSystem, PhysicsSystem, CharacterSystem), walk: Vector3<f32>, steps: u32) -> Vector3<f32> {
+        let (movement, collision, physics, characters) = systems;
+        let delta_time = 1.0 / 60.0;
+        characters.get_controller_mut(CHARACTER).unwrap().set_move(walk);
+        for _ in 0..steps {
+            physics.update(movement, delta_time);
+            characters.update(movement, collision, physics, delta_time);
+            movement.update(delta_time);
+            collision.update(movement, physics, delta_time);
+        }
+        movement.get_coords(CHARACTER).unwrap().position
+    }
+
+    #[test]
+    fn walks_up_steps_but_not_walls() {
+        let step = |height: f32| move |movement: &mut MovementSystem, collision: &mut CollisionSystem, physics: &mut PhysicsSystem| {
+            block(movement, collision, physics, 2, Vector3::new(3.0, height / 2.0, 0.0), Vector3::new(2.0, height, 4.0));
+        };
+
+        let mut low = level(CharacterController::new(), step(0.2));
+        let position = walk(&mut low, Vector3::new(2.0, 0.0, 0.0), 90);
+        assert!(position.x > 2.5, "should have walked onto the step, stopped at {:?}", position);
+        assert!((position.y - 1.1).abs() < 0.05, "should be standing on top of the step, at {:?}", position);
+        assert!(low.3.get_controller(CHARACTER).unwrap().is_grounded());
+
+        let mut high = level(CharacterController::new(), step(0.6));
+        let position = walk(&mut high, Vector3::new(2.0, 0.0, 0.0), 90);
+        assert!(position.x < 1.75, "walked through a wall to {:?}", position);
+        assert!((position.y - 0.9).abs() < 0.05, "climbed a wall to {:?}", position);
+    }
+
+    #[test]
+    fn too_steep_slopes_are_walls() {
+        // ramps going up along +x, starting just past the character. Lifted so the bottom corner clears the floor,
+        // which leaves a lip at the start well under the step height
+        let ramp = |degrees: f32| move |movement: &mut MovementSystem, collision: &mut CollisionSystem, physics: &mut PhysicsSystem| {
+            let rotation = Quaternion::from_angle_z(Deg(degrees));
+            let half = Vector3::new(3.0, 0.1, 2.0);
+            let lip = 2.0 * half.y * degrees.to_radians().cos() + 0.01;
+            let center = Vector3::new(1.0, lip, 0.0) + rotation.rotate_vector(Vector3::new(half.x, -half.y, 0.0));
+            movement.add_coords(2, WorldCoords::new(center.x, center.y, center.z, 0.0));
+            collision.add_collider(2, Collider::obb(half, rotation));
+            physics.add_rigidbody(2, PhysicsEntity::static_body());
+        };
+
+        let mut gentle = level(CharacterController::new(), ramp(25.0));
+        let position = walk(&mut gentle, Vector3::new(2.0, 0.0, 0.0), 90);
+        assert!(position.y > 1.4, "should have walked up a 25 degree ramp, at {:?}", position);
+
+        let mut steep = level(CharacterController::new(), ramp(60.0));
+        let position = walk(&mut steep, Vector3::new(2.0, 0.0, 0.0), 90);
+        assert!(position.y < 1.2, "climbed a 60 degree ramp to {:?}", position);
+        assert!(position.x < 1.5);
+    }
+
+    #[test]
+    fn rides_a_moving_platform_and_jumps_off_it() {
+        let mut systems = level(CharacterController::new(), |movement, collision, physics| {
+            // a kinematic platform with its top at y = 1, under where the character gets dropped
+            movement.add_coords(2, WorldCoords::new(0.0, 0.75, 0.0, 0.0));
+            let mut velocity = Velocity { direction: Vector3::zero(), speed: 0.0 };
+            velocity.set_linear(Vector3::new(1.0, 0.0, 0.0));
+            movement.add_velocity(2, velocity);
+            collision.add_collider(2, Collider::bounding_box(3.0, 0.5, 3.0));
+            physics.add_rigidbody(2, PhysicsEntity::kinematic());
+        });
+        systems.0.get_coords_mut(CHARACTER).unwrap().position = Vector3::new(0.0, 2.0, 0.0);
+
+        // settles onto it then gets carried, without walking itself
+        walk(&mut systems, Vector3::zero(), 30);
+        let controller = systems.3.get_controller(CHARACTER).unwrap();
+        assert!(controller.is_grounded());
+        assert_eq!(controller.ground_entity(), Some(2));
+        let before = systems.0.get_coords(CHARACTER).unwrap().position;
+        let platform_before = systems.0.get_coords(2).unwrap().position;
+        let after = walk(&mut systems, Vector3::zero(), 60);
+        let carried = after.x - before.x;
+        let platform_moved = systems.0.get_coords(2).unwrap().position.x - platform_before.x;
+        assert!(platform_moved > 0.9);
+        assert!((carried - platform_moved).abs() < 0.05, "moved {} while the platform moved {}", carried, platform_moved);
+        assert!((after.y - 1.9).abs() < 0.05, "should still be standing on it, at {:?}", after);
+
+        systems.3.get_controller_mut(CHARACTER).unwrap().jump();
+        let airborne = walk(&mut systems, Vector3::zero(), 10);
+        assert!(airborne.y > 2.0 && !systems.3.get_controller(CHARACTER).unwrap().is_grounded());
+        walk(&mut systems, Vector3::zero(), 90);
+        assert!(systems.3.get_controller(CHARACTER).unwrap().is_grounded(), "should have landed again");
+    }
+}
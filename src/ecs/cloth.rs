@@ -0,0 +1,616 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Zero};
+
+use super::aabb::Aabb;
+use super::collision_system::{CollisionShape, CollisionSystem};
+use super::layers::QueryFilter;
+use super::physics::PhysicsSystem;
+use super::world::{ComponentStorage, MovementSystem};
+
+//cloth and ropes are just a bunch of points moved with verlet (the velocity is wherever it is minus where it was last step)
+//then pulled back together by sticks between them, so nothing here touches the rigidbody solver. They get pushed out of
+//colliders but never push back, a flag wrapping around a pole moves the flag and not the pole
+//a sheet starts flat in its local xy plane with particle 0 at the top left corner and the rows going down -y, a rope is
+//a line of particles from start to end
+
+/// Holds a particle still, either somewhere in the world or stuck to an entity so it gets carried along
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClothPin {
+    World(Vector3<f32>),
+    Entity { entity_id: u32, local: Vector3<f32> }, // local is in the entities space, rotation included
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClothParticle {
+    pub position: Vector3<f32>,
+    pub previous: Vector3<f32>,
+    pub inverse_mass: f32,
+    pub pin: Option<ClothPin>,
+}
+
+impl ClothParticle {
+    fn new(position: Vector3<f32>, inverse_mass: f32) -> Self {
+        Self { position, previous: position, inverse_mass, pin: None }
+    }
+
+    // pinned particles act like they weigh infinity so the sticks only move the free end
+    fn weight(&self) -> f32 {
+        if self.pin.is_some() { 0.0 } else { self.inverse_mass }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StickKind {
+    Stretch, // neighbours, and the diagonals on a sheet so it doesnt shear
+    Bend,    // skips one particle so folding over gets pushed back flat
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClothStick {
+    pub a: usize,
+    pub b: usize,
+    pub rest: f32,
+    pub kind: StickKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClothKind {
+    Sheet { columns: usize, rows: usize },
+    Rope { radius: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Cloth {
+    pub particles: Vec<ClothParticle>,
+    pub sticks: Vec<ClothStick>,
+    pub kind: ClothKind,
+    pub stiffness: f32,      // 0 to 1, how much of the stretch gets taken out over a step
+    pub bend_stiffness: f32, // same for folding, 0 lets it fold like a napkin
+    pub iterations: u32,
+    pub damping: f32,        // fraction of the velocity lost per second, keeps it from flapping forever
+    pub thickness: f32,      // how far the particles stay off colliders
+    pub friction: f32,       // 0 to 1, how much sliding along a collider gets stopped
+    pub wind_response: f32,  // scales the wind and drag, a heavy tarp wants less than a silk flag
+    pub filter: QueryFilter, // which colliders it bumps into
+    last_delta_time: f32,
+}
+
+impl Cloth {
+    /// A `width` by `height` sheet of `columns` by `rows` particles, `position` is the top left corner and `rotation`
+    /// turns the local xy plane it starts in
+    pub fn sheet(position: Vector3<f32>, rotation: Quaternion<f32>, width: f32, height: f32, columns: usize, rows: usize, mass: f32) -> Self {
+        let (columns, rows) = (columns.max(2), rows.max(2));
+        let inverse_mass = (columns * rows) as f32 / mass.max(1e-6);
+        let mut particles = Vec::with_capacity(columns * rows);
+        for row in 0..rows {
+            for column in 0..columns {
+                let local = Vector3::new(
+                    width * column as f32 / (columns - 1) as f32,
+                    -height * row as f32 / (rows - 1) as f32,
+                    0.0,
+                );
+                particles.push(ClothParticle::new(position + rotation.rotate_vector(local), inverse_mass));
+            }
+        }
+
+        let mut cloth = Self::with_particles(particles, ClothKind::Sheet { columns, rows });
+        let index = |column: usize, row: usize| row * columns + column;
+        for row in 0..rows {
+            for column in 0..columns {
+                let here = index(column, row);
+                if column + 1 < columns {
+                    cloth.add_stick(here, index(column + 1, row), StickKind::Stretch);
+                }
+                if row + 1 < rows {
+                    cloth.add_stick(here, index(column, row + 1), StickKind::Stretch);
+                }
+                if column + 1 < columns && row + 1 < rows {
+                    cloth.add_stick(here, index(column + 1, row + 1), StickKind::Stretch);
+                    cloth.add_stick(index(column + 1, row), index(column, row + 1), StickKind::Stretch);
+                }
+                if column + 2 < columns {
+                    cloth.add_stick(here, index(column + 2, row), StickKind::Bend);
+                }
+                if row + 2 < rows {
+                    cloth.add_stick(here, index(column, row + 2), StickKind::Bend);
+                }
+            }
+        }
+        cloth
+    }
+
+    /// A rope of `segments` pieces from `start` to `end`, it can go slack but wont stretch
+    pub fn rope(start: Vector3<f32>, end: Vector3<f32>, segments: usize, mass: f32, radius: f32) -> Self {
+        let segments = segments.max(1);
+        let inverse_mass = (segments + 1) as f32 / mass.max(1e-6);
+        let particles = (0..=segments)
+            .map(|i| ClothParticle::new(start + (end - start) * (i as f32 / segments as f32), inverse_mass))
+            .collect();
+
+        let mut cloth = Self::with_particles(particles, ClothKind::Rope { radius }).with_bend_stiffness(0.0).with_thickness(radius);
+        for i in 0..segments {
+            cloth.add_stick(i, i + 1, StickKind::Stretch);
+            if i + 2 <= segments {
+                cloth.add_stick(i, i + 2, StickKind::Bend);
+            }
+        }
+        cloth
+    }
+
+    fn with_particles(particles: Vec<ClothParticle>, kind: ClothKind) -> Self {
+        Self {
+            particles,
+            sticks: Vec::new(),
+            kind,
+            stiffness: 1.0,
+            bend_stiffness: 0.2,
+            iterations: 8,
+            damping: 0.5,
+            thickness: 0.02,
+            friction: 0.3,
+            wind_response: 1.0,
+            filter: QueryFilter::new(),
+            last_delta_time: 0.0,
+        }
+    }
+
+    fn add_stick(&mut self, a: usize, b: usize, kind: StickKind) {
+        let rest = (self.particles[b].position - self.particles[a].position).magnitude();
+        self.sticks.push(ClothStick { a, b, rest, kind });
+    }
+
+    pub fn with_stiffness(mut self, stiffness: f32) -> Self {
+        self.stiffness = stiffness.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_bend_stiffness(mut self, stiffness: f32) -> Self {
+        self.bend_stiffness = stiffness.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_iterations(mut self, iterations: u32) -> Self {
+        self.iterations = iterations.max(1);
+        self
+    }
+
+    pub fn with_damping(mut self, damping: f32) -> Self {
+        self.damping = damping.max(0.0);
+        self
+    }
+
+    pub fn with_thickness(mut self, thickness: f32) -> Self {
+        self.thickness = thickness.max(0.0);
+        self
+    }
+
+    pub fn with_friction(mut self, friction: f32) -> Self {
+        self.friction = friction.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_wind_response(mut self, response: f32) -> Self {
+        self.wind_response = response;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: QueryFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+
+    /// Index of the particle at `column`, `row` on a sheet, None for ropes or off the edge
+    pub fn particle_index(&self, column: usize, row: usize) -> Option<usize> {
+        match self.kind {
+            ClothKind::Sheet { columns, rows } if column < columns && row < rows => Some(row * columns + column),
+            _ => None,
+        }
+    }
+
+    pub fn pin_in_place(&mut self, index: usize) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.pin = Some(ClothPin::World(particle.position));
+        }
+    }
+
+    /// Sticks the particle to the entity right where it is now, so it keeps the same spot on it as the entity moves
+    pub fn pin_to_entity(&mut self, index: usize, entity_id: u32, movement_system: &MovementSystem) -> Result<(), String> {
+        let coords = movement_system.get_coords(entity_id).ok_or(format!("entity {} has no coords to pin cloth to", entity_id))?;
+        let particle = self.particles.get_mut(index).ok_or(format!("cloth has no particle {}", index))?;
+        let local = coords.rotation.invert().rotate_vector(particle.position - coords.position);
+        particle.pin = Some(ClothPin::Entity { entity_id, local });
+        Ok(())
+    }
+
+    pub fn unpin(&mut self, index: usize) {
+        if let Some(particle) = self.particles.get_mut(index) {
+            particle.pin = None;
+        }
+    }
+
+    /// Pins the whole top row of a sheet where it is, for banners
+    pub fn pin_top_edge(&mut self) {
+        if let ClothKind::Sheet { columns, .. } = self.kind {
+            for column in 0..columns {
+                self.pin_in_place(column);
+            }
+        }
+    }
+
+    /// Box around every particle, thickness included
+    pub fn bounds(&self) -> Aabb {
+        let mut min = Vector3::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vector3::new(f32::MIN, f32::MIN, f32::MIN);
+        for particle in &self.particles {
+            min = Vector3::new(min.x.min(particle.position.x), min.y.min(particle.position.y), min.z.min(particle.position.z));
+            max = Vector3::new(max.x.max(particle.position.x), max.y.max(particle.position.y), max.z.max(particle.position.z));
+        }
+        let pad = Vector3::new(self.thickness, self.thickness, self.thickness);
+        Aabb::new(min - pad, max + pad)
+    }
+
+    pub fn step(&mut self, movement_system: &MovementSystem, collision_system: &CollisionSystem, physics_system: &PhysicsSystem, delta_time: f32) {
+        if delta_time <= 0.0 || self.particles.is_empty() {
+            return;
+        }
+        self.move_pins(movement_system);
+
+        // verlet keeps velocity as a distance per step, so it needs rescaling when the step length changes
+        let time_scale = if self.last_delta_time > 0.0 { delta_time / self.last_delta_time } else { 1.0 };
+        let velocity_delta_time = if self.last_delta_time > 0.0 { self.last_delta_time } else { delta_time };
+        let keep = (1.0 - self.damping * delta_time).max(0.0);
+        let normals = self.particle_normals();
+        for (i, particle) in self.particles.iter_mut().enumerate() {
+            if particle.pin.is_some() {
+                continue;
+            }
+            let velocity = (particle.position - particle.previous) / velocity_delta_time;
+            let fields = physics_system.sample_fields(movement_system, collision_system, particle.position, velocity);
+            // the air only pushes on whats facing it, a flag edge on to the wind barely moves
+            let air = match self.kind {
+                ClothKind::Sheet { .. } => normals[i] * fields.air.dot(normals[i]),
+                ClothKind::Rope { .. } => fields.air - normals[i] * fields.air.dot(normals[i]),
+            };
+            let acceleration = fields.gravity + fields.acceleration + air * self.wind_response;
+            let moved = (particle.position - particle.previous) * time_scale * keep;
+            particle.previous = particle.position;
+            particle.position += moved + acceleration * delta_time * delta_time;
+        }
+        self.last_delta_time = delta_time;
+
+        // everything it could reach this step, grabbed once so each iteration isnt walking every collider again
+        let mut reach = self.bounds();
+        let pad = Vector3::new(0.5, 0.5, 0.5);
+        reach = Aabb::new(reach.min - pad, reach.max + pad);
+        let obstacles = collision_system.parts_in_bounds(movement_system, &reach, &self.filter);
+
+        // stiffness is for the whole step, this is what each iteration needs to take out to get there
+        let per_iteration = |stiffness: f32| 1.0 - (1.0 - stiffness).powf(1.0 / self.iterations as f32);
+        let (stretch, bend) = (per_iteration(self.stiffness), per_iteration(self.bend_stiffness));
+        let slack = matches!(self.kind, ClothKind::Rope { .. });
+        for iteration in 0..self.iterations {
+            // going through in the same order every time drags the whole sheet toward the first particles, flipping
+            // it each iteration evens that out
+            for k in 0..self.sticks.len() {
+                let stick = self.sticks[if iteration % 2 == 0 { k } else { self.sticks.len() - 1 - k }];
+                let stiffness = match stick.kind {
+                    StickKind::Stretch => stretch,
+                    StickKind::Bend => bend,
+                };
+                if stiffness <= 0.0 {
+                    continue;
+                }
+                let (a, b) = (self.particles[stick.a], self.particles[stick.b]);
+                let (weight_a, weight_b) = (a.weight(), b.weight());
+                let delta = b.position - a.position;
+                let length = delta.magnitude();
+                if weight_a + weight_b <= 0.0 || length < 1e-6 || (slack && length < stick.rest) {
+                    continue;
+                }
+                let correction = delta * ((length - stick.rest) / length * stiffness / (weight_a + weight_b));
+                self.particles[stick.a].position += correction * weight_a;
+                self.particles[stick.b].position -= correction * weight_b;
+            }
+            for particle in self.particles.iter_mut().filter(|particle| particle.pin.is_none()) {
+                for (_, position, rotation, shape) in &obstacles {
+                    collide_particle(particle, shape, *position, *rotation, self.thickness, self.friction);
+                }
+            }
+        }
+    }
+
+    fn move_pins(&mut self, movement_system: &MovementSystem) {
+        for particle in &mut self.particles {
+            let target = match particle.pin {
+                Some(ClothPin::World(position)) => position,
+                Some(ClothPin::Entity { entity_id, local }) => match movement_system.get_coords(entity_id) {
+                    Some(coords) => coords.position + coords.rotation.rotate_vector(local),
+                    None => continue,
+                },
+                None => continue,
+            };
+            particle.previous = particle.position;
+            particle.position = target;
+        }
+    }
+
+    // sheets get a smoothed surface normal per particle, ropes get the direction along the rope instead
+    fn particle_normals(&self) -> Vec<Vector3<f32>> {
+        let mut normals = vec![Vector3::zero(); self.particles.len()];
+        match self.kind {
+            ClothKind::Sheet { .. } => {
+                for triangle in self.triangles() {
+                    let [a, b, c] = triangle.map(|i| self.particles[i].position);
+                    let face = (b - a).cross(c - a);
+                    for i in triangle {
+                        normals[i] += face;
+                    }
+                }
+            },
+            ClothKind::Rope { .. } => {
+                let last = self.particles.len() - 1;
+                for (i, normal) in normals.iter_mut().enumerate() {
+                    *normal = self.particles[(i + 1).min(last)].position - self.particles[i.saturating_sub(1)].position;
+                }
+            },
+        }
+        normals.iter().map(|normal| if normal.magnitude2() > 1e-12 { normal.normalize() } else { Vector3::zero() }).collect()
+    }
+
+    // wound so the normals point out the +z side of the sheet as it was built
+    fn triangles(&self) -> Vec<[usize; 3]> {
+        let ClothKind::Sheet { columns, rows } = self.kind else { return Vec::new() };
+        let mut triangles = Vec::with_capacity((columns - 1) * (rows - 1) * 2);
+        for row in 0..rows - 1 {
+            for column in 0..columns - 1 {
+                let here = row * columns + column;
+                let (right, below) = (here + 1, here + columns);
+                triangles.push([here, below, right]);
+                triangles.push([right, below, below + 1]);
+            }
+        }
+        triangles
+    }
+
+    /// Interleaved position and normal, 6 floats a vertex like `Mesh::new` wants. Sheets have every vertex twice
+    /// (front then back with the normal flipped) so both sides light right, ropes are a tube of `ROPE_SIDES` around
+    /// each particle. The count never changes so upload it once with `Mesh::new_dynamic` then `update_vertices` each frame
+    pub fn vertices(&self) -> Vec<f32> {
+        let normals = self.particle_normals();
+        match self.kind {
+            ClothKind::Sheet { .. } => {
+                let mut vertices = Vec::with_capacity(self.particles.len() * 12);
+                for side in [1.0, -1.0] {
+                    for (particle, normal) in self.particles.iter().zip(&normals) {
+                        push_vertex(&mut vertices, particle.position, normal * side);
+                    }
+                }
+                vertices
+            },
+            ClothKind::Rope { radius } => {
+                let mut vertices = Vec::with_capacity(self.particles.len() * ROPE_SIDES * 6);
+                // carry the same sideways direction down the rope so the tube doesnt twist up
+                let mut side = Vector3::zero();
+                for (particle, tangent) in self.particles.iter().zip(&normals) {
+                    side -= tangent * side.dot(*tangent);
+                    if side.magnitude2() < 1e-6 {
+                        let helper = if tangent.y.abs() < 0.9 { Vector3::unit_y() } else { Vector3::unit_x() };
+                        side = helper - tangent * helper.dot(*tangent);
+                    }
+                    side = side.normalize();
+                    let other = tangent.cross(side);
+                    for j in 0..ROPE_SIDES {
+                        let angle = j as f32 / ROPE_SIDES as f32 * std::f32::consts::TAU;
+                        let out = side * angle.cos() + other * angle.sin();
+                        push_vertex(&mut vertices, particle.position + out * radius, out);
+                    }
+                }
+                vertices
+            },
+        }
+    }
+
+    /// Goes with `vertices`, only depends on the layout so it only needs grabbing once
+    pub fn indices(&self) -> Vec<i32> {
+        match self.kind {
+            ClothKind::Sheet { .. } => {
+                let back = self.particles.len();
+                let triangles = self.triangles();
+                let front = triangles.iter().flat_map(|&[a, b, c]| [a, b, c]);
+                let back = triangles.iter().flat_map(|&[a, b, c]| [a + back, c + back, b + back]);
+                front.chain(back).map(|i| i as i32).collect()
+            },
+            ClothKind::Rope { .. } => {
+                let mut indices = Vec::with_capacity(self.particles.len().saturating_sub(1) * ROPE_SIDES * 6);
+                for ring in 0..self.particles.len().saturating_sub(1) {
+                    for j in 0..ROPE_SIDES {
+                        let a = ring * ROPE_SIDES + j;
+                        let b = ring * ROPE_SIDES + (j + 1) % ROPE_SIDES;
+                        let (c, d) = (a + ROPE_SIDES, b + ROPE_SIDES);
+                        indices.extend([a, b, c, b, d, c].map(|i| i as i32));
+                    }
+                }
+                indices
+            },
+        }
+    }
+}
+
+pub const ROPE_SIDES: usize = 6;
+
+fn push_vertex(vertices: &mut Vec<f32>, position: Vector3<f32>, normal: Vector3<f32>) {
+    vertices.extend_from_slice(&[position.x, position.y, position.z, normal.x, normal.y, normal.z]);
+}
+
+//pushes a particle out to `thickness` off the shape, then takes out the velocity along the normal and holds back some of the sliding
+fn collide_particle(particle: &mut ClothParticle, shape: &CollisionShape, position: Vector3<f32>, rotation: Quaternion<f32>, thickness: f32, friction: f32) {
+    let local = rotation.invert().rotate_vector(particle.position - position);
+    let (surface, normal) = match shape {
+        CollisionShape::Sphere { radius } => round_surface(local, Vector3::zero(), *radius),
+        CollisionShape::Capsule { radius, half_height } => {
+            round_surface(local, Vector3::new(0.0, local.y.clamp(-half_height, *half_height), 0.0), *radius)
+        },
+        CollisionShape::Box { width, height, depth } => box_surface(local, Vector3::new(*width, *height, *depth) * 0.5),
+        CollisionShape::OBB { half_extents, .. } => box_surface(local, *half_extents),
+        _ => return,
+    };
+    let pushed = surface + normal * thickness;
+    if (local - surface).dot(normal) >= thickness {
+        return;
+    }
+    let normal = rotation.rotate_vector(normal);
+    particle.position = position + rotation.rotate_vector(pushed);
+
+    // no bounce, whatever it had going in or out along the normal is gone so the push doesnt turn into a launch
+    let moved = particle.position - particle.previous;
+    let sliding = moved - normal * moved.dot(normal);
+    particle.previous += normal * moved.dot(normal);
+    particle.position -= sliding * friction;
+}
+
+// closest point on a sphere around `center` and the way out, for spheres and the round bits of capsules
+fn round_surface(local: Vector3<f32>, center: Vector3<f32>, radius: f32) -> (Vector3<f32>, Vector3<f32>) {
+    let offset = local - center;
+    let distance = offset.magnitude();
+    let normal = if distance > 1e-6 { offset / distance } else { Vector3::unit_y() };
+    (center + normal * radius, normal)
+}
+
+// closest point on the boxes surface, inside it goes out through whichever face is nearest
+fn box_surface(local: Vector3<f32>, half: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let clamped = Vector3::new(local.x.clamp(-half.x, half.x), local.y.clamp(-half.y, half.y), local.z.clamp(-half.z, half.z));
+    let offset = local - clamped;
+    if offset.magnitude2() > 1e-12 {
+        return (clamped, offset.normalize());
+    }
+    let depths = [half.x - local.x.abs(), half.y - local.y.abs(), half.z - local.z.abs()];
+    let axis = (0..3).min_by(|a, b| depths[*a].total_cmp(&depths[*b])).unwrap_or(1);
+    let mut normal = Vector3::zero();
+    normal[axis] = if local[axis] < 0.0 { -1.0 } else { 1.0 };
+    let mut surface = local;
+    surface[axis] = half[axis] * normal[axis];
+    (surface, normal)
+}
+
+pub struct ClothSystem {
+    cloths: ComponentStorage<Cloth>,
+}
+
+impl Default for ClothSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ClothSystem {
+    pub fn new() -> Self {
+        Self {
+            cloths: ComponentStorage::new(),
+        }
+    }
+
+    pub fn add_cloth(&mut self, entity_id: u32, cloth: Cloth) {
+        self.cloths.insert(entity_id, cloth);
+    }
+
+    pub fn get_cloth(&self, entity_id: u32) -> Option<&Cloth> {
+        self.cloths.get(entity_id)
+    }
+
+    pub fn get_cloth_mut(&mut self, entity_id: u32) -> Option<&mut Cloth> {
+        self.cloths.get_mut(entity_id)
+    }
+
+    pub fn remove_cloth(&mut self, entity_id: u32) {
+        self.cloths.remove(entity_id);
+    }
+
+    /// Steps every cloth against where the bodies ended up, goes after the collision update
+    pub fn update(&mut self, movement_system: &MovementSystem, collision_system: &CollisionSystem, physics_system: &PhysicsSystem, delta_time: f32) {
+        for entity_id in self.cloths.sorted_ids() {
+            if let Some(cloth) = self.cloths.get_mut(entity_id) {
+                cloth.step(movement_system, collision_system, physics_system, delta_time);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::collision_system::Collider;
+    use crate::ecs::force_fields::ForceField;
+    use crate::model::transform::WorldCoords;
+
+    fn systems() -> (MovementSystem, CollisionSystem, PhysicsSystem) {
+        (MovementSystem::new(), CollisionSystem::new(), PhysicsSystem::new())
+    }
+
+    #[test]
+    fn banner_hangs_over_a_ball_without_going_through() {
+        let (mut movement, mut collision, physics) = systems();
+        movement.add_coords(1, WorldCoords::new(0.5, -1.2, 0.3, 0.0));
+        collision.add_collider(1, Collider::sphere(0.4));
+
+        let mut banner = Cloth::sheet(Vector3::new(0.0, 0.0, 0.0), Quaternion::new(1.0, 0.0, 0.0, 0.0), 1.0, 1.5, 8, 12, 0.5);
+        banner.pin_top_edge();
+        let rest: Vec<f32> = banner.sticks.iter().filter(|stick| stick.kind == StickKind::Stretch).map(|stick| stick.rest).collect();
+        for _ in 0..300 {
+            banner.step(&movement, &collision, &physics, 1.0 / 60.0);
+        }
+
+        let center = Vector3::new(0.5, -1.2, 0.3);
+        for particle in &banner.particles {
+            assert!(particle.position.x.is_finite() && particle.position.y.is_finite());
+            assert!((particle.position - center).magnitude() > 0.4, "particle inside the ball at {:?}", particle.position);
+        }
+        let stretched = banner.sticks.iter().filter(|stick| stick.kind == StickKind::Stretch).zip(&rest)
+            .map(|(stick, rest)| (banner.particles[stick.b].position - banner.particles[stick.a].position).magnitude() / rest)
+            .fold(0.0, f32::max);
+        assert!(stretched < 1.1, "stretched to {}", stretched);
+        // the ball pokes through the sheet from the +z side so the bottom has to drape around it on the -z side
+        let bottom = banner.particles[banner.particle_index(4, 11).unwrap()].position;
+        assert!(bottom.z < 0.0, "bottom should drape off the ball, at {:?}", bottom);
+    }
+
+    #[test]
+    fn rope_follows_its_pin_and_wind_pushes_it() {
+        let (mut movement, mut collision, mut physics) = systems();
+        movement.add_coords(1, WorldCoords::new(0.0, 5.0, 0.0, 0.0));
+        let mut rope = Cloth::rope(Vector3::new(0.0, 5.0, 0.0), Vector3::new(0.0, 3.0, 0.0), 10, 0.2, 0.03).with_damping(1.0);
+        rope.pin_to_entity(0, 1, &movement).unwrap();
+
+        // walk the anchor over 2 units then let the swing die down
+        for step in 0..360 {
+            movement.get_coords_mut(1).unwrap().position.x = (step as f32 / 60.0).min(1.0) * 2.0;
+            rope.step(&movement, &collision, &physics, 1.0 / 60.0);
+        }
+        assert_eq!(rope.particles[0].position, Vector3::new(2.0, 5.0, 0.0));
+        let end = rope.particles[10].position;
+        assert!((end.x - 2.0).abs() < 0.1 && (end.y - 3.0).abs() < 0.1, "rope end at {:?}", end);
+
+        movement.add_coords(2, WorldCoords::new(2.0, 4.0, 0.0, 0.0));
+        collision.add_collider(2, Collider::sphere(5.0).as_trigger());
+        physics.add_force_field(2, ForceField::wind(Vector3::new(0.0, 0.0, 10.0), 2.0));
+        for _ in 0..240 {
+            rope.step(&movement, &collision, &physics, 1.0 / 60.0);
+        }
+        assert!(rope.particles[10].position.z > 0.5, "wind should blow the rope along +z, end at {:?}", rope.particles[10].position);
+    }
+
+    #[test]
+    fn buffers_match_the_layout() {
+        let sheet = Cloth::sheet(Vector3::zero(), Quaternion::new(1.0, 0.0, 0.0, 0.0), 2.0, 1.0, 5, 4, 1.0);
+        let vertices = sheet.vertices();
+        let indices = sheet.indices();
+        assert_eq!(vertices.len(), 5 * 4 * 2 * 6);
+        assert_eq!(indices.len(), 4 * 3 * 2 * 3 * 2);
+        assert!(indices.iter().all(|&i| (i as usize) < vertices.len() / 6));
+        // flat sheet facing +z on the front copy and -z on the back
+        assert_eq!(&vertices[3..6], &[0.0, 0.0, 1.0]);
+        assert_eq!(&vertices[5 * 4 * 6 + 3..5 * 4 * 6 + 6], &[0.0, 0.0, -1.0]);
+
+        let rope = Cloth::rope(Vector3::zero(), Vector3::new(0.0, -1.0, 0.0), 4, 1.0, 0.05);
+        assert_eq!(rope.vertices().len(), 5 * ROPE_SIDES * 6);
+        assert_eq!(rope.indices().len(), 4 * ROPE_SIDES * 6);
+    }
+}
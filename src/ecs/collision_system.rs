@@ -1,6 +1,18 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::time::Instant;
 use cgmath::{InnerSpace, Matrix4, Quaternion, Rotation, Vector2, Vector3, Zero};
 use crate::ecs::physics::PhysicsSystem;
+use crate::ecs::gjk::{gjk_epa, WorldShape};
+use crate::ecs::ccd::{sweep_radius, time_of_impact, CCD_SKIN};
+use crate::ecs::layers::{layer_bit, QueryFilter, ALL_LAYERS, DEFAULT_LAYER};
+use crate::ecs::queries::{ray_shape, RaycastHit};
+use crate::ecs::aabb::Aabb;
+use crate::ecs::heightfield::Heightfield;
+use crate::ecs::convex_hull::ConvexHull;
+use crate::ecs::snapshot::ContactCache;
+use crate::ecs::stats::CollisionStats;
+use crate::ecs::materials::{ContactMaterial, MaterialId, PhysicsMaterial, PhysicsMaterials};
+use crate::ecs::contact_manifold::{box_box_contacts, reduce_points, ContactManifold, ContactModification, ContactPoint, ModifiableContact, FEATURE_NONE};
 use crate::graphics::gl_wrapper::ShaderProgram;
 use crate::model::transform::WorldCoords;
 use super::components::Velocity;
@@ -14,14 +26,63 @@ pub enum CollisionShape {
     Sphere { radius: f32 },
     Box { width: f32, height: f32, depth: f32 },
     OBB {half_extents: Vector3<f32>, rotation: Quaternion<f32>}, // Oriented Bounding Box the half_extents is just like how far the wall of the box is from the center
+    Capsule { radius: f32, half_height: f32 }, // standing up along y, half_height is half the straight bit in the middle (not counting the caps)
+    Compound(Vec<(Collider, LocalTransform)>), // a bunch of shapes stuck together, each placed relative to the entity
+    Heightfield(Heightfield), // terrain, only really makes sense on a static body
+    ConvexHull(ConvexHull), // any convex shape from a cloud of points, rocks, crystals, low poly props
+}
+
+/// Where a child of a compound collider sits relative to the entity
+#[derive(Debug, Clone, Copy)]
+pub struct LocalTransform {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+}
+
+impl Default for LocalTransform {
+    fn default() -> Self {
+        Self {
+            position: Vector3::zero(),
+            rotation: Quaternion::new(1.0, 0.0, 0.0, 0.0),
+        }
+    }
+}
+
+impl LocalTransform {
+    pub fn new(position: Vector3<f32>, rotation: Quaternion<f32>) -> Self {
+        Self { position, rotation }
+    }
+
+    pub fn from_position(position: Vector3<f32>) -> Self {
+        Self { position, ..Self::default() }
+    }
+}
+
+/// The children of a compound placed in the world (or wherever `position`/`rotation` is), with their index in the compound
+pub(crate) fn compound_children(
+    children: &[(Collider, LocalTransform)],
+    position: Vector3<f32>,
+    rotation: Quaternion<f32>,
+) -> impl Iterator<Item = (usize, Vector3<f32>, Quaternion<f32>, &Collider)> {
+    children.iter().enumerate().map(move |(index, (child, transform))| {
+        let coords = WorldCoords {
+            position: position + rotation.rotate_vector(transform.position),
+            rotation: rotation * transform.rotation,
+            scale: Vector3::new(1.0, 1.0, 1.0),
+        };
+        let (child_position, child_rotation) = CollisionSystem::world_pose(child, &coords);
+        (index, child_position, child_rotation, child)
+    })
 }
 
 #[derive(Debug, Clone)]
 pub struct Collider {
     pub shape: CollisionShape,
     pub is_trigger: bool, // If true, doesn't prevent movement but still fires events
-    pub layer: u32, // For collision filtering
+    pub membership: u32, // bitmask of the layers this collider is in
+    pub filter: u32,     // bitmask of the layers it collides with
     pub offset: Vector3<f32>,
+    pub material: Option<MaterialId>, // see PhysicsMaterials, None goes by the rigidbody
 }
 
 impl Collider {
@@ -29,8 +90,10 @@ impl Collider {
         Self {
             shape: CollisionShape::Circle { radius },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         }
     }
     
@@ -38,8 +101,10 @@ impl Collider {
         Self {
             shape: CollisionShape::Rectangle { width, height },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         }
     }
     
@@ -47,8 +112,10 @@ impl Collider {
         Self {
             shape: CollisionShape::Sphere { radius },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         }
     }
     
@@ -56,8 +123,10 @@ impl Collider {
         Self {
             shape: CollisionShape::Box { width, height, depth },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         }
     }
 
@@ -65,18 +134,79 @@ impl Collider {
         Self {
             shape: CollisionShape::OBB {half_extents, rotation: rotation },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         }
     }
     
+    /// `height` is the whole thing top to bottom, caps included
+    pub fn capsule(radius: f32, height: f32) -> Self {
+        Self {
+            shape: CollisionShape::Capsule { radius, half_height: (height / 2.0 - radius).max(0.0) },
+            is_trigger: false,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
+            offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
+        }
+    }
+
+    /// Several shapes acting as one collider, each child keeps its own shape and offset on top of its transform.
+    /// Only the compounds own trigger flag and layers count, the childrens are ignored
+    pub fn compound(children: Vec<(Collider, LocalTransform)>) -> Self {
+        Self {
+            shape: CollisionShape::Compound(children),
+            is_trigger: false,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
+            offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
+        }
+    }
+
+    pub fn heightfield(heightfield: Heightfield) -> Self {
+        Self {
+            shape: CollisionShape::Heightfield(heightfield),
+            is_trigger: false,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
+            offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
+        }
+    }
+
+    /// See `ConvexHull::new` for building one from points
+    pub fn convex_hull(hull: ConvexHull) -> Self {
+        Self {
+            shape: CollisionShape::ConvexHull(hull),
+            is_trigger: false,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
+            offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
+        }
+    }
+
     pub fn as_trigger(mut self) -> Self {
         self.is_trigger = true;
         self
     }
     
+    /// Puts the collider in just layer `layer` (0 to 31)
     pub fn with_layer(mut self, layer: u32) -> Self {
-        self.layer = layer;
+        self.membership = layer_bit(layer);
+        self
+    }
+
+    pub fn with_membership(mut self, membership: u32) -> Self {
+        self.membership = membership;
+        self
+    }
+
+    pub fn with_filter(mut self, filter: u32) -> Self {
+        self.filter = filter;
         self
     }
 
@@ -85,6 +215,12 @@ impl Collider {
         self
     }
 
+    /// Makes it out of the material registered under `name` in `CollisionSystem::materials`
+    pub fn with_material(mut self, name: &str) -> Self {
+        self.material = Some(MaterialId::new(name));
+        self
+    }
+
      pub fn as_obb(&self) -> Option<(&Vector3<f32>, &Quaternion<f32>)> {
         match &self.shape {
             CollisionShape::OBB{half_extents, rotation} => Some((half_extents, rotation)),
@@ -100,13 +236,49 @@ pub struct CollisionEvent {
     pub collision_point: Vector3<f32>,
     pub normal: Vector3<f32>, // Direction to separate entity_a from entity_b
     pub penetration: f32,
+    pub child_a: Option<usize>, // which child of a compound got hit, None if it isnt one
+    pub child_b: Option<usize>,
+}
+
+//a shape that actually gets tested, placed in the world, with its child index if it came out of a compound
+type Part<'a> = (Option<usize>, Vector3<f32>, Quaternion<f32>, &'a Collider);
+
+//one child pair of a compound collision, with the child shapes and where they were so the manifold can clip them
+struct ChildContact<'a> {
+    event: CollisionEvent,
+    pose_a: (Vector3<f32>, Quaternion<f32>),
+    shape_a: &'a CollisionShape,
+    pose_b: (Vector3<f32>, Quaternion<f32>),
+    shape_b: &'a CollisionShape,
+}
+
+/// Raised when pairs start or stop overlapping, so gameplay code doesnt have to diff the collision events itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactEvent {
+    TriggerEnter { trigger: u32, other: u32 },
+    TriggerStay { trigger: u32, other: u32 }, // every frame after the enter while theyre still overlapping
+    TriggerExit { trigger: u32, other: u32 },
+    CollisionBegin { entity_a: u32, entity_b: u32 },
+    CollisionEnd { entity_a: u32, entity_b: u32 },
 }
 
+/// A callback registered with `add_contact_modifier`
+pub type ContactModifier = Box<dyn Fn(&mut ModifiableContact)>;
+
 pub struct CollisionSystem {
     colliders: HashMap<u32, Collider>,
     collision_events: Vec<CollisionEvent>,
-    // Collision matrix - which layers can collide with which
-    collision_matrix: HashMap<(u32, u32), bool>,
+    contact_events: Vec<ContactEvent>,
+    // every pair overlapping last frame (lower id, higher id), with the trigger in it if its a trigger pair
+    overlapping: HashMap<(u32, u32), Option<u32>>,
+    // contacts that stick around between frames, keyed by (lower entity id, higher entity id)
+    manifolds: HashMap<(u32, u32), ContactManifold>,
+    // extra checks on top of the layer masks, a pair only collides if every one of these says yes
+    pair_filters: Vec<Box<dyn Fn(u32, u32) -> bool>>,
+    // get to change or switch off every touching pair after the narrow phase, right before solving
+    contact_modifiers: Vec<ContactModifier>,
+    pub materials: PhysicsMaterials,
+    stats: CollisionStats, // from the last update
     collision_shader: Option<ShaderProgram>,//todo here do I want to just use an ID and use SHADER_MANAGER or do I want to not do that... and have it store its self
     //i wonder if there is a way i could structure shader manager so that it is bascially all static functions that dont use an object but all mutate a single list of shader programs or just
     //shader program ideas
@@ -119,11 +291,22 @@ impl CollisionSystem {
         Self {
             colliders: HashMap::new(),
             collision_events: Vec::new(),
-            collision_matrix: HashMap::new(),
+            contact_events: Vec::new(),
+            overlapping: HashMap::new(),
+            manifolds: HashMap::new(),
+            pair_filters: Vec::new(),
+            contact_modifiers: Vec::new(),
+            materials: PhysicsMaterials::new(),
+            stats: CollisionStats::default(),
             collision_shader: None,
         }
     }
     
+    /// Counts and timings from the last update
+    pub fn stats(&self) -> &CollisionStats {
+        &self.stats
+    }
+
     pub fn add_collider(&mut self, entity_id: u32, collider: Collider) {
         self.colliders.insert(entity_id, collider);
     }
@@ -140,14 +323,39 @@ impl CollisionSystem {
         self.colliders.get_mut(&entity_id)
     }
     
-    // Set which layers can collide with each other
-    pub fn set_collision_layers(&mut self, layer_a: u32, layer_b: u32, can_collide: bool) {
-        self.collision_matrix.insert((layer_a, layer_b), can_collide);
-        self.collision_matrix.insert((layer_b, layer_a), can_collide);
+    /// Adds a callback that gets asked about every pair whose layers already match, returning false keeps them from colliding.
+    /// The ids come in (lower, higher) order
+    pub fn add_pair_filter(&mut self, filter: impl Fn(u32, u32) -> bool + 'static) {
+        self.pair_filters.push(Box::new(filter));
     }
-    
-    fn can_collide(&self, layer_a: u32, layer_b: u32) -> bool {
-        self.collision_matrix.get(&(layer_a, layer_b)).copied().unwrap_or(true)
+
+    pub fn clear_pair_filters(&mut self) {
+        self.pair_filters.clear();
+    }
+
+    /// Adds a callback that sees every touching (non trigger) pair each step after the contacts are found and before
+    /// theyre solved, it can switch the pair off, bend its normal, change its friction/bounce or give it a surface
+    /// velocity. Pairs come in (lower, higher) id order and the callbacks run in the order they were added
+    pub fn add_contact_modifier(&mut self, modifier: impl Fn(&mut ModifiableContact) + 'static) {
+        self.add_boxed_contact_modifier(Box::new(modifier));
+    }
+
+    /// Same as `add_contact_modifier` for a callback thats already boxed
+    pub fn add_boxed_contact_modifier(&mut self, modifier: ContactModifier) {
+        self.contact_modifiers.push(modifier);
+    }
+
+    pub fn clear_contact_modifiers(&mut self) {
+        self.contact_modifiers.clear();
+    }
+
+    //both sides have to want each other, then the callbacks get a say
+    fn can_collide(&self, entity_a: u32, collider_a: &Collider, entity_b: u32, collider_b: &Collider) -> bool {
+        if collider_a.membership & collider_b.filter == 0 || collider_b.membership & collider_a.filter == 0 {
+            return false;
+        }
+        let pair = (entity_a.min(entity_b), entity_a.max(entity_b));
+        self.pair_filters.iter().all(|filter| filter(pair.0, pair.1))
     }
 
     pub fn init_collision_debug(&mut self){
@@ -262,7 +470,7 @@ impl CollisionSystem {
             }
         }
         
-        println!("FINAL: Normal: {:?}, Penetration: {:.4}\n", collision_normal, min_penetration);
+        // println!("FINAL: Normal: {:?}, Penetration: {:.4}\n", collision_normal, min_penetration);
         
         // Additional safety check: verify the normal makes sense
         if min_penetration > 0.0 && collision_normal.magnitude2() > 0.0001 {
@@ -461,6 +669,8 @@ impl CollisionSystem {
                 collision_point,
                 normal,
                 penetration,
+                child_a: None,
+                child_b: None,
             })
         } else {
             None
@@ -595,53 +805,500 @@ impl CollisionSystem {
 
     pub fn update(&mut self, movement_system: &mut MovementSystem, physics_system: &mut PhysicsSystem, delta_time: f32) {
         self.collision_events.clear();
-        
-        let mut entities_with_collision: Vec<(u32, Vector3<f32>, Quaternion<f32>, &Collider)> = Vec::new();
+        let mut stats = CollisionStats {
+            colliders: self.colliders.len(),
+            triggers: self.colliders.values().filter(|collider| collider.is_trigger).count(),
+            ..CollisionStats::default()
+        };
+
+        // fast bodies get pulled back to where they first hit something before the normal checks run
+        let started = Instant::now();
+        stats.ccd_bodies = self.sweep_fast_bodies(movement_system, physics_system, delta_time);
+        stats.ccd_time = started.elapsed();
+
+        let started = Instant::now();
+        let mut entities_with_collision = Vec::new();
         
         for (entity_id, collider) in &self.colliders {
             if let Some(coords) = movement_system.get_coords(*entity_id) {
-                // put the rotation of the two together I have it like this if
-                // you want rotation of collider offset from the entity rotation
-                let world_rotation = match &collider.shape {
-                    CollisionShape::OBB { rotation, .. } => {
-                        coords.rotation * rotation  // Combine rotations
-                    },
-                    _ => coords.rotation,
-                };
-                
-                // Apply offset with rotation
-                let rotated_offset = coords.rotation.rotate_vector(collider.offset);
-                let collider_pos = coords.position + rotated_offset;
+                let (collider_pos, world_rotation) = Self::world_pose(collider, coords);
                 
                 entities_with_collision.push((
                     *entity_id, 
                     collider_pos,
                     world_rotation,  // This is now world_rotation for OBB
-                    collider
+                    collider,
+                    Aabb::of_shape(&collider.shape, collider_pos, world_rotation),
                 ));
             }
         }
-        
-        for i in 0..entities_with_collision.len() {
-            for j in (i + 1)..entities_with_collision.len() {
-                let (entity_a, pos_a, rot_a, collider_a) = entities_with_collision[i];
-                let (entity_b, pos_b, rot_b, collider_b) = entities_with_collision[j];
+
+        //sorted so a pair always comes out as (lower id, higher id), the manifold cache is keyed on that
+        entities_with_collision.sort_by_key(|(entity_id, ..)| *entity_id);
+
+        //broad phase, boxes that dont touch means the shapes cant either
+        let count = entities_with_collision.len();
+        stats.broad_phase_pairs = count * count.saturating_sub(1) / 2;
+        let jointed = physics_system.non_colliding_pairs();
+        let mut candidates = Vec::new();
+        for i in 0..count {
+            for j in (i + 1)..count {
+                let (entity_a, _, _, collider_a, bounds_a) = entities_with_collision[i];
+                let (entity_b, _, _, collider_b, bounds_b) = entities_with_collision[j];
+                if bounds_a.overlaps(&bounds_b)
+                    && !jointed.contains(&(entity_a.min(entity_b), entity_a.max(entity_b)))
+                    && self.can_collide(entity_a, collider_a, entity_b, collider_b) {
+                    candidates.push((i, j));
+                }
+            }
+        }
+        stats.candidate_pairs = candidates.len();
+        stats.broad_phase_time = started.elapsed();
+
+        let started = Instant::now();
+        let mut touching: HashSet<(u32, u32)> = HashSet::new();
+        let mut overlapping: HashMap<(u32, u32), Option<u32>> = HashMap::new();
+
+        for (i, j) in candidates {
+            let (entity_a, pos_a, rot_a, collider_a, _) = entities_with_collision[i];
+            let (entity_b, pos_b, rot_b, collider_b, _) = entities_with_collision[j];
+
+            //compounds get checked child by child (and terrain triangle by triangle), the deepest hit stands in for the whole pair
+            let compound = Self::has_parts(collider_a) || Self::has_parts(collider_b);
+            let child_contacts = if compound {
+                self.part_collisions(entity_a, pos_a, collider_a, rot_a, entity_b, pos_b, collider_b, rot_b)
+            } else {
+                Vec::new()
+            };
+            let collision = if compound {
+                Self::deepest(&child_contacts)
+            } else {
+                self.check_collision(entity_a, pos_a, collider_a, rot_a, entity_b, pos_b, collider_b, rot_b)
+            };
+            
+            if let Some(collision) = collision {
+                self.collision_events.push(collision.clone());
+
+                let trigger = if collider_a.is_trigger {
+                    Some(entity_a)
+                } else if collider_b.is_trigger {
+                    Some(entity_b)
+                } else {
+                    None
+                };
+                overlapping.insert((entity_a, entity_b), trigger);
                 
-                if !self.can_collide(collider_a.layer, collider_b.layer) {
+                if !collider_a.is_trigger && !collider_b.is_trigger {
+                    let manifold = self.manifolds
+                        .entry((entity_a, entity_b))
+                        .or_insert_with(|| ContactManifold::new(entity_a, entity_b, collision.normal));
+
+                    if compound {
+                        Self::update_compound_manifold(manifold, &child_contacts, (pos_a, rot_a), (pos_b, rot_b));
+                    } else {
+                        Self::update_manifold(
+                            manifold,
+                            &collision,
+                            (pos_a, rot_a, &collider_a.shape),
+                            (pos_b, rot_b, &collider_b.shape),
+                        );
+                    }
+                    touching.insert((entity_a, entity_b));
+                }
+            }
+        }
+
+        //pairs that stopped touching lose their cached contacts
+        self.manifolds.retain(|pair, _| touching.contains(pair));
+
+        self.update_contact_events(overlapping);
+
+        //friction and bounce for every touching pair, mixed from their materials
+        let pairs: Vec<(u32, u32)> = self.manifolds.keys().copied().collect();
+        for (entity_a, entity_b) in pairs {
+            let material = self.contact_material(physics_system, entity_a, entity_b);
+            if let Some(manifold) = self.manifolds.get_mut(&(entity_a, entity_b)) {
+                manifold.material = material;
+            }
+        }
+        //modifications only last a step, everything starts back enabled before the callbacks get a go
+        let mut pairs: Vec<(&(u32, u32), &mut ContactManifold)> = self.manifolds.iter_mut().collect();
+        pairs.sort_by_key(|(pair, _)| **pair);
+        for (_, manifold) in pairs {
+            manifold.modification = ContactModification::default();
+            for modifier in &self.contact_modifiers {
+                modifier(&mut ModifiableContact::new(manifold));
+            }
+            if !manifold.is_enabled() {
+                // nothing left over to warm start with when it gets switched back on
+                for point in manifold.points.iter_mut() {
+                    point.normal_impulse = 0.0;
+                    point.tangent_impulse = [0.0, 0.0];
+                }
+            }
+        }
+
+        stats.colliding_pairs = self.overlapping.len();
+        stats.manifolds = self.manifolds.len();
+        stats.contact_points = self.manifolds.values().map(|manifold| manifold.points.len()).sum();
+        stats.narrow_phase_time = started.elapsed();
+        self.stats = stats;
+
+        //everything gets solved together once all the contacts for the step are known
+        let mut pairs: Vec<(&(u32, u32), &mut ContactManifold)> = self.manifolds.iter_mut().collect();
+        pairs.sort_by_key(|(pair, _)| **pair);
+        let mut manifolds: Vec<&mut ContactManifold> = pairs.into_iter()
+            .map(|(_, manifold)| manifold)
+            .filter(|manifold| manifold.is_enabled())
+            .collect();
+        physics_system.solve_constraints(movement_system, &mut manifolds, delta_time);
+    }
+
+    //diffs this frames overlapping pairs against last frames, sorted so the events always come out in the same order
+    fn update_contact_events(&mut self, overlapping: HashMap<(u32, u32), Option<u32>>) {
+        self.contact_events.clear();
+
+        let other_of = |(entity_a, entity_b): (u32, u32), trigger: u32| if trigger == entity_a { entity_b } else { entity_a };
+
+        let mut current: Vec<(&(u32, u32), &Option<u32>)> = overlapping.iter().collect();
+        current.sort_by_key(|(pair, _)| **pair);
+        for (pair, trigger) in current {
+            let existed = self.overlapping.contains_key(pair);
+            let event = match (*trigger, existed) {
+                (Some(trigger), false) => ContactEvent::TriggerEnter { trigger, other: other_of(*pair, trigger) },
+                (Some(trigger), true) => ContactEvent::TriggerStay { trigger, other: other_of(*pair, trigger) },
+                (None, false) => ContactEvent::CollisionBegin { entity_a: pair.0, entity_b: pair.1 },
+                (None, true) => continue,
+            };
+            self.contact_events.push(event);
+        }
+
+        let mut ended: Vec<(&(u32, u32), &Option<u32>)> = self.overlapping.iter()
+            .filter(|(pair, _)| !overlapping.contains_key(pair))
+            .collect();
+        ended.sort_by_key(|(pair, _)| **pair);
+        for (pair, trigger) in ended {
+            self.contact_events.push(match *trigger {
+                Some(trigger) => ContactEvent::TriggerExit { trigger, other: other_of(*pair, trigger) },
+                None => ContactEvent::CollisionEnd { entity_a: pair.0, entity_b: pair.1 },
+            });
+        }
+
+        self.overlapping = overlapping;
+    }
+
+    //where a collider actually is, the offset turns with the entity and OBBs put their own rotation on top
+    //(I have it like this if you want the collider rotated away from the entity rotation)
+    pub(crate) fn world_pose(collider: &Collider, coords: &WorldCoords) -> (Vector3<f32>, Quaternion<f32>) {
+        let rotation = match &collider.shape {
+            CollisionShape::OBB { rotation, .. } => coords.rotation * rotation,
+            _ => coords.rotation,
+        };
+        (coords.position + coords.rotation.rotate_vector(collider.offset), rotation)
+    }
+
+    /// Every non trigger collider overlapping the trigger on `trigger_id` right now, going by the same layer rules as
+    /// the normal trigger events. Sorted by id
+    pub fn inside_trigger(&self, movement_system: &MovementSystem, trigger_id: u32) -> Vec<u32> {
+        let Some(trigger) = self.colliders.get(&trigger_id) else { return Vec::new() };
+        let Some(coords) = movement_system.get_coords(trigger_id) else { return Vec::new() };
+        let (position, rotation) = Self::world_pose(trigger, coords);
+        let bounds = Aabb::of_shape(&trigger.shape, position, rotation);
+
+        let mut inside: Vec<u32> = self.colliders.iter()
+            .filter(|(entity_id, collider)| {
+                **entity_id != trigger_id && !collider.is_trigger && self.can_collide(trigger_id, trigger, **entity_id, collider)
+            })
+            .filter_map(|(entity_id, collider)| {
+                let (other_position, other_rotation) = Self::world_pose(collider, movement_system.get_coords(*entity_id)?);
+                if !bounds.overlaps(&Aabb::of_shape(&collider.shape, other_position, other_rotation)) {
+                    return None;
+                }
+                self.check_collision(trigger_id, position, trigger, rotation, *entity_id, other_position, collider, other_rotation)
+                    .map(|_| *entity_id)
+            })
+            .collect();
+        inside.sort();
+        inside
+    }
+
+    /// Everything `collider` would be overlapping if entity `entity_id` was at `position`/`rotation` instead of where it is,
+    /// triggers are skipped. Each event has the asked about entity as A so the normals push it out
+    pub fn contacts_at(
+        &self,
+        movement_system: &MovementSystem,
+        entity_id: u32,
+        collider: &Collider,
+        position: Vector3<f32>,
+        rotation: Quaternion<f32>,
+    ) -> Vec<CollisionEvent> {
+        let mut others: Vec<(&u32, &Collider)> = self.colliders.iter()
+            .filter(|(other_id, other)| **other_id != entity_id && !other.is_trigger && self.can_collide(entity_id, collider, **other_id, other))
+            .collect();
+        others.sort_by_key(|(other_id, _)| **other_id);
+
+        let coords = WorldCoords { position, rotation, scale: Vector3::new(1.0, 1.0, 1.0) };
+        let (position, rotation) = Self::world_pose(collider, &coords);
+
+        others.into_iter().filter_map(|(other_id, other)| {
+            let other_coords = movement_system.get_coords(*other_id)?;
+            let (other_position, other_rotation) = Self::world_pose(other, other_coords);
+            self.check_collision(entity_id, position, collider, rotation, *other_id, other_position, other, other_rotation)
+        }).collect()
+    }
+
+    /// Closest collider the ray hits within `max_distance`, `direction` doesnt have to be normalized
+    pub fn raycast(
+        &self,
+        movement_system: &MovementSystem,
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Option<RaycastHit> {
+        self.raycast_all(movement_system, origin, direction, max_distance, filter).into_iter().next()
+    }
+
+    /// Every collider the ray hits within `max_distance`, nearest first
+    pub fn raycast_all(
+        &self,
+        movement_system: &MovementSystem,
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+        max_distance: f32,
+        filter: &QueryFilter,
+    ) -> Vec<RaycastHit> {
+        if direction.magnitude2() < 1e-12 {
+            return Vec::new();
+        }
+        let direction = direction.normalize();
+
+        let mut hits: Vec<RaycastHit> = self.colliders.iter()
+            .filter(|(entity_id, collider)| filter.accepts(**entity_id, collider.membership, collider.is_trigger))
+            .filter_map(|(entity_id, collider)| {
+                let coords = movement_system.get_coords(*entity_id)?;
+                let (position, rotation) = Self::world_pose(collider, coords);
+                Self::parts(collider, position, rotation).into_iter()
+                    .filter_map(|(child, child_position, child_rotation, child_collider)| {
+                        let (distance, normal) = ray_shape(origin, direction, max_distance, &child_collider.shape, child_position, child_rotation)?;
+                        Some(RaycastHit { entity_id: *entity_id, point: origin + direction * distance, normal, distance, child })
+                    })
+                    .min_by(|a, b| a.distance.total_cmp(&b.distance))
+            })
+            .collect();
+        hits.sort_by(|a, b| a.distance.total_cmp(&b.distance).then(a.entity_id.cmp(&b.entity_id)));
+        hits
+    }
+
+    /// Ids of every collider `shape` would be overlapping if it was at `position`/`rotation`, sorted
+    pub fn overlap(
+        &self,
+        movement_system: &MovementSystem,
+        shape: CollisionShape,
+        position: Vector3<f32>,
+        rotation: Quaternion<f32>,
+        filter: &QueryFilter,
+    ) -> Vec<u32> {
+        // the query shape pretends to be an entity that cant exist so nothing gets skipped as "itself"
+        let query_id = u32::MAX;
+        let query = Collider { shape, is_trigger: false, membership: ALL_LAYERS, filter: ALL_LAYERS, offset: Vector3::zero(), material: None };
+
+        let mut found: Vec<u32> = self.colliders.iter()
+            .filter(|(entity_id, collider)| filter.accepts(**entity_id, collider.membership, collider.is_trigger))
+            .filter_map(|(entity_id, collider)| {
+                let coords = movement_system.get_coords(*entity_id)?;
+                let (other_position, other_rotation) = Self::world_pose(collider, coords);
+                self.check_collision(query_id, position, &query, rotation, *entity_id, other_position, collider, other_rotation)
+                    .map(|_| *entity_id)
+            })
+            .collect();
+        found.sort();
+        found
+    }
+
+    pub fn overlap_sphere(&self, movement_system: &MovementSystem, center: Vector3<f32>, radius: f32, filter: &QueryFilter) -> Vec<u32> {
+        self.overlap(movement_system, CollisionShape::Sphere { radius }, center, Quaternion::new(1.0, 0.0, 0.0, 0.0), filter)
+    }
+
+    /// Whether `point` is inside the collider on `entity_id`, layers and the trigger flag dont matter
+    pub fn contains_point(&self, movement_system: &MovementSystem, entity_id: u32, point: Vector3<f32>) -> bool {
+        let Some(collider) = self.colliders.get(&entity_id) else { return false };
+        let Some(coords) = movement_system.get_coords(entity_id) else { return false };
+        let (position, rotation) = Self::world_pose(collider, coords);
+        if !Aabb::of_shape(&collider.shape, position, rotation).overlaps(&Aabb::new(point, point)) {
+            return false;
+        }
+        // a tiny sphere stands in for the point, same trick as `overlap`
+        let query = Collider { shape: CollisionShape::Sphere { radius: 1e-4 }, is_trigger: false, membership: ALL_LAYERS, filter: ALL_LAYERS, offset: Vector3::zero(), material: None };
+        self.check_collision(u32::MAX, point, &query, Quaternion::new(1.0, 0.0, 0.0, 0.0), entity_id, position, collider, rotation).is_some()
+    }
+
+    //every shape (compound children split out) whose box touches `bounds`, placed in the world. For things that do
+    //their own collision against the world like cloth, triggers are skipped
+    pub(crate) fn parts_in_bounds(&self, movement_system: &MovementSystem, bounds: &Aabb, filter: &QueryFilter) -> Vec<(u32, Vector3<f32>, Quaternion<f32>, CollisionShape)> {
+        let mut ids: Vec<u32> = self.colliders.keys().copied().collect();
+        ids.sort_unstable();
+        let mut parts = Vec::new();
+        for entity_id in ids {
+            let collider = &self.colliders[&entity_id];
+            if collider.is_trigger || !filter.accepts(entity_id, collider.membership, false) {
+                continue;
+            }
+            let Some(coords) = movement_system.get_coords(entity_id) else { continue };
+            let (position, rotation) = Self::world_pose(collider, coords);
+            for (_, part_position, part_rotation, part) in Self::parts(collider, position, rotation) {
+                if Aabb::of_shape(&part.shape, part_position, part_rotation).overlaps(bounds) {
+                    parts.push((entity_id, part_position, part_rotation, part.shape.clone()));
+                }
+            }
+        }
+        parts
+    }
+
+    /// CCD for bodies that opted in, sweeps them from where they were at the start of the step (worked back from their
+    /// velocity) to where they are now against static and kinematic colliders, and clamps them to the first hit
+    //gives back how many bodies actually got swept
+    fn sweep_fast_bodies(&self, movement_system: &mut MovementSystem, physics_system: &PhysicsSystem, delta_time: f32) -> usize {
+        let mut fast_bodies: Vec<(u32, &Collider)> = self.colliders.iter()
+            .filter(|(entity_id, collider)| {
+                !collider.is_trigger && physics_system.get_rigidbody(**entity_id)
+                    .is_some_and(|rb| rb.ccd && !rb.is_static() && !rb.is_kinematic && !rb.is_sleeping())
+            })
+            .map(|(entity_id, collider)| (*entity_id, collider))
+            .collect();
+        fast_bodies.sort_by_key(|(entity_id, _)| *entity_id);
+
+        let mut swept_bodies = 0;
+        for (entity_id, collider) in fast_bodies {
+            let Some(velocity) = movement_system.get_velocity(entity_id) else { continue };
+            let motion = velocity.linear() * delta_time;
+            let Some(coords) = movement_system.get_coords(entity_id) else { continue };
+            let (position, rotation) = Self::world_pose(collider, coords);
+
+            //each part (every child for a compound) is swept on its own, and only if its going fast enough that it
+            //could skip past something the discrete check would miss
+            let swept: Vec<(Vector3<f32>, f32)> = Self::parts(collider, position, rotation).into_iter()
+                .filter_map(|(_, end, _, part)| Some((end, sweep_radius(&part.shape)?)))
+                .filter(|(_, radius)| motion.magnitude() > *radius)
+                .collect();
+            if swept.is_empty() {
+                continue;
+            }
+            swept_bodies += 1;
+
+            let mut first_hit: Option<f32> = None;
+            for (other_id, other) in &self.colliders {
+                if *other_id == entity_id || other.is_trigger || !self.can_collide(entity_id, collider, *other_id, other) {
                     continue;
                 }
-                
-                if let Some(collision) = self.check_collision(
-                    entity_a, pos_a, collider_a, rot_a, 
-                    entity_b, pos_b, collider_b, rot_b
-                ) {
-                    self.collision_events.push(collision.clone());
-                    
-                    if !collider_a.is_trigger && !collider_b.is_trigger {
-                        physics_system.resolve_collision(movement_system, &collision);
+                // other dynamic bodies are left to the discrete pass
+                if physics_system.get_rigidbody(*other_id).is_some_and(|rb| !rb.is_static() && !rb.is_kinematic) {
+                    continue;
+                }
+                let Some(other_coords) = movement_system.get_coords(*other_id) else { continue };
+                let (other_position, other_rotation) = Self::world_pose(other, other_coords);
+
+                for (_, target_position, target_rotation, target) in Self::parts(other, other_position, other_rotation) {
+                    for (end, radius) in &swept {
+                        if let Some(toi) = time_of_impact(end - motion, motion, *radius, &target.shape, target_position, target_rotation) {
+                            first_hit = Some(first_hit.map_or(toi, |best| best.min(toi)));
+                        }
                     }
                 }
             }
+
+            if let Some(toi) = first_hit {
+                // a little past the hit so theres an actual contact for the solver to stop it with
+                let fraction = (toi + CCD_SKIN / motion.magnitude()).min(1.0);
+                if let Some(coords) = movement_system.get_coords_mut(entity_id) {
+                    coords.position += motion * (fraction - 1.0);
+                }
+            }
+        }
+        swept_bodies
+    }
+
+    //turns this frames collision into contact points and merges them into the cached manifold
+    fn update_manifold(
+        manifold: &mut ContactManifold,
+        collision: &CollisionEvent,
+        (pos_a, rot_a, shape_a): (Vector3<f32>, Quaternion<f32>, &CollisionShape),
+        (pos_b, rot_b, shape_b): (Vector3<f32>, Quaternion<f32>, &CollisionShape),
+    ) {
+        let normal = collision.normal;
+
+        //box vs box gets the whole face clipped at once
+        if let (Some(half_a), Some(half_b)) = (Self::box_half_extents(shape_a), Self::box_half_extents(shape_b)) {
+            let contacts = box_box_contacts((pos_a, rot_a, half_a), (pos_b, rot_b, half_b), normal);
+            if !contacts.is_empty() {
+                manifold.replace_points(normal, contacts);
+                return;
+            }
+        }
+
+        let point_a = collision.collision_point - normal * (collision.penetration / 2.0);
+        let point_b = collision.collision_point + normal * (collision.penetration / 2.0);
+
+        let is_round = |shape: &CollisionShape| matches!(shape, CollisionShape::Sphere { .. } | CollisionShape::Circle { .. });
+        if is_round(shape_a) || is_round(shape_b) {
+            //round things only ever touch at one spot so there is nothing to build up
+            let point = ContactPoint::new(point_a, point_b, normal, 1, (pos_a, rot_a), (pos_b, rot_b));
+            manifold.replace_points(normal, vec![point]);
+        } else {
+            let point = ContactPoint::new(point_a, point_b, normal, FEATURE_NONE, (pos_a, rot_a), (pos_b, rot_b));
+            manifold.add_point(normal, point, (pos_a, rot_a), (pos_b, rot_b));
+        }
+    }
+
+    //same idea as update_manifold but for every child pair that touched, all of them go in the one manifold
+    //(with the deepest ones normal) so a chair gets a contact under each leg instead of just the deepest one
+    fn update_compound_manifold(
+        manifold: &mut ContactManifold,
+        contacts: &[ChildContact],
+        parent_a: (Vector3<f32>, Quaternion<f32>),
+        parent_b: (Vector3<f32>, Quaternion<f32>),
+    ) {
+        let Some(deepest) = Self::deepest(contacts) else { return };
+        let normal = deepest.normal;
+
+        let mut points: Vec<ContactPoint> = Vec::new();
+        for contact in contacts {
+            let event = &contact.event;
+            // a child pushing off some other way would fight the manifold normal, those wait until theyre the deepest
+            if event.normal.dot(normal) < 0.7 {
+                continue;
+            }
+
+            let clipped = match (Self::box_half_extents(contact.shape_a), Self::box_half_extents(contact.shape_b)) {
+                (Some(half_a), Some(half_b)) => {
+                    box_box_contacts((contact.pose_a.0, contact.pose_a.1, half_a), (contact.pose_b.0, contact.pose_b.1, half_b), event.normal)
+                },
+                _ => Vec::new(),
+            };
+
+            if clipped.is_empty() {
+                let point_a = event.collision_point - event.normal * (event.penetration / 2.0);
+                let point_b = event.collision_point + event.normal * (event.penetration / 2.0);
+                points.push(ContactPoint::new(point_a, point_b, event.normal, FEATURE_NONE, parent_a, parent_b));
+            } else {
+                //feature ids only have to be unique within a child pair, so the pair gets mixed into the top bits
+                let tag = ((event.child_a.map_or(0, |i| i + 1) as u32 & 0xff) << 16) | ((event.child_b.map_or(0, |i| i + 1) as u32 & 0xff) << 24);
+                for point in clipped {
+                    let mut point = point.rebased(contact.pose_a, contact.pose_b, parent_a, parent_b);
+                    point.feature_id |= tag;
+                    points.push(point);
+                }
+            }
+        }
+
+        manifold.replace_points(normal, reduce_points(normal, points));
+    }
+
+    fn box_half_extents(shape: &CollisionShape) -> Option<Vector3<f32>> {
+        match shape {
+            CollisionShape::Box { width, height, depth } => Some(Vector3::new(width / 2.0, height / 2.0, depth / 2.0)),
+            CollisionShape::OBB { half_extents, .. } => Some(*half_extents),
+            _ => None,
         }
     }
 
@@ -684,6 +1341,108 @@ impl CollisionSystem {
     //     }
     // }
     
+    //shapes that can touch something in more than one place at once, these go through part_collisions
+    fn has_parts(collider: &Collider) -> bool {
+        matches!(collider.shape, CollisionShape::Compound(..) | CollisionShape::Heightfield(..))
+    }
+
+    //a collider split into the shapes that actually get tested, just itself unless its a compound
+    fn parts(collider: &Collider, position: Vector3<f32>, rotation: Quaternion<f32>) -> Vec<Part<'_>> {
+        match &collider.shape {
+            CollisionShape::Compound(children) => compound_children(children, position, rotation)
+                .map(|(index, child_position, child_rotation, child)| (Some(index), child_position, child_rotation, child))
+                .collect(),
+            _ => vec![(None, position, rotation, collider)],
+        }
+    }
+
+    //every child against every child (or the other whole shape), with a box check first since compounds tend to be spread out
+    //heightfields come through here too since they give back a contact for every triangle something is sitting in
+    #[allow(clippy::too_many_arguments)]
+    fn part_collisions<'a>(
+        &self,
+        entity_a: u32, pos_a: Vector3<f32>, collider_a: &'a Collider, rot_a: Quaternion<f32>,
+        entity_b: u32, pos_b: Vector3<f32>, collider_b: &'a Collider, rot_b: Quaternion<f32>
+    ) -> Vec<ChildContact<'a>> {
+        let parts_b = Self::parts(collider_b, pos_b, rot_b);
+        let mut contacts = Vec::new();
+        for (child_a, child_pos_a, child_rot_a, child_collider_a) in Self::parts(collider_a, pos_a, rot_a) {
+            let bounds_a = Aabb::of_shape(&child_collider_a.shape, child_pos_a, child_rot_a);
+            for (child_b, child_pos_b, child_rot_b, child_collider_b) in &parts_b {
+                if !bounds_a.overlaps(&Aabb::of_shape(&child_collider_b.shape, *child_pos_b, *child_rot_b)) {
+                    continue;
+                }
+                let events = match (&child_collider_a.shape, &child_collider_b.shape) {
+                    (CollisionShape::Heightfield(terrain), other) => {
+                        Self::terrain_collisions(terrain, (child_pos_a, child_rot_a), other, (*child_pos_b, *child_rot_b), entity_b, entity_a)
+                            .into_iter()
+                            .map(Self::flipped)
+                            .collect()
+                    },
+                    (other, CollisionShape::Heightfield(terrain)) => {
+                        Self::terrain_collisions(terrain, (*child_pos_b, *child_rot_b), other, (child_pos_a, child_rot_a), entity_a, entity_b)
+                    },
+                    _ => self.check_collision(
+                        entity_a, child_pos_a, child_collider_a, child_rot_a,
+                        entity_b, *child_pos_b, child_collider_b, *child_rot_b
+                    ).into_iter().collect(),
+                };
+                for mut event in events {
+                    event.child_a = child_a.or(event.child_a);
+                    event.child_b = child_b.or(event.child_b);
+                    contacts.push(ChildContact {
+                        event,
+                        pose_a: (child_pos_a, child_rot_a),
+                        shape_a: &child_collider_a.shape,
+                        pose_b: (*child_pos_b, *child_rot_b),
+                        shape_b: &child_collider_b.shape,
+                    });
+                }
+            }
+        }
+        contacts
+    }
+
+    //`other` sinking into the terrain, done in the terrains own space and brought back out. other is entity A here
+    fn terrain_collisions(
+        terrain: &Heightfield,
+        (terrain_position, terrain_rotation): (Vector3<f32>, Quaternion<f32>),
+        other: &CollisionShape,
+        (other_position, other_rotation): (Vector3<f32>, Quaternion<f32>),
+        other_entity: u32,
+        terrain_entity: u32,
+    ) -> Vec<CollisionEvent> {
+        let to_local = terrain_rotation.invert();
+        let local_position = to_local.rotate_vector(other_position - terrain_position);
+        terrain.contacts(other, local_position, to_local * other_rotation).into_iter().map(|contact| CollisionEvent {
+            entity_a: other_entity,
+            entity_b: terrain_entity,
+            collision_point: terrain_position + terrain_rotation.rotate_vector(contact.point),
+            normal: terrain_rotation.rotate_vector(contact.normal),
+            penetration: contact.penetration,
+            child_a: None,
+            child_b: None,
+        }).collect()
+    }
+
+    //same collision seen from the other side
+    fn flipped(event: CollisionEvent) -> CollisionEvent {
+        CollisionEvent {
+            entity_a: event.entity_b,
+            entity_b: event.entity_a,
+            normal: -event.normal,
+            child_a: event.child_b,
+            child_b: event.child_a,
+            ..event
+        }
+    }
+
+    fn deepest(contacts: &[ChildContact]) -> Option<CollisionEvent> {
+        contacts.iter()
+            .max_by(|a, b| a.event.penetration.total_cmp(&b.event.penetration))
+            .map(|contact| contact.event.clone())
+    }
+
     //really im going to be honest, i have like no memory of how to do collision so i hope that this is correct
     //btw it was not and it was really wrong... its fixed now but yeah
     fn check_collision(
@@ -691,6 +1450,11 @@ impl CollisionSystem {
         entity_a: u32, pos_a: Vector3<f32>, collider_a: &Collider, rot_a: Quaternion<f32>,
         entity_b: u32, pos_b: Vector3<f32>, collider_b: &Collider, rot_b: Quaternion<f32>
     ) -> Option<CollisionEvent> {
+        if Self::has_parts(collider_a) || Self::has_parts(collider_b) {
+            let contacts = self.part_collisions(entity_a, pos_a, collider_a, rot_a, entity_b, pos_b, collider_b, rot_b);
+            return Self::deepest(&contacts);
+        }
+
         match (&collider_a.shape, &collider_b.shape) {
             // Circle vs Circle (2D)
             (CollisionShape::Circle { radius: r1 }, CollisionShape::Circle { radius: r2 }) => {
@@ -714,6 +1478,8 @@ impl CollisionSystem {
                         ),
                         normal: Vector3::new(normal.x, normal.y, 0.0),
                         penetration: combined_radius - distance,
+                        child_a: None,
+                        child_b: None,
                     })
                 } else {
                     None
@@ -738,6 +1504,8 @@ impl CollisionSystem {
                         collision_point: pos_b + normal * *r2,//just deref here because lazy and like its a float maybe todo fix later to not be ass
                         normal,
                         penetration: combined_radius - distance,
+                        child_a: None,
+                        child_b: None,
                     })
                 } else {
                     None
@@ -776,6 +1544,8 @@ impl CollisionSystem {
                         ),
                         normal,
                         penetration,
+                        child_a: None,
+                        child_b: None,
                     })
                 } else {
                     None
@@ -784,7 +1554,7 @@ impl CollisionSystem {
             
             // Box vs Box (3D AABB)
             (CollisionShape::Box { width: w1, height: h1, depth: d1 }, 
-             CollisionShape::Box { width: w2, height: h2, depth: d2 }) => {
+             CollisionShape::Box { width: w2, height: h2, depth: d2 }) if is_unrotated(rot_a) && is_unrotated(rot_b) => {
                 let half_w1 = w1 / 2.0;
                 let half_h1 = h1 / 2.0;
                 let half_d1 = d1 / 2.0;
@@ -820,12 +1590,49 @@ impl CollisionSystem {
                         ),
                         normal,
                         penetration,
+                        child_a: None,
+                        child_b: None,
                     })
                 } else {
                     None
                 }
             },
 
+            // Box vs Box once either one has turned, same SAT test the OBBs go through
+            (CollisionShape::Box { width: w1, height: h1, depth: d1 },
+             CollisionShape::Box { width: w2, height: h2, depth: d2 }) => {
+                let box_a = Collider {
+                    shape: CollisionShape::OBB {
+                        half_extents: Vector3::new(*w1 / 2.0, *h1 / 2.0, *d1 / 2.0),
+                        rotation: rot_a,
+                    },
+                    ..*collider_a
+                };
+
+                let box_b = Collider {
+                    shape: CollisionShape::OBB {
+                        half_extents: Vector3::new(*w2 / 2.0, *h2 / 2.0, *d2 / 2.0),
+                        rotation: rot_b,
+                    },
+                    ..*collider_b
+                };
+
+                // straight to the SAT test, boxes that got knocked over end up here every frame so no debug output
+                Self::check_obb_collision(&box_a, pos_a, &box_b, pos_b).map(|(normal, penetration)| {
+                    // the SAT normal points from A to B, flipped so it pushes A out of B like every other pair
+                    let normal = if normal.dot(pos_b - pos_a) > 0.0 { -normal } else { normal };
+                    CollisionEvent {
+                        entity_a,
+                        entity_b,
+                        collision_point: pos_b + normal * (penetration / 2.0),
+                        normal,
+                        penetration,
+                        child_a: None,
+                        child_b: None,
+                    }
+                })
+            },
+
             (CollisionShape::Circle { radius }, CollisionShape::Rectangle { width, height }) => {
                 let half_w = width / 2.0;
                 let half_h = height / 2.0;
@@ -863,6 +1670,8 @@ impl CollisionSystem {
                         collision_point: Vector3::new(closest_x, closest_y, pos_a.z),
                         normal: Vector3::new(normal.x, normal.y, 0.0),
                         penetration,
+                        child_a: None,
+                        child_b: None,
                     })
                 } else {
                     None
@@ -907,13 +1716,15 @@ impl CollisionSystem {
                         collision_point: Vector3::new(closest_x, closest_y, pos_b.z),
                         normal: Vector3::new(-normal.x, -normal.y, 0.0), // Flip normal since entity_a is the rectangle
                         penetration,
+                        child_a: None,
+                        child_b: None,
                     })
                 } else {
                     None
                 }
             },
 
-            (CollisionShape::Sphere { radius: r1 }, CollisionShape::Box { width: w2, height: h2, depth: d2 }) => {
+            (CollisionShape::Sphere { radius: r1 }, CollisionShape::Box { width: w2, height: h2, depth: d2 }) if is_unrotated(rot_b) => {
                 // Sphere vs AABB collision detection
                 let half_w = w2 / 2.0;
                 let half_h = h2 / 2.0;
@@ -957,60 +1768,29 @@ impl CollisionSystem {
                         collision_point: Vector3::new(closest_x, closest_y, closest_z),
                         normal,
                         penetration,
+                        child_a: None,
+                        child_b: None,
                     })
                 } else {
                     None
                 }
             },
 
-            (CollisionShape::Box { width: w2, height: h2, depth: d2 }, CollisionShape::Sphere { radius: r1 }) => {
-                // Sphere vs AABB collision detection
-                let half_w = w2 / 2.0;
-                let half_h = h2 / 2.0;
-                let half_d = d2 / 2.0;
-                
-                // Find the closest point on the box to the sphere's center
-                let closest_x = pos_a.x.max(pos_b.x - half_w).min(pos_b.x + half_w);
-                let closest_y = pos_a.y.max(pos_b.y - half_h).min(pos_b.y + half_h);
-                let closest_z = pos_a.z.max(pos_b.z - half_d).min(pos_b.z + half_d);
-                
-                // Calculate distance from sphere center to this closest point
-                let dx = pos_a.x - closest_x;
-                let dy = pos_a.y - closest_y;
-                let dz = pos_a.z - closest_z;
-                let distance_squared = dx * dx + dy * dy + dz * dz;
-                
-                if distance_squared < r1 * r1 {
-                    let distance = distance_squared.sqrt();
-                    let penetration = r1 - distance;
-                    
-                    let normal = if distance > 0.0 {
-                        Vector3::new(dx, dy, dz).normalize()
-                    } else {
-                        // Sphere center is inside box, push out along closest axis
-                        let dx_edge = (pos_a.x - pos_b.x).abs() - half_w;
-                        let dy_edge = (pos_a.y - pos_b.y).abs() - half_h;
-                        let dz_edge = (pos_a.z - pos_b.z).abs() - half_d;
-                        
-                        if dx_edge <= dy_edge && dx_edge <= dz_edge {
-                            Vector3::new(if pos_a.x > pos_b.x { 1.0 } else { -1.0 }, 0.0, 0.0)
-                        } else if dy_edge <= dz_edge {
-                            Vector3::new(0.0, if pos_a.y > pos_b.y { 1.0 } else { -1.0 }, 0.0)
-                        } else {
-                            Vector3::new(0.0, 0.0, if pos_a.z > pos_b.z { 1.0 } else { -1.0 })
-                        }
-                    };
-
-                    Some(CollisionEvent {
-                        entity_a,
-                        entity_b,
-                        collision_point: Vector3::new(closest_x, closest_y, closest_z),
-                        normal,
-                        penetration,
-                    })
-                } else {
-                    None
-                }
+            (CollisionShape::Box { .. }, CollisionShape::Sphere { .. }) if is_unrotated(rot_a) => {
+                // same test as sphere vs box with the two swapped, this used to treat the box as the sphere
+                // which put the contact point in the middle of the box (didnt matter until contacts could spin things)
+                self.check_collision(
+                    entity_b, pos_b, collider_b, rot_b,
+                    entity_a, pos_a, collider_a, rot_a
+                ).map(|collision| CollisionEvent {
+                    entity_a,
+                    entity_b,
+                    collision_point: collision.collision_point,
+                    normal: -collision.normal, // flipped back so it still pushes A out of B
+                    penetration: collision.penetration,
+                    child_a: None,
+                    child_b: None,
+                })
             },
 
             (CollisionShape::OBB { half_extents, rotation: _ }, CollisionShape::OBB { .. }) => {
@@ -1114,18 +1894,10 @@ impl CollisionSystem {
         *radius,
         pos_b,      // Sphere position
     ) {
-        // CRITICAL: Contact point must be on sphere surface
-        // normal points FROM sphere TO OBB (after the flip in check_obb_sphere_collision)
-        // So: sphere_center - normal * radius = point on sphere surface toward OBB
-        let contact_point = pos_b - normal * *radius;
-        
-        println!("OBB-Sphere collision:");
-        println!("  OBB pos (A): {:?}", pos_a);
-        println!("  Sphere pos (B): {:?}", pos_b);
-        println!("  Normal: {:?}", normal);
-        println!("  Radius: {}", radius);
-        println!("  Contact point: {:?}", contact_point);
-        println!("  Distance from sphere center: {}", (contact_point - pos_b).magnitude());
+        // Contact point must be on sphere surface
+        // normal pushes the OBB (A) out of the sphere (B) so it points FROM sphere TO OBB
+        // So: sphere_center + normal * radius = point on sphere surface toward OBB
+        let contact_point = pos_b + normal * *radius;
         
         Some(CollisionEvent {
             entity_a,
@@ -1133,6 +1905,8 @@ impl CollisionSystem {
             collision_point: contact_point,
             normal,
             penetration,
+            child_a: None,
+            child_b: None,
         })
     } else {
         None
@@ -1164,16 +1938,48 @@ impl CollisionSystem {
             collision_point: pos_a + normal * *radius,  // FIXED: Sphere pos + normal * radius
             normal: -normal,  // Flip to maintain convention: A to B
             penetration,
+            child_a: None,
+            child_b: None,
         })
     } else {
         None
     }
 },
                         
-            // need to add all the mixed collision types later
-            _ => None, // Unsupported collision pair
+            // everything else (box vs sphere with rotation, 2d vs 3d, ect) goes through gjk/epa
+            // the cases above are just fast paths now
+            _ => Self::check_convex_collision(
+                entity_a, pos_a, collider_a, rot_a,
+                entity_b, pos_b, collider_b, rot_b
+            ),
         }
     }
+
+    //generic convex vs convex check, any shape with a support map works here
+    //circles and rectangles are treated as flat shapes on the xy plane when they meet a 3d shape
+    #[allow(clippy::too_many_arguments)]
+    pub fn check_convex_collision(
+        entity_a: u32, pos_a: Vector3<f32>, collider_a: &Collider, rot_a: Quaternion<f32>,
+        entity_b: u32, pos_b: Vector3<f32>, collider_b: &Collider, rot_b: Quaternion<f32>
+    ) -> Option<CollisionEvent> {
+        let shape_a = WorldShape::new(&collider_a.shape, pos_a, rot_a);
+        let shape_b = WorldShape::new(&collider_b.shape, pos_b, rot_b);
+
+        let contact = gjk_epa(&shape_a, &shape_b)?;
+        if contact.penetration <= 0.0 {
+            return None;
+        }
+
+        Some(CollisionEvent {
+            entity_a,
+            entity_b,
+            collision_point: contact.contact_point(),
+            normal: contact.normal,
+            penetration: contact.penetration,
+            child_a: None,
+            child_b: None,
+        })
+    }
     
     fn resolve_collision(&self, movement_system: &mut MovementSystem, collision: &CollisionEvent) {
         // Simple position-based resolution
@@ -1194,6 +2000,46 @@ impl CollisionSystem {
     pub fn get_collision_events(&self) -> &[CollisionEvent] {
         &self.collision_events
     }
+
+    /// Enter/stay/exit for triggers and begin/end for solid contacts from the last update
+    pub fn get_contact_events(&self) -> &[ContactEvent] {
+        &self.contact_events
+    }
+    
+    /// The friction and bounce two colliders get when they touch, see `PhysicsMaterials::resolve`
+    pub fn contact_material(&self, physics_system: &PhysicsSystem, entity_a: u32, entity_b: u32) -> ContactMaterial {
+        // no material on the collider means the rigidbodies own numbers, like before materials were a thing
+        let fallback = |entity_id: u32| {
+            physics_system.get_rigidbody(entity_id)
+                .map(|rigidbody| PhysicsMaterial::new(rigidbody.friction, rigidbody.friction, rigidbody.restitution))
+        };
+        let material = |entity_id: u32| self.colliders.get(&entity_id).and_then(|collider| collider.material);
+        self.materials.resolve(material(entity_a), fallback(entity_a), material(entity_b), fallback(entity_b))
+    }
+
+    //sorted copies of the contact cache for snapshots, the hashmaps get rebuilt from them on restore
+    pub(crate) fn contact_cache(&self) -> ContactCache {
+        let mut manifolds: Vec<((u32, u32), ContactManifold)> = self.manifolds.iter().map(|(pair, manifold)| (*pair, manifold.clone())).collect();
+        manifolds.sort_by_key(|(pair, _)| *pair);
+        let mut overlapping: Vec<((u32, u32), Option<u32>)> = self.overlapping.iter().map(|(pair, trigger)| (*pair, *trigger)).collect();
+        overlapping.sort_by_key(|(pair, _)| *pair);
+        ContactCache { manifolds, overlapping }
+    }
+
+    pub(crate) fn restore_contact_cache(&mut self, cache: &ContactCache) {
+        self.manifolds = cache.manifolds.iter().cloned().collect();
+        self.overlapping = cache.overlapping.iter().copied().collect();
+        self.collision_events.clear();
+        self.contact_events.clear();
+    }
+
+    pub fn get_manifolds(&self) -> impl Iterator<Item = &ContactManifold> {
+        self.manifolds.values()
+    }
+
+    pub fn get_manifold(&self, entity_a: u32, entity_b: u32) -> Option<&ContactManifold> {
+        self.manifolds.get(&(entity_a.min(entity_b), entity_a.max(entity_b)))
+    }
     
     // Check if a specific entity collided this frame
     pub fn entity_collided_with(&self, entity_id: u32) -> Vec<u32> {
@@ -1324,4 +2170,9 @@ impl CollisionSystem {
             }
         }
     }
-}
\ No newline at end of file
+}
+
+//boxes only get the cheap AABB tests while they havent been turned, a spinning box needs the real thing
+fn is_unrotated(rotation: Quaternion<f32>) -> bool {
+    rotation.s.abs() > 0.99999
+}
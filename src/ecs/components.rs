@@ -1,4 +1,4 @@
-use cgmath::Vector3;
+use cgmath::{InnerSpace, Vector3, Zero};
 
 use crate::model::objload::ModelTrait;
 
@@ -9,4 +9,22 @@ pub struct Renderable {
 pub struct Velocity {
     pub direction: Vector3<f32>,
     pub speed: f32,
-}
\ No newline at end of file
+}
+
+impl Velocity {
+    pub fn linear(&self) -> Vector3<f32> {
+        self.direction * self.speed
+    }
+
+    //the physics code kept doing this same dance everywhere so now it lives here
+    pub fn set_linear(&mut self, velocity: Vector3<f32>) {
+        let speed = velocity.magnitude();
+        if speed > 0.001 {
+            self.direction = velocity / speed;
+            self.speed = speed;
+        } else {
+            self.direction = Vector3::zero();
+            self.speed = 0.0;
+        }
+    }
+}
@@ -0,0 +1,504 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
+
+use super::materials::ContactMaterial;
+
+//a manifold is every contact point between two bodies that share the same normal
+//one point per frame is not enough for a box sitting on the floor, it just teeters around that one point
+//so boxes get up to 4 points (the clipped face) and everything else builds its points up over a few frames
+
+pub const MAX_MANIFOLD_POINTS: usize = 4;
+
+//how far a cached point can drift (in world units) before we stop trusting it
+const PERSISTENT_THRESHOLD: f32 = 0.05;
+
+/// Feature id for contacts that dont come from a specific pair of features (sphere, gjk ect)
+/// these get matched between frames by distance instead
+pub const FEATURE_NONE: u32 = 0;
+
+#[derive(Debug, Clone, Copy)]
+pub struct ContactPoint {
+    pub position: Vector3<f32>, // world space, halfway between the two surfaces
+    pub local_a: Vector3<f32>,  // the point on A in A's local space
+    pub local_b: Vector3<f32>,  // the point on B in B's local space
+    pub penetration: f32,
+    pub feature_id: u32,
+
+    // accumulated impulses from last frame, used to warm start the solver
+    pub normal_impulse: f32,
+    pub tangent_impulse: [f32; 2],
+}
+
+impl ContactPoint {
+    //point_a is the deepest point of A inside B, point_b the deepest point of B inside A
+    pub fn new(
+        point_a: Vector3<f32>,
+        point_b: Vector3<f32>,
+        normal: Vector3<f32>,
+        feature_id: u32,
+        transform_a: (Vector3<f32>, Quaternion<f32>),
+        transform_b: (Vector3<f32>, Quaternion<f32>),
+    ) -> Self {
+        Self {
+            position: (point_a + point_b) * 0.5,
+            local_a: to_local(point_a, transform_a),
+            local_b: to_local(point_b, transform_b),
+            penetration: (point_b - point_a).dot(normal),
+            feature_id,
+            normal_impulse: 0.0,
+            tangent_impulse: [0.0, 0.0],
+        }
+    }
+}
+
+impl ContactPoint {
+    /// The same contact with its anchors moved from one pair of frames to another, compound children hand
+    /// their contacts up to the whole body like this
+    pub fn rebased(
+        mut self,
+        from_a: (Vector3<f32>, Quaternion<f32>),
+        from_b: (Vector3<f32>, Quaternion<f32>),
+        to_a: (Vector3<f32>, Quaternion<f32>),
+        to_b: (Vector3<f32>, Quaternion<f32>),
+    ) -> Self {
+        self.local_a = to_local(to_world(self.local_a, from_a), to_a);
+        self.local_b = to_local(to_world(self.local_b, from_b), to_b);
+        self
+    }
+}
+
+fn to_local(point: Vector3<f32>, (position, rotation): (Vector3<f32>, Quaternion<f32>)) -> Vector3<f32> {
+    rotation.invert().rotate_vector(point - position)
+}
+
+fn to_world(point: Vector3<f32>, (position, rotation): (Vector3<f32>, Quaternion<f32>)) -> Vector3<f32> {
+    position + rotation.rotate_vector(point)
+}
+
+#[derive(Debug, Clone)]
+pub struct ContactManifold {
+    pub entity_a: u32,
+    pub entity_b: u32,
+    pub normal: Vector3<f32>, // same convention as CollisionEvent, pushes A away from B
+    pub points: Vec<ContactPoint>,
+    pub material: ContactMaterial, // mixed from both sides, filled in by the collision system right before solving
+    pub(crate) modification: ContactModification, // what the contact modifiers did to it this step
+}
+
+/// Changes the contact modifiers made to a manifold, they only last for the step they were made in so the cached
+/// normal and points stay whatever the narrow phase found
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct ContactModification {
+    pub enabled: bool,
+    pub normal: Option<Vector3<f32>>,
+    pub surface_velocity: Vector3<f32>, // how fast Bs surface slides along under A, on top of how B is moving
+}
+
+impl Default for ContactModification {
+    fn default() -> Self {
+        Self { enabled: true, normal: None, surface_velocity: Vector3::new(0.0, 0.0, 0.0) }
+    }
+}
+
+impl ContactManifold {
+    pub fn new(entity_a: u32, entity_b: u32, normal: Vector3<f32>) -> Self {
+        Self {
+            entity_a,
+            entity_b,
+            normal,
+            points: Vec::with_capacity(MAX_MANIFOLD_POINTS),
+            material: ContactMaterial::default(),
+            modification: ContactModification::default(),
+        }
+    }
+
+    /// The normal the solver pushes along, the narrow phases one unless a contact modifier changed it
+    pub fn solver_normal(&self) -> Vector3<f32> {
+        self.modification.normal.unwrap_or(self.normal)
+    }
+
+    pub fn surface_velocity(&self) -> Vector3<f32> {
+        self.modification.surface_velocity
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.modification.enabled
+    }
+
+    pub fn deepest_penetration(&self) -> f32 {
+        self.points.iter().map(|p| p.penetration).fold(0.0, f32::max)
+    }
+
+    /// Replace every point with a freshly generated set (box clipping gives us the whole face at once)
+    /// warm start impulses carry over from old points with the same feature id
+    pub fn replace_points(&mut self, normal: Vector3<f32>, mut new_points: Vec<ContactPoint>) {
+        let keep_impulses = self.normal.dot(normal) > 0.95;
+        if keep_impulses {
+            for point in new_points.iter_mut() {
+                if let Some(old) = self.find_match(point) {
+                    point.normal_impulse = old.normal_impulse;
+                    point.tangent_impulse = old.tangent_impulse;
+                }
+            }
+        }
+
+        new_points.truncate(MAX_MANIFOLD_POINTS);
+        self.normal = normal;
+        self.points = new_points;
+    }
+
+    /// Add a single point to the manifold and keep the old ones that are still valid
+    /// this is how shapes that only give one point per frame (gjk) slowly build a stable manifold
+    pub fn add_point(
+        &mut self,
+        normal: Vector3<f32>,
+        mut new_point: ContactPoint,
+        transform_a: (Vector3<f32>, Quaternion<f32>),
+        transform_b: (Vector3<f32>, Quaternion<f32>),
+    ) {
+        if self.normal.dot(normal) < 0.95 {
+            //the normal flipped around, the old points are for a different contact
+            self.points.clear();
+        }
+        self.normal = normal;
+        self.refresh(transform_a, transform_b);
+
+        if let Some(index) = self.find_match_index(&new_point) {
+            new_point.normal_impulse = self.points[index].normal_impulse;
+            new_point.tangent_impulse = self.points[index].tangent_impulse;
+            self.points[index] = new_point;
+        } else {
+            self.points.push(new_point);
+        }
+
+        if self.points.len() > MAX_MANIFOLD_POINTS {
+            self.reduce();
+        }
+    }
+
+    //move the cached points along with the bodies and drop the ones that dont make sense anymore
+    fn refresh(&mut self, transform_a: (Vector3<f32>, Quaternion<f32>), transform_b: (Vector3<f32>, Quaternion<f32>)) {
+        let normal = self.normal;
+        self.points.retain_mut(|point| {
+            let world_a = to_world(point.local_a, transform_a);
+            let world_b = to_world(point.local_b, transform_b);
+            point.penetration = (world_b - world_a).dot(normal);
+            point.position = (world_a + world_b) * 0.5;
+
+            //separated, or the two anchors slid apart along the surface
+            let drift = (world_b - world_a) - normal * point.penetration;
+            point.penetration > -PERSISTENT_THRESHOLD && drift.magnitude2() < PERSISTENT_THRESHOLD * PERSISTENT_THRESHOLD
+        });
+    }
+
+    fn find_match_index(&self, point: &ContactPoint) -> Option<usize> {
+        if point.feature_id != FEATURE_NONE {
+            if let Some(index) = self.points.iter().position(|p| p.feature_id == point.feature_id) {
+                return Some(index);
+            }
+        }
+
+        let mut best = None;
+        let mut best_distance = PERSISTENT_THRESHOLD * PERSISTENT_THRESHOLD;
+        for (i, old) in self.points.iter().enumerate() {
+            let distance = (old.position - point.position).magnitude2();
+            if distance < best_distance {
+                best_distance = distance;
+                best = Some(i);
+            }
+        }
+        best
+    }
+
+    fn find_match(&self, point: &ContactPoint) -> Option<&ContactPoint> {
+        self.find_match_index(point).map(|i| &self.points[i])
+    }
+
+    //cut back down to 4 points, we keep the deepest one and then whatever covers the most area
+    //(a big contact patch is way more stable than 4 points bunched up in a corner)
+    fn reduce(&mut self) {
+        let points = std::mem::take(&mut self.points);
+        let mut chosen: Vec<usize> = Vec::with_capacity(MAX_MANIFOLD_POINTS);
+
+        let deepest = (0..points.len())
+            .max_by(|a, b| points[*a].penetration.total_cmp(&points[*b].penetration))
+            .unwrap_or(0);
+        chosen.push(deepest);
+
+        let furthest = (0..points.len())
+            .filter(|i| !chosen.contains(i))
+            .max_by(|a, b| {
+                let da = (points[*a].position - points[deepest].position).magnitude2();
+                let db = (points[*b].position - points[deepest].position).magnitude2();
+                da.total_cmp(&db)
+            });
+        if let Some(i) = furthest {
+            chosen.push(i);
+        }
+
+        while chosen.len() < MAX_MANIFOLD_POINTS && chosen.len() < points.len() {
+            let next = (0..points.len())
+                .filter(|i| !chosen.contains(i))
+                .max_by(|a, b| {
+                    let area_a = Self::area_with(&points, &chosen, *a);
+                    let area_b = Self::area_with(&points, &chosen, *b);
+                    area_a.total_cmp(&area_b)
+                });
+            match next {
+                Some(i) => chosen.push(i),
+                None => break,
+            }
+        }
+
+        chosen.sort_unstable();
+        self.points = chosen.into_iter().map(|i| points[i]).collect();
+    }
+
+    //how much area the polygon would cover if `candidate` was added (triangle fan from the first point)
+    fn area_with(points: &[ContactPoint], chosen: &[usize], candidate: usize) -> f32 {
+        let origin = points[chosen[0]].position;
+        let mut area = 0.0;
+        for &i in &chosen[1..] {
+            area += (points[i].position - origin).cross(points[candidate].position - origin).magnitude();
+        }
+        area
+    }
+}
+
+/// Builds the contact patch between two boxes by clipping the incident face against the reference face
+/// `normal` is the collision normal (pushes A away from B), half extents and rotations are in world space
+pub fn box_box_contacts(
+    (center_a, rotation_a, half_a): (Vector3<f32>, Quaternion<f32>, Vector3<f32>),
+    (center_b, rotation_b, half_b): (Vector3<f32>, Quaternion<f32>, Vector3<f32>),
+    normal: Vector3<f32>,
+) -> Vec<ContactPoint> {
+    let axes_a = box_axes(rotation_a);
+    let axes_b = box_axes(rotation_b);
+
+    //the reference face is whichever face lines up best with the normal, the other box supplies the incident face
+    //A's face has to point towards B (-normal) and B's face towards A (+normal)
+    let (face_a, align_a) = best_face(&axes_a, -normal);
+    let (face_b, align_b) = best_face(&axes_b, normal);
+
+    //tiny bias so we dont flip flop between the two boxes every frame when they are perfectly stacked
+    let reference_is_a = align_a + 0.001 >= align_b;
+
+    let (ref_center, ref_axes, ref_half, ref_face, inc_center, inc_axes, inc_half) = if reference_is_a {
+        (center_a, axes_a, half_a, face_a, center_b, axes_b, half_b)
+    } else {
+        (center_b, axes_b, half_b, face_b, center_a, axes_a, half_a)
+    };
+
+    let ref_axis = ref_face / 2;
+    let ref_normal = ref_axes[ref_axis] * face_sign(ref_face);
+    let (inc_face, _) = best_face(&inc_axes, -ref_normal);
+
+    let mut polygon: Vec<(Vector3<f32>, u32)> = face_vertices(inc_center, &inc_axes, inc_half, inc_face)
+        .iter()
+        .enumerate()
+        .map(|(i, v)| (*v, i as u32))
+        .collect();
+
+    //clip against the 4 side planes of the reference face
+    let mut plane_index = 0;
+    for (axis, axis_direction) in ref_axes.iter().enumerate() {
+        if axis == ref_axis {
+            continue;
+        }
+        for side in [1.0, -1.0] {
+            let plane_normal = axis_direction * side;
+            let plane_offset = plane_normal.dot(ref_center) + extent(ref_half, axis);
+            polygon = clip_polygon(&polygon, plane_normal, plane_offset, plane_index);
+            plane_index += 1;
+            if polygon.is_empty() {
+                return Vec::new();
+            }
+        }
+    }
+
+    let ref_plane_offset = ref_normal.dot(ref_center) + extent(ref_half, ref_axis);
+    let transform_a = (center_a, rotation_a);
+    let transform_b = (center_b, rotation_b);
+
+    let mut contacts = Vec::with_capacity(polygon.len());
+    for (point, clip_id) in polygon {
+        let depth = ref_plane_offset - ref_normal.dot(point);
+        if depth < 0.0 {
+            continue;
+        }
+        //the incident point sits inside the reference box, its twin is pushed back up onto the reference face
+        let on_reference = point + ref_normal * depth;
+        let (point_a, point_b) = if reference_is_a {
+            (on_reference, point)
+        } else {
+            (point, on_reference)
+        };
+
+        let feature_id = 1 + (((!reference_is_a) as u32) << 12 | (ref_face as u32) << 8 | (inc_face as u32) << 5 | clip_id);
+        contacts.push(ContactPoint::new(point_a, point_b, normal, feature_id, transform_a, transform_b));
+    }
+
+    reduce_points(normal, contacts)
+}
+
+/// Cuts a pile of contacts back down to the best MAX_MANIFOLD_POINTS, same way a manifold does when it overflows
+pub fn reduce_points(normal: Vector3<f32>, contacts: Vec<ContactPoint>) -> Vec<ContactPoint> {
+    if contacts.len() <= MAX_MANIFOLD_POINTS {
+        return contacts;
+    }
+    let mut manifold = ContactManifold::new(0, 0, normal);
+    manifold.points = contacts;
+    manifold.reduce();
+    manifold.points
+}
+
+fn box_axes(rotation: Quaternion<f32>) -> [Vector3<f32>; 3] {
+    [
+        rotation.rotate_vector(Vector3::new(1.0, 0.0, 0.0)),
+        rotation.rotate_vector(Vector3::new(0.0, 1.0, 0.0)),
+        rotation.rotate_vector(Vector3::new(0.0, 0.0, 1.0)),
+    ]
+}
+
+fn extent(half: Vector3<f32>, axis: usize) -> f32 {
+    match axis {
+        0 => half.x,
+        1 => half.y,
+        _ => half.z,
+    }
+}
+
+//faces are numbered 0..6 as (axis * 2) for the + side and (axis * 2 + 1) for the - side
+fn face_sign(face: usize) -> f32 {
+    if face.is_multiple_of(2) { 1.0 } else { -1.0 }
+}
+
+fn best_face(axes: &[Vector3<f32>; 3], direction: Vector3<f32>) -> (usize, f32) {
+    let mut best = (0, f32::MIN);
+    for (axis, axis_direction) in axes.iter().enumerate() {
+        let alignment = axis_direction.dot(direction);
+        if alignment > best.1 {
+            best = (axis * 2, alignment);
+        }
+        if -alignment > best.1 {
+            best = (axis * 2 + 1, -alignment);
+        }
+    }
+    best
+}
+
+fn face_vertices(center: Vector3<f32>, axes: &[Vector3<f32>; 3], half: Vector3<f32>, face: usize) -> [Vector3<f32>; 4] {
+    let axis = face / 2;
+    let u_axis = (axis + 1) % 3;
+    let v_axis = (axis + 2) % 3;
+
+    let face_center = center + axes[axis] * extent(half, axis) * face_sign(face);
+    let u = axes[u_axis] * extent(half, u_axis);
+    let v = axes[v_axis] * extent(half, v_axis);
+
+    [
+        face_center + u + v,
+        face_center - u + v,
+        face_center - u - v,
+        face_center + u - v,
+    ]
+}
+
+//sutherland hodgman against a single plane, keeps whatever is on the back side (dot <= offset)
+//every vertex carries an id so the clipped points keep the same id frame to frame
+fn clip_polygon(polygon: &[(Vector3<f32>, u32)], plane_normal: Vector3<f32>, plane_offset: f32, plane_index: u32) -> Vec<(Vector3<f32>, u32)> {
+    let mut output = Vec::with_capacity(polygon.len() + 2);
+    for i in 0..polygon.len() {
+        let (current, current_id) = polygon[i];
+        let (next, _) = polygon[(i + 1) % polygon.len()];
+        let current_distance = plane_normal.dot(current) - plane_offset;
+        let next_distance = plane_normal.dot(next) - plane_offset;
+
+        if current_distance <= 0.0 {
+            output.push((current, current_id));
+        }
+        if (current_distance <= 0.0) != (next_distance <= 0.0) {
+            let t = current_distance / (current_distance - next_distance);
+            let intersection = current + (next - current) * t;
+            //new vertex born from this plane and this edge
+            output.push((intersection, 4 + plane_index * 4 + (current_id & 3)));
+        }
+    }
+    output
+}
+
+/// One touching pair as a contact modifier sees it, after the narrow phase and right before it gets solved.
+/// Anything changed here only counts for this step
+pub struct ModifiableContact<'a> {
+    manifold: &'a mut ContactManifold,
+}
+
+impl<'a> ModifiableContact<'a> {
+    pub(crate) fn new(manifold: &'a mut ContactManifold) -> Self {
+        Self { manifold }
+    }
+
+    /// The lower of the two ids
+    pub fn entity_a(&self) -> u32 {
+        self.manifold.entity_a
+    }
+
+    pub fn entity_b(&self) -> u32 {
+        self.manifold.entity_b
+    }
+
+    /// Whichever of the two isnt `entity_id`
+    pub fn other(&self, entity_id: u32) -> u32 {
+        if entity_id == self.manifold.entity_a { self.manifold.entity_b } else { self.manifold.entity_a }
+    }
+
+    /// Pushes A away from B
+    pub fn normal(&self) -> Vector3<f32> {
+        self.manifold.solver_normal()
+    }
+
+    /// The normal turned around to push `entity_id` away from the other one
+    pub fn normal_for(&self, entity_id: u32) -> Vector3<f32> {
+        if entity_id == self.manifold.entity_a { self.normal() } else { -self.normal() }
+    }
+
+    pub fn points(&self) -> &[ContactPoint] {
+        &self.manifold.points
+    }
+
+    pub fn material(&self) -> ContactMaterial {
+        self.manifold.material
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.manifold.modification.enabled
+    }
+
+    /// Lets the pair pass through each other this step, one way platforms and ignoring your own bullets
+    pub fn disable(&mut self) {
+        self.manifold.modification.enabled = false;
+    }
+
+    /// `normal` pushes A away from B like the original one
+    pub fn set_normal(&mut self, normal: Vector3<f32>) {
+        if normal.magnitude2() > 0.0 {
+            self.manifold.modification.normal = Some(normal.normalize());
+        }
+    }
+
+    pub fn set_friction(&mut self, static_friction: f32, dynamic_friction: f32) {
+        self.manifold.material.static_friction = static_friction;
+        self.manifold.material.dynamic_friction = dynamic_friction;
+    }
+
+    pub fn set_restitution(&mut self, restitution: f32) {
+        self.manifold.material.restitution = restitution;
+    }
+
+    /// Makes `entity_id`s surface act like its sliding at `velocity` (world space) on top of how the body itself moves,
+    /// friction then drags the other one along with it. Conveyor belts, escalators, treadmills
+    pub fn set_surface_velocity(&mut self, entity_id: u32, velocity: Vector3<f32>) {
+        let normal = self.normal();
+        let along = velocity - normal * velocity.dot(normal);
+        self.manifold.modification.surface_velocity = if entity_id == self.manifold.entity_a { -along } else { along };
+    }
+}
@@ -0,0 +1,202 @@
+use cgmath::{InnerSpace, Matrix3, SquareMatrix, Vector3, Zero};
+
+use super::aabb::Aabb;
+
+//a convex shape made from a cloud of points, the hull is built once up front (incrementally, one point at a time
+//eating the faces it can see) so collision only ever has to look at its corners and face planes
+//points that end up inside the hull get thrown away
+
+#[derive(Debug, Clone)]
+pub struct ConvexHull {
+    vertices: Vec<Vector3<f32>>,
+    faces: Vec<[usize; 3]>, // counter clockwise seen from outside
+    planes: Vec<(Vector3<f32>, f32)>, // outward normal and distance from the origin along it, one per face
+}
+
+impl ConvexHull {
+    /// Hull around `points` (in the colliders own space). Errors if they are all flat, on a line or too few
+    pub fn new(points: &[Vector3<f32>]) -> Result<Self, String> {
+        if points.len() < 4 {
+            return Err(format!("a convex hull needs at least 4 points, got {}", points.len()));
+        }
+        let size = points.iter().fold(0.0_f32, |size, point| size.max(point.x.abs()).max(point.y.abs()).max(point.z.abs()));
+        let epsilon = size.max(1.0) * 1e-5;
+
+        //starting tetrahedron out of points as far apart as possible so its not a sliver
+        let first = 0;
+        let second = furthest(points, |point| (point - points[first]).magnitude2());
+        let line = (points[second] - points[first]).normalize();
+        let third = furthest(points, |point| {
+            let offset = point - points[first];
+            (offset - line * offset.dot(line)).magnitude2()
+        });
+        let normal = (points[second] - points[first]).cross(points[third] - points[first]);
+        if normal.magnitude() <= epsilon * epsilon {
+            return Err("convex hull points are all on one line".to_string());
+        }
+        let normal = normal.normalize();
+        let fourth = furthest(points, |point| (point - points[first]).dot(normal).abs());
+        if (points[fourth] - points[first]).dot(normal).abs() <= epsilon {
+            return Err("convex hull points are all flat, it has no volume".to_string());
+        }
+
+        let mut faces = if (points[fourth] - points[first]).dot(normal) < 0.0 {
+            vec![[first, second, third], [first, third, fourth], [first, fourth, second], [second, fourth, third]]
+        } else {
+            vec![[first, third, second], [first, second, fourth], [first, fourth, third], [second, third, fourth]]
+        };
+
+        for (index, point) in points.iter().enumerate() {
+            if [first, second, third, fourth].contains(&index) {
+                continue;
+            }
+            let visible: Vec<bool> = faces.iter().map(|face| {
+                let (normal, distance) = face_plane(points, face);
+                point.dot(normal) - distance > epsilon
+            }).collect();
+            if !visible.contains(&true) {
+                continue;
+            }
+
+            //edges of the visible faces that dont have a visible face on the other side are the rim of the hole
+            let mut horizon = Vec::new();
+            for (face, _) in faces.iter().zip(&visible).filter(|(_, visible)| **visible) {
+                for edge in [(face[0], face[1]), (face[1], face[2]), (face[2], face[0])] {
+                    let shared = faces.iter().zip(&visible).any(|(other, visible)| {
+                        *visible && [(other[0], other[1]), (other[1], other[2]), (other[2], other[0])].contains(&(edge.1, edge.0))
+                    });
+                    if !shared {
+                        horizon.push(edge);
+                    }
+                }
+            }
+            let mut kept = visible.iter();
+            faces.retain(|_| !kept.next().copied().unwrap_or(false));
+            faces.extend(horizon.into_iter().map(|(a, b)| [a, b, index]));
+        }
+
+        //only keep the points that are actually corners
+        let mut remap = vec![usize::MAX; points.len()];
+        let mut vertices = Vec::new();
+        for face in &mut faces {
+            for index in face.iter_mut() {
+                if remap[*index] == usize::MAX {
+                    remap[*index] = vertices.len();
+                    vertices.push(points[*index]);
+                }
+                *index = remap[*index];
+            }
+        }
+        let planes = faces.iter().map(|face| face_plane(&vertices, face)).collect();
+        Ok(Self { vertices, faces, planes })
+    }
+
+    /// Box shaped hull, `half_extents` out from the center on each axis
+    pub fn cuboid(half_extents: Vector3<f32>) -> Self {
+        let corners: Vec<Vector3<f32>> = (0..8).map(|i| {
+            let sign = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            Vector3::new(half_extents.x * sign(1), half_extents.y * sign(2), half_extents.z * sign(4))
+        }).collect();
+        Self::new(&corners).expect("a box with no size cant be a convex hull")
+    }
+
+    pub fn vertices(&self) -> &[Vector3<f32>] {
+        &self.vertices
+    }
+
+    pub fn faces(&self) -> &[[usize; 3]] {
+        &self.faces
+    }
+
+    pub fn local_bounds(&self) -> Aabb {
+        self.vertices.iter().fold(Aabb::new(self.vertices[0], self.vertices[0]), |bounds, vertex| bounds.merge(Aabb::new(*vertex, *vertex)))
+    }
+
+    /// Furthest corner along `direction`
+    pub fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        self.vertices.iter().copied().max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction))).unwrap_or(Vector3::zero())
+    }
+
+    pub fn contains(&self, point: Vector3<f32>) -> bool {
+        self.planes.iter().all(|(normal, distance)| point.dot(*normal) <= *distance)
+    }
+
+    /// Biggest sphere around the local origin that fits inside, 0 if the origin isnt inside the hull
+    pub fn inner_radius(&self) -> f32 {
+        self.planes.iter().map(|(_, distance)| *distance).fold(f32::INFINITY, f32::min).max(0.0)
+    }
+
+    /// Ray in the hulls own space (`direction` normalized), same rules as the other shapes, starting inside is a miss
+    pub fn raycast(&self, origin: Vector3<f32>, direction: Vector3<f32>, max_distance: f32) -> Option<(f32, Vector3<f32>)> {
+        self.clip_ray(origin, direction, max_distance, 0.0)
+    }
+
+    //the ray clipped by every face plane pushed out by `grow`, ccd uses the grown version for its swept spheres
+    pub(crate) fn clip_ray(&self, origin: Vector3<f32>, direction: Vector3<f32>, max_distance: f32, grow: f32) -> Option<(f32, Vector3<f32>)> {
+        let mut enter = f32::NEG_INFINITY;
+        let mut exit = max_distance;
+        let mut hit_normal = Vector3::zero();
+        for (normal, distance) in &self.planes {
+            let above = origin.dot(*normal) - distance - grow;
+            let towards = direction.dot(*normal);
+            if towards.abs() < 1e-8 {
+                if above > 0.0 {
+                    return None;
+                }
+                continue;
+            }
+            let t = -above / towards;
+            if towards < 0.0 {
+                if t > enter {
+                    enter = t;
+                    hit_normal = *normal;
+                }
+            } else {
+                exit = exit.min(t);
+            }
+            if enter > exit {
+                return None;
+            }
+        }
+        (enter >= 0.0).then_some((enter, hit_normal))
+    }
+
+    /// Volume, center of mass and the inertia per unit of mass around that center, all in the hulls own space.
+    /// Every face makes a tetrahedron with a point inside and those get added up
+    pub fn mass_properties(&self) -> (f32, Vector3<f32>, Matrix3<f32>) {
+        let inside = self.vertices.iter().fold(Vector3::zero(), |sum, vertex| sum + vertex) / self.vertices.len() as f32;
+        let mut volume = 0.0;
+        let mut moment = Vector3::zero();
+        let mut covariance = Matrix3::zero();
+        for face in &self.faces {
+            let [a, b, c] = face.map(|index| self.vertices[index] - inside);
+            let determinant = a.dot(b.cross(c));
+            let sum = a + b + c;
+            volume += determinant / 6.0;
+            moment += sum * (determinant / 24.0);
+            covariance += (outer(a, a) + outer(b, b) + outer(c, c) + outer(sum, sum)) * (determinant / 120.0);
+        }
+        if volume <= 0.0 {
+            return (0.0, inside, Matrix3::zero());
+        }
+
+        //move the covariance from the inside point to the center of mass, then inertia is trace * identity - covariance
+        let center = moment / volume;
+        let covariance = (covariance - outer(center, center) * volume) / volume;
+        let trace = covariance.x.x + covariance.y.y + covariance.z.z;
+        (volume, inside + center, Matrix3::identity() * trace - covariance)
+    }
+}
+
+pub(crate) fn outer(a: Vector3<f32>, b: Vector3<f32>) -> Matrix3<f32> {
+    Matrix3::from_cols(a * b.x, a * b.y, a * b.z)
+}
+
+fn face_plane(points: &[Vector3<f32>], face: &[usize; 3]) -> (Vector3<f32>, f32) {
+    let normal = (points[face[1]] - points[face[0]]).cross(points[face[2]] - points[face[0]]).normalize();
+    (normal, normal.dot(points[face[0]]))
+}
+
+fn furthest(points: &[Vector3<f32>], score: impl Fn(Vector3<f32>) -> f32) -> usize {
+    (0..points.len()).max_by(|a, b| score(points[*a]).total_cmp(&score(points[*b]))).unwrap_or(0)
+}
@@ -0,0 +1,134 @@
+use cgmath::{InnerSpace, Vector3, Zero};
+
+//force generators that live on trigger colliders, every body inside the trigger gets pushed by it through apply_force
+//everything here is an acceleration so a pebble and a crate get moved the same, the force is just that times the mass
+
+/// How a point field gets weaker further from its center
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Falloff {
+    Constant,      // full strength all the way to the edge
+    Linear,        // full at the center, nothing at the radius
+    InverseSquare, // like real gravity, capped at full strength inside 1 unit so it doesnt blow up at the center
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ForceFieldKind {
+    /// Replaces the world gravity for bodies inside, low gravity rooms, upside down rooms, ect
+    Gravity { gravity: Vector3<f32> },
+    /// Pushes on top of the world gravity, jump pads and conveyor air
+    Directional { acceleration: Vector3<f32> },
+    /// Pulls towards the triggers center, negative strength pushes away instead
+    Point { strength: f32, radius: f32, falloff: Falloff },
+    /// Drags bodies towards the wind velocity, turbulence (0 to 1ish) is how much the gusts wander
+    Wind { velocity: Vector3<f32>, strength: f32, turbulence: f32 },
+    /// Slows down everything inside, mud, thick fog, ect
+    Drag { drag: f32 },
+}
+
+/// What the fields do at one spot, for things that arent rigidbodies (cloth, particles) from `PhysicsSystem::sample_fields`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldSample {
+    pub gravity: Vector3<f32>,      // the world gravity, or whichever gravity zone wins there
+    pub acceleration: Vector3<f32>, // directional and point fields
+    pub air: Vector3<f32>,          // wind and drag, kept apart so cloth can take just the part that hits it face on
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ForceField {
+    pub kind: ForceFieldKind,
+    pub enabled: bool,
+    pub priority: i32, // when gravity zones overlap the highest priority one wins, ties go to the lower entity id
+}
+
+impl ForceField {
+    pub fn new(kind: ForceFieldKind) -> Self {
+        Self { kind, enabled: true, priority: 0 }
+    }
+
+    pub fn gravity(gravity: Vector3<f32>) -> Self {
+        Self::new(ForceFieldKind::Gravity { gravity })
+    }
+
+    pub fn directional(acceleration: Vector3<f32>) -> Self {
+        Self::new(ForceFieldKind::Directional { acceleration })
+    }
+
+    /// Straight up, strong enough to beat gravity while something is standing on the pad
+    pub fn jump_pad(acceleration: f32) -> Self {
+        Self::directional(Vector3::new(0.0, acceleration, 0.0))
+    }
+
+    pub fn attractor(strength: f32, radius: f32, falloff: Falloff) -> Self {
+        Self::new(ForceFieldKind::Point { strength, radius, falloff })
+    }
+
+    pub fn repulsor(strength: f32, radius: f32, falloff: Falloff) -> Self {
+        Self::new(ForceFieldKind::Point { strength: -strength, radius, falloff })
+    }
+
+    pub fn wind(velocity: Vector3<f32>, strength: f32) -> Self {
+        Self::new(ForceFieldKind::Wind { velocity, strength, turbulence: 0.0 })
+    }
+
+    pub fn drag(drag: f32) -> Self {
+        Self::new(ForceFieldKind::Drag { drag })
+    }
+
+    /// Only does anything for wind
+    pub fn with_turbulence(mut self, amount: f32) -> Self {
+        if let ForceFieldKind::Wind { turbulence, .. } = &mut self.kind {
+            *turbulence = amount;
+        }
+        self
+    }
+
+    pub fn with_priority(mut self, priority: i32) -> Self {
+        self.priority = priority;
+        self
+    }
+
+    pub fn with_enabled(mut self, enabled: bool) -> Self {
+        self.enabled = enabled;
+        self
+    }
+
+    /// Acceleration this field gives a body at `position` moving at `velocity`, gravity zones arent in here since
+    /// they replace the world gravity instead of adding to it
+    pub(crate) fn acceleration(&self, center: Vector3<f32>, position: Vector3<f32>, velocity: Vector3<f32>, time: f32) -> Vector3<f32> {
+        match self.kind {
+            ForceFieldKind::Gravity { .. } => Vector3::zero(),
+            ForceFieldKind::Directional { acceleration } => acceleration,
+            ForceFieldKind::Point { strength, radius, falloff } => {
+                let to_center = center - position;
+                let distance = to_center.magnitude();
+                if distance > radius || distance < 1e-4 {
+                    return Vector3::zero();
+                }
+                let scale = match falloff {
+                    Falloff::Constant => 1.0,
+                    Falloff::Linear => 1.0 - distance / radius,
+                    Falloff::InverseSquare => 1.0 / (distance * distance).max(1.0),
+                };
+                to_center / distance * strength * scale
+            },
+            ForceFieldKind::Wind { velocity: wind, strength, turbulence } => {
+                let gust = wind + gust_noise(position, time) * (turbulence * wind.magnitude());
+                (gust - velocity) * strength
+            },
+            ForceFieldKind::Drag { drag } => -velocity * drag,
+        }
+    }
+}
+
+//cheap smooth wobble in -1 to 1 on each axis, made from sines so it comes out the same every run and for every body at the
+//same place, nearby bodies get similar gusts so stuff blows around together instead of jittering on its own
+fn gust_noise(position: Vector3<f32>, time: f32) -> Vector3<f32> {
+    let wave = |a: f32, b: f32, c: f32| {
+        (time * 1.3 + a * 0.7).sin() * 0.5 + (time * 2.9 + b * 1.1 + 1.7).sin() * 0.3 + (time * 5.3 + c * 0.5 + 4.1).sin() * 0.2
+    };
+    Vector3::new(
+        wave(position.x, position.z, position.y),
+        wave(position.y + 11.0, position.x, position.z) * 0.5, // less up and down than side to side
+        wave(position.z + 23.0, position.y, position.x),
+    )
+}
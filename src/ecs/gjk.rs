@@ -0,0 +1,606 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
+
+use super::collision_system::{compound_children, CollisionShape};
+
+//GJK (Gilbert-Johnson-Keerthi) + EPA (Expanding Polytope Algorithm)
+//https://caseymuratori.com/blog_0003 is the video that made gjk click for me
+//https://dyn4j.org/2010/05/epa-expanding-polytope-algorithm/ for the epa part
+
+//the whole trick is the minkowski difference A - B, if two convex shapes overlap then A - B contains the origin
+//we never actually build A - B, we only ever ask "what is the furthest point of A - B in this direction" which is
+//support_a(d) - support_b(-d). so any shape that can answer that question (a support map) can collide with any other one
+
+const GJK_MAX_ITERATIONS: usize = 64;
+const EPA_MAX_ITERATIONS: usize = 64;
+const EPA_TOLERANCE: f32 = 0.0001;
+
+pub trait SupportMap {
+    /// Furthest point of the shape along `direction` (does not need to be normalized)
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32>;
+}
+
+//support of a shape in its own local frame
+//NOTE the OBB's own rotation is NOT applied here, CollisionSystem::update already folds it into the world rotation
+//so applying it again would rotate the box twice
+fn local_support(shape: &CollisionShape, direction: Vector3<f32>) -> Vector3<f32> {
+    match shape {
+        //the 2d shapes are treated as flat shapes lying on the xy plane (same plane the 2d checks use)
+        CollisionShape::Circle { radius } => {
+            let flat = Vector3::new(direction.x, direction.y, 0.0);
+            if flat.magnitude2() > 0.0 {
+                flat.normalize() * *radius
+            } else {
+                Vector3::new(*radius, 0.0, 0.0)
+            }
+        },
+        CollisionShape::Rectangle { width, height } => Vector3::new(
+            width / 2.0 * sign(direction.x),
+            height / 2.0 * sign(direction.y),
+            0.0,
+        ),
+        CollisionShape::Sphere { radius } => {
+            if direction.magnitude2() > 0.0 {
+                direction.normalize() * *radius
+            } else {
+                Vector3::new(*radius, 0.0, 0.0)
+            }
+        },
+        CollisionShape::Box { width, height, depth } => {
+            box_support(Vector3::new(width / 2.0, height / 2.0, depth / 2.0), direction)
+        },
+        CollisionShape::OBB { half_extents, .. } => box_support(*half_extents, direction),
+        CollisionShape::Capsule { radius, half_height } => {
+            //the end of the middle segment that faces the direction, then out by the radius like a sphere
+            let end = Vector3::new(0.0, half_height * sign(direction.y), 0.0);
+            if direction.magnitude2() > 0.0 {
+                end + direction.normalize() * *radius
+            } else {
+                end + Vector3::new(*radius, 0.0, 0.0)
+            }
+        },
+        //furthest of all the childrens furthest points, so gjk sees the convex hull around the whole compound
+        //the collision system splits compounds into their children before it gets here, this is for anything else asking
+        CollisionShape::Compound(children) => {
+            compound_children(children, Vector3::new(0.0, 0.0, 0.0), Quaternion::new(1.0, 0.0, 0.0, 0.0))
+                .map(|(_, position, rotation, child)| {
+                    position + rotation.rotate_vector(local_support(&child.shape, rotation.invert().rotate_vector(direction)))
+                })
+                .max_by(|a, b| a.dot(direction).total_cmp(&b.dot(direction)))
+                .unwrap_or(Vector3::new(0.0, 0.0, 0.0))
+        },
+        //terrain isnt convex at all, this is just the box around it. the collision system does terrain triangle by triangle
+        CollisionShape::Heightfield(heightfield) => {
+            let bounds = heightfield.local_bounds();
+            Vector3::new(
+                if direction.x < 0.0 { bounds.min.x } else { bounds.max.x },
+                if direction.y < 0.0 { bounds.min.y } else { bounds.max.y },
+                if direction.z < 0.0 { bounds.min.z } else { bounds.max.z },
+            )
+        },
+        CollisionShape::ConvexHull(hull) => hull.support(direction),
+    }
+}
+
+fn box_support(half_extents: Vector3<f32>, direction: Vector3<f32>) -> Vector3<f32> {
+    Vector3::new(
+        half_extents.x * sign(direction.x),
+        half_extents.y * sign(direction.y),
+        half_extents.z * sign(direction.z),
+    )
+}
+
+//like f32::signum but -0.0 counts as positive too, a zero direction still just picks a corner
+fn sign(value: f32) -> f32 {
+    if value < 0.0 { -1.0 } else { 1.0 }
+}
+
+impl SupportMap for CollisionShape {
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        match self {
+            CollisionShape::OBB { rotation, .. } => {
+                let local_direction = rotation.invert().rotate_vector(direction);
+                rotation.rotate_vector(local_support(self, local_direction))
+            },
+            _ => local_support(self, direction),
+        }
+    }
+}
+
+/// A collision shape placed in the world. `rotation` is the full world rotation of the shape,
+/// the same one `CollisionSystem::update` hands to `check_collision` (for OBBs it already includes the OBB rotation)
+pub struct WorldShape<'a> {
+    pub shape: &'a CollisionShape,
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+}
+
+impl<'a> WorldShape<'a> {
+    pub fn new(shape: &'a CollisionShape, position: Vector3<f32>, rotation: Quaternion<f32>) -> Self {
+        Self { shape, position, rotation }
+    }
+}
+
+impl SupportMap for WorldShape<'_> {
+    fn support(&self, direction: Vector3<f32>) -> Vector3<f32> {
+        let local_direction = self.rotation.invert().rotate_vector(direction);
+        self.position + self.rotation.rotate_vector(local_support(self.shape, local_direction))
+    }
+}
+
+/// Result of the EPA pass, normal follows the `CollisionEvent` convention (direction to push A out of B)
+#[derive(Debug, Clone, Copy)]
+pub struct ContactInfo {
+    pub normal: Vector3<f32>,
+    pub penetration: f32,
+    pub point_a: Vector3<f32>, // deepest point of A inside B
+    pub point_b: Vector3<f32>, // deepest point of B inside A
+}
+
+impl ContactInfo {
+    pub fn contact_point(&self) -> Vector3<f32> {
+        (self.point_a + self.point_b) * 0.5
+    }
+}
+
+//a point on the minkowski difference, we also keep the two points it came from so epa can give back real contact points
+#[derive(Debug, Clone, Copy)]
+struct SupportPoint {
+    point: Vector3<f32>,
+    a: Vector3<f32>,
+    b: Vector3<f32>,
+}
+
+fn minkowski_support<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B, direction: Vector3<f32>) -> SupportPoint {
+    let pa = a.support(direction);
+    let pb = b.support(-direction);
+    SupportPoint { point: pa - pb, a: pa, b: pb }
+}
+
+fn triple_cross(a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> Vector3<f32> {
+    a.cross(b).cross(c)
+}
+
+/// Plain boolean GJK test, cheaper than `gjk_epa` when you dont care about the penetration
+pub fn gjk_intersects<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B) -> bool {
+    gjk(a, b).is_some()
+}
+
+//returns the final simplex if the origin is inside A - B
+//the simplex is stored oldest first, newest last
+fn gjk<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B) -> Option<Vec<SupportPoint>> {
+    let mut direction = Vector3::new(1.0, 0.0, 0.0);
+    let first = minkowski_support(a, b, direction);
+    let mut simplex = vec![first];
+    direction = -first.point;
+
+    for _ in 0..GJK_MAX_ITERATIONS {
+        //origin sits right on the simplex so they are touching
+        if direction.magnitude2() < 1e-12 {
+            return Some(simplex);
+        }
+
+        let new_point = minkowski_support(a, b, direction);
+        //the furthest we can go towards the origin doesnt even reach it so there is a gap
+        if new_point.point.dot(direction) < 0.0 {
+            return None;
+        }
+
+        simplex.push(new_point);
+        if next_simplex(&mut simplex, &mut direction) {
+            return Some(simplex);
+        }
+    }
+
+    None
+}
+
+//figures out which feature of the simplex is closest to the origin, drops the rest and picks the next search direction
+//returns true once the origin is enclosed
+fn next_simplex(simplex: &mut Vec<SupportPoint>, direction: &mut Vector3<f32>) -> bool {
+    match simplex.len() {
+        2 => line_case(simplex, direction),
+        3 => triangle_case(simplex, direction),
+        4 => tetrahedron_case(simplex, direction),
+        _ => false,
+    }
+}
+
+fn line_case(simplex: &mut Vec<SupportPoint>, direction: &mut Vector3<f32>) -> bool {
+    let a = simplex[1];
+    let b = simplex[0];
+    let ab = b.point - a.point;
+    let ao = -a.point;
+
+    if ab.dot(ao) > 0.0 {
+        *direction = triple_cross(ab, ao, ab);
+        //origin is on the segment itself
+        if direction.magnitude2() < 1e-12 {
+            return true;
+        }
+    } else {
+        *simplex = vec![a];
+        *direction = ao;
+    }
+    false
+}
+
+fn triangle_case(simplex: &mut Vec<SupportPoint>, direction: &mut Vector3<f32>) -> bool {
+    let a = simplex[2];
+    let b = simplex[1];
+    let c = simplex[0];
+    let ab = b.point - a.point;
+    let ac = c.point - a.point;
+    let ao = -a.point;
+    let abc = ab.cross(ac);
+
+    if abc.cross(ac).dot(ao) > 0.0 {
+        if ac.dot(ao) > 0.0 {
+            *simplex = vec![c, a];
+            *direction = triple_cross(ac, ao, ac);
+            return false;
+        }
+        *simplex = vec![b, a];
+        return line_case(simplex, direction);
+    }
+
+    if ab.cross(abc).dot(ao) > 0.0 {
+        *simplex = vec![b, a];
+        return line_case(simplex, direction);
+    }
+
+    let side = abc.dot(ao);
+    if side.abs() < 1e-9 {
+        //origin lies inside the triangle
+        return true;
+    }
+    if side > 0.0 {
+        *direction = abc;
+    } else {
+        //flip the winding so the next point lands on the right side
+        *simplex = vec![b, c, a];
+        *direction = -abc;
+    }
+    false
+}
+
+fn tetrahedron_case(simplex: &mut Vec<SupportPoint>, direction: &mut Vector3<f32>) -> bool {
+    let a = simplex[3];
+    let b = simplex[2];
+    let c = simplex[1];
+    let d = simplex[0];
+    let ao = -a.point;
+
+    //the three faces touching the newest point, each paired with the vertex that is not on it
+    //instead of trusting the winding we just orient each normal away from that leftover vertex
+    let faces = [(b, c, d), (c, d, b), (d, b, c)];
+    for (p, q, opposite) in faces {
+        let mut normal = (p.point - a.point).cross(q.point - a.point);
+        if normal.dot(opposite.point - a.point) > 0.0 {
+            normal = -normal;
+        }
+        if normal.dot(ao) > 0.0 {
+            *simplex = vec![q, p, a];
+            return triangle_case(simplex, direction);
+        }
+    }
+
+    true
+}
+
+/// GJK intersection test followed by EPA for the penetration depth, normal and contact points
+pub fn gjk_epa<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B) -> Option<ContactInfo> {
+    let simplex = gjk(a, b)?;
+    let simplex = complete_simplex(a, b, simplex)?;
+    epa(a, b, simplex)
+}
+
+//gjk can finish early with a point, line or triangle when the origin sits right on it (touching shapes)
+//epa needs a proper tetrahedron to start from so we "blow up" the simplex by searching around it
+fn complete_simplex<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B, mut simplex: Vec<SupportPoint>) -> Option<[SupportPoint; 4]> {
+    let axes = [
+        Vector3::new(1.0, 0.0, 0.0),
+        Vector3::new(0.0, 1.0, 0.0),
+        Vector3::new(0.0, 0.0, 1.0),
+    ];
+
+    if simplex.len() == 1 {
+        for axis in axes.iter().flat_map(|axis| [*axis, -*axis]) {
+            let point = minkowski_support(a, b, axis);
+            if (point.point - simplex[0].point).magnitude2() > 1e-8 {
+                simplex.push(point);
+                break;
+            }
+        }
+    }
+
+    if simplex.len() == 2 {
+        let line = simplex[1].point - simplex[0].point;
+        //pick the world axis least aligned with the line so the cross product is well behaved
+        let least_aligned = axes.iter()
+            .min_by(|x, y| x.dot(line).abs().total_cmp(&y.dot(line).abs()))
+            .copied()
+            .unwrap_or(axes[0]);
+        let perpendicular = line.cross(least_aligned).normalize();
+        let other = line.normalize().cross(perpendicular);
+        for direction in [perpendicular, -perpendicular, other, -other] {
+            let point = minkowski_support(a, b, direction);
+            if point.point.dot(direction) - simplex[0].point.dot(direction) > 1e-5 {
+                simplex.push(point);
+                break;
+            }
+        }
+    }
+
+    if simplex.len() == 3 {
+        let normal = (simplex[1].point - simplex[0].point).cross(simplex[2].point - simplex[0].point);
+        if normal.magnitude2() < 1e-12 {
+            return None;
+        }
+        for direction in [normal, -normal] {
+            let point = minkowski_support(a, b, direction);
+            if point.point.dot(direction) - simplex[0].point.dot(direction) > 1e-5 {
+                simplex.push(point);
+                break;
+            }
+        }
+    }
+
+    //both shapes are flat in the same plane (or something equally cursed), epa cant give a depth for that
+    if simplex.len() != 4 {
+        return None;
+    }
+
+    Some([simplex[0], simplex[1], simplex[2], simplex[3]])
+}
+
+struct PolytopeFace {
+    indices: [usize; 3],
+    normal: Vector3<f32>,
+    distance: f32,
+}
+
+//`interior` is any point strictly inside the polytope, normals get flipped to point away from it
+//(the origin can sit right on a face when the shapes are just touching so its no good for this)
+fn make_face(vertices: &[SupportPoint], indices: [usize; 3], interior: Vector3<f32>) -> Option<PolytopeFace> {
+    let a = vertices[indices[0]].point;
+    let b = vertices[indices[1]].point;
+    let c = vertices[indices[2]].point;
+    let normal = (b - a).cross(c - a);
+    if normal.magnitude2() < 1e-14 {
+        return None;
+    }
+    let mut normal = normal.normalize();
+    let mut indices = indices;
+    if normal.dot(a - interior) < 0.0 {
+        normal = -normal;
+        indices.swap(1, 2);
+    }
+    //origin is inside (or on) the polytope so this is never really negative, clamp the float noise away
+    let distance = normal.dot(a).max(0.0);
+    Some(PolytopeFace { indices, normal, distance })
+}
+
+fn epa<A: SupportMap + ?Sized, B: SupportMap + ?Sized>(a: &A, b: &B, simplex: [SupportPoint; 4]) -> Option<ContactInfo> {
+    let mut vertices: Vec<SupportPoint> = simplex.to_vec();
+    let interior = (simplex[0].point + simplex[1].point + simplex[2].point + simplex[3].point) / 4.0;
+    let mut faces: Vec<PolytopeFace> = [[0, 1, 2], [0, 3, 1], [0, 2, 3], [1, 3, 2]]
+        .iter()
+        .filter_map(|indices| make_face(&vertices, *indices, interior))
+        .collect();
+
+    if faces.is_empty() {
+        return None;
+    }
+
+    for _ in 0..EPA_MAX_ITERATIONS {
+        let closest = closest_face(&faces);
+        let normal = faces[closest].normal;
+        let distance = faces[closest].distance;
+
+        let new_point = minkowski_support(a, b, normal);
+        //cant push the polytope out any further in this direction so this face is the boundary
+        if new_point.point.dot(normal) - distance < EPA_TOLERANCE {
+            return Some(contact_from_face(&vertices, &faces[closest]));
+        }
+
+        //every face that can "see" the new point gets removed and the hole gets patched with new faces
+        let mut horizon: Vec<(usize, usize)> = Vec::new();
+        faces.retain(|face| {
+            let visible = face.normal.dot(new_point.point - vertices[face.indices[0]].point) > 0.0;
+            if visible {
+                for k in 0..3 {
+                    let edge = (face.indices[k], face.indices[(k + 1) % 3]);
+                    //an edge shared by two removed faces is not on the horizon
+                    if let Some(position) = horizon.iter().position(|e| *e == (edge.1, edge.0) || *e == edge) {
+                        horizon.swap_remove(position);
+                    } else {
+                        horizon.push(edge);
+                    }
+                }
+            }
+            !visible
+        });
+
+        let new_index = vertices.len();
+        vertices.push(new_point);
+        for (from, to) in horizon {
+            if let Some(face) = make_face(&vertices, [from, to, new_index], interior) {
+                faces.push(face);
+            }
+        }
+
+        if faces.is_empty() {
+            return None;
+        }
+    }
+
+    //ran out of iterations, the closest face we have is still a decent answer
+    let closest = closest_face(&faces);
+    Some(contact_from_face(&vertices, &faces[closest]))
+}
+
+fn closest_face(faces: &[PolytopeFace]) -> usize {
+    let mut closest = 0;
+    for (i, face) in faces.iter().enumerate() {
+        if face.distance < faces[closest].distance {
+            closest = i;
+        }
+    }
+    closest
+}
+
+fn contact_from_face(vertices: &[SupportPoint], face: &PolytopeFace) -> ContactInfo {
+    let v0 = vertices[face.indices[0]];
+    let v1 = vertices[face.indices[1]];
+    let v2 = vertices[face.indices[2]];
+
+    //project the origin onto the face and use its barycentric coords to map back onto A and B
+    let projected = face.normal * face.distance;
+    let (u, v, w) = barycentric(projected, v0.point, v1.point, v2.point);
+
+    ContactInfo {
+        //translating A by -normal * distance separates them so the push direction for A is -normal
+        normal: -face.normal,
+        penetration: face.distance,
+        point_a: v0.a * u + v1.a * v + v2.a * w,
+        point_b: v0.b * u + v1.b * v + v2.b * w,
+    }
+}
+
+fn barycentric(p: Vector3<f32>, a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) -> (f32, f32, f32) {
+    let v0 = b - a;
+    let v1 = c - a;
+    let v2 = p - a;
+    let d00 = v0.dot(v0);
+    let d01 = v0.dot(v1);
+    let d11 = v1.dot(v1);
+    let d20 = v2.dot(v0);
+    let d21 = v2.dot(v1);
+    let denom = d00 * d11 - d01 * d01;
+    if denom.abs() < 1e-12 {
+        return (1.0, 0.0, 0.0);
+    }
+    let v = (d11 * d20 - d01 * d21) / denom;
+    let w = (d00 * d21 - d01 * d20) / denom;
+    (1.0 - v - w, v, w)
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{Deg, InnerSpace, Quaternion, Rotation3, Vector3};
+
+    use super::{gjk_epa, gjk_intersects, ContactInfo, WorldShape};
+    use crate::ecs::collision_system::{Collider, CollisionShape, CollisionSystem};
+
+    fn identity() -> Quaternion<f32> {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    fn assert_finite(contact: &ContactInfo) {
+        assert!(contact.penetration.is_finite(), "penetration is {}", contact.penetration);
+        for v in [contact.normal, contact.point_a, contact.point_b] {
+            assert!(v.x.is_finite() && v.y.is_finite() && v.z.is_finite(), "got a NaN in {:?}", contact);
+        }
+    }
+
+    #[test]
+    fn sphere_sphere_matches_the_analytic_answer() {
+        let a = CollisionShape::Sphere { radius: 1.0 };
+        let b = CollisionShape::Sphere { radius: 0.5 };
+        let pos_a = Vector3::new(0.3, -0.2, 0.1);
+        let pos_b = pos_a + Vector3::new(1.0, 0.6, -0.4);
+
+        let contact = gjk_epa(&WorldShape::new(&a, pos_a, identity()), &WorldShape::new(&b, pos_b, identity())).unwrap();
+        assert_finite(&contact);
+
+        // depth is the sum of the radii minus the distance, A gets pushed straight away from B
+        let offset = pos_b - pos_a;
+        let depth = 1.5 - offset.magnitude();
+        assert!((contact.penetration - depth).abs() < 0.01, "penetration {} vs {}", contact.penetration, depth);
+        assert!(contact.normal.dot(-offset.normalize()) > 0.999, "normal {:?}", contact.normal);
+    }
+
+    #[test]
+    fn capsule_against_rotated_obb() {
+        let capsule = CollisionShape::Capsule { radius: 0.5, half_height: 1.0 };
+        let rotation = Quaternion::from_angle_y(Deg(45.0));
+        let obb = CollisionShape::OBB { half_extents: Vector3::new(1.0, 1.0, 1.0), rotation };
+        let pos_b = Vector3::new(1.8, 0.5, 0.0);
+
+        // the turned box pokes its vertical edge out to sqrt(2) along x, so it reaches in past the capsules side
+        let contact = gjk_epa(
+            &WorldShape::new(&capsule, Vector3::new(0.0, 0.0, 0.0), identity()),
+            &WorldShape::new(&obb, pos_b, rotation),
+        ).unwrap();
+        assert_finite(&contact);
+
+        let depth = 0.5 - (pos_b.x - 2.0_f32.sqrt());
+        assert!((contact.penetration - depth).abs() < 0.01, "penetration {} vs {}", contact.penetration, depth);
+        assert!(contact.normal.dot(Vector3::new(-1.0, 0.0, 0.0)) > 0.99, "normal {:?}", contact.normal);
+    }
+
+    #[test]
+    fn circle_against_box_mixes_2d_and_3d() {
+        // the circle lies flat on the xy plane and dips 0.1 into the top of the box
+        let circle = Collider::circle(0.5);
+        let cube = Collider::bounding_box(2.0, 2.0, 2.0);
+        let event = CollisionSystem::check_convex_collision(
+            0, Vector3::new(0.2, 1.4, 0.0), &circle, identity(),
+            1, Vector3::new(0.0, 0.0, 0.0), &cube, identity(),
+        ).unwrap();
+
+        assert!((event.penetration - 0.1).abs() < 0.01, "penetration {}", event.penetration);
+        assert!(event.normal.dot(Vector3::new(0.0, 1.0, 0.0)) > 0.99, "normal {:?}", event.normal);
+    }
+
+    #[test]
+    fn separated_shapes_dont_collide() {
+        let sphere = CollisionShape::Sphere { radius: 1.0 };
+        let obb = CollisionShape::OBB { half_extents: Vector3::new(0.5, 0.5, 0.5), rotation: Quaternion::from_angle_z(Deg(30.0)) };
+        let a = WorldShape::new(&sphere, Vector3::new(0.0, 0.0, 0.0), identity());
+        let b = WorldShape::new(&obb, Vector3::new(0.0, 2.5, 0.5), Quaternion::from_angle_z(Deg(30.0)));
+
+        assert!(!gjk_intersects(&a, &b));
+        assert!(gjk_epa(&a, &b).is_none());
+    }
+
+    #[test]
+    fn touching_and_coincident_shapes_stay_finite() {
+        let sphere = CollisionShape::Sphere { radius: 1.0 };
+        let cube = CollisionShape::Box { width: 2.0, height: 2.0, depth: 2.0 };
+
+        // exactly touching, gjk ends on a point or line sitting on the origin. either no contact or a zero depth one
+        let touching = [
+            (&sphere, &sphere, Vector3::new(2.0, 0.0, 0.0)),
+            (&cube, &cube, Vector3::new(0.0, 2.0, 0.0)),
+            (&cube, &sphere, Vector3::new(0.0, 0.0, 2.0)),
+        ];
+        for (a, b, offset) in touching {
+            let contact = gjk_epa(
+                &WorldShape::new(a, Vector3::new(0.0, 0.0, 0.0), identity()),
+                &WorldShape::new(b, offset, identity()),
+            );
+            if let Some(contact) = contact {
+                assert_finite(&contact);
+                assert!(contact.penetration < 0.01, "touching shapes went {} deep", contact.penetration);
+            }
+        }
+
+        // same centre, the first gjk direction is zero. any face is a fine answer but the depth has to be the full size
+        let contact = gjk_epa(
+            &WorldShape::new(&cube, Vector3::new(1.0, 1.0, 1.0), identity()),
+            &WorldShape::new(&cube, Vector3::new(1.0, 1.0, 1.0), identity()),
+        ).unwrap();
+        assert_finite(&contact);
+        assert!((contact.penetration - 2.0).abs() < 0.001, "penetration {}", contact.penetration);
+        assert!((contact.normal.magnitude() - 1.0).abs() < 0.001);
+
+        // round shapes only ever get approximated from the inside by the polytope so the depth comes out a bit short
+        let contact = gjk_epa(
+            &WorldShape::new(&sphere, Vector3::new(1.0, 1.0, 1.0), identity()),
+            &WorldShape::new(&sphere, Vector3::new(1.0, 1.0, 1.0), identity()),
+        ).unwrap();
+        assert_finite(&contact);
+        assert!(contact.penetration > 1.8 && contact.penetration <= 2.0, "penetration {}", contact.penetration);
+    }
+}
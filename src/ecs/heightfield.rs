@@ -0,0 +1,431 @@
+use std::ops::Range;
+
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3};
+
+use super::aabb::Aabb;
+use super::collision_system::CollisionShape;
+
+//terrain as a grid of heights instead of a pile of triangles, every cell is split into two triangles on the fly
+//so only the handful of cells under something ever get looked at
+//the grid is centered on the entity in x/z and everything under the surface counts as solid ground, so nothing
+//can slip through by getting too deep
+
+#[derive(Debug, Clone)]
+pub struct Heightfield {
+    columns: usize, // points along x
+    rows: usize,    // points along z
+    spacing: f32,   // distance between neighbouring points
+    heights: Vec<f32>, // row by row, heights[row * columns + column]
+    min_height: f32,
+    max_height: f32,
+}
+
+/// A spot where something sank into the terrain, all in the heightfields own space
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct TerrainContact {
+    pub point: Vector3<f32>,  // halfway between the surface and the deepest point of the other shape
+    pub normal: Vector3<f32>, // pushes the other shape back out of the ground
+    pub penetration: f32,
+}
+
+impl Heightfield {
+    /// `heights` is `rows` rows of `columns` heights each, the first row is at -z
+    pub fn new(columns: usize, rows: usize, spacing: f32, heights: Vec<f32>) -> Self {
+        assert!(columns >= 2 && rows >= 2, "a heightfield needs at least 2x2 points, got {}x{}", columns, rows);
+        assert_eq!(heights.len(), columns * rows, "heightfield is {}x{} but got {} heights", columns, rows, heights.len());
+        let min_height = heights.iter().copied().fold(f32::INFINITY, f32::min);
+        let max_height = heights.iter().copied().fold(f32::NEG_INFINITY, f32::max);
+        Self { columns, rows, spacing, heights, min_height, max_height }
+    }
+
+    pub fn flat(columns: usize, rows: usize, spacing: f32, height: f32) -> Self {
+        Self::new(columns, rows, spacing, vec![height; columns * rows])
+    }
+
+    /// One point per pixel, black is 0 and white is `max_height`. Colour images get turned grey first
+    pub fn from_image(image: &image::DynamicImage, spacing: f32, max_height: f32) -> Self {
+        let grey = image.to_luma16();
+        let (width, height) = grey.dimensions();
+        let heights = grey.pixels().map(|pixel| pixel.0[0] as f32 / u16::MAX as f32 * max_height).collect();
+        Self::new(width as usize, height as usize, spacing, heights)
+    }
+
+    pub fn from_image_file(file_path: &str, spacing: f32, max_height: f32) -> Result<Self, String> {
+        let image = image::open(file_path).map_err(|e| format!("Failed to load heightmap: {}", e))?;
+        Ok(Self::from_image(&image, spacing, max_height))
+    }
+
+    pub fn columns(&self) -> usize {
+        self.columns
+    }
+
+    pub fn rows(&self) -> usize {
+        self.rows
+    }
+
+    pub fn spacing(&self) -> f32 {
+        self.spacing
+    }
+
+    pub fn width(&self) -> f32 {
+        (self.columns - 1) as f32 * self.spacing
+    }
+
+    pub fn depth(&self) -> f32 {
+        (self.rows - 1) as f32 * self.spacing
+    }
+
+    /// Box around the whole terrain in its own space. The ground under the surface is solid so the box
+    /// keeps going down a ways below the lowest point
+    pub fn local_bounds(&self) -> Aabb {
+        let floor = self.min_height - self.width().max(self.depth());
+        Aabb::new(
+            Vector3::new(-self.width() / 2.0, floor, -self.depth() / 2.0),
+            Vector3::new(self.width() / 2.0, self.max_height, self.depth() / 2.0),
+        )
+    }
+
+    /// Height of the surface at `x`/`z` (local), None off the edge
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let (column, row, fx, fz) = self.cell_at(x, z)?;
+        let h00 = self.height(column, row);
+        let h10 = self.height(column + 1, row);
+        let h01 = self.height(column, row + 1);
+        let h11 = self.height(column + 1, row + 1);
+        // same split as cell_triangles, the diagonal goes from (0,0) to (1,1)
+        if fz >= fx {
+            Some(h00 + fz * (h01 - h00) + fx * (h11 - h01))
+        } else {
+            Some(h00 + fx * (h10 - h00) + fz * (h11 - h10))
+        }
+    }
+
+    /// Up facing normal of the triangle at `x`/`z` (local), None off the edge
+    pub fn normal_at(&self, x: f32, z: f32) -> Option<Vector3<f32>> {
+        let (column, row, fx, fz) = self.cell_at(x, z)?;
+        let [first, second] = self.cell_triangles(column, row);
+        Some(triangle_normal(if fz >= fx { &first } else { &second }))
+    }
+
+    fn height(&self, column: usize, row: usize) -> f32 {
+        self.heights[row * self.columns + column]
+    }
+
+    fn vertex(&self, column: usize, row: usize) -> Vector3<f32> {
+        Vector3::new(
+            column as f32 * self.spacing - self.width() / 2.0,
+            self.height(column, row),
+            row as f32 * self.spacing - self.depth() / 2.0,
+        )
+    }
+
+    //cell under a point plus how far across it the point is (0 to 1 each way)
+    fn cell_at(&self, x: f32, z: f32) -> Option<(usize, usize, f32, f32)> {
+        let u = (x + self.width() / 2.0) / self.spacing;
+        let v = (z + self.depth() / 2.0) / self.spacing;
+        if u < 0.0 || v < 0.0 || u > (self.columns - 1) as f32 || v > (self.rows - 1) as f32 {
+            return None;
+        }
+        let column = (u as usize).min(self.columns - 2);
+        let row = (v as usize).min(self.rows - 2);
+        Some((column, row, u - column as f32, v - row as f32))
+    }
+
+    //both triangles of a cell, wound so their normals point up
+    fn cell_triangles(&self, column: usize, row: usize) -> [[Vector3<f32>; 3]; 2] {
+        let p00 = self.vertex(column, row);
+        let p10 = self.vertex(column + 1, row);
+        let p01 = self.vertex(column, row + 1);
+        let p11 = self.vertex(column + 1, row + 1);
+        [[p00, p01, p11], [p00, p11, p10]]
+    }
+
+    /// Every triangle of the surface in the heightfields own space, counter clockwise seen from above
+    pub fn triangles(&self) -> impl Iterator<Item = [Vector3<f32>; 3]> + '_ {
+        (0..self.rows - 1).flat_map(move |row| (0..self.columns - 1).flat_map(move |column| self.cell_triangles(column, row)))
+    }
+
+    //cells that overlap a box in x/z, clamped to the grid
+    fn cells_in(&self, bounds: &Aabb) -> Option<(Range<usize>, Range<usize>)> {
+        if bounds.min.y > self.max_height {
+            return None;
+        }
+        let to_cell = |value: f32, half: f32, count: usize| ((value + half) / self.spacing).floor().clamp(0.0, (count - 1) as f32) as usize;
+        let first_column = to_cell(bounds.min.x, self.width() / 2.0, self.columns);
+        let last_column = to_cell(bounds.max.x, self.width() / 2.0, self.columns).min(self.columns - 2);
+        let first_row = to_cell(bounds.min.z, self.depth() / 2.0, self.rows);
+        let last_row = to_cell(bounds.max.z, self.depth() / 2.0, self.rows).min(self.rows - 2);
+        if bounds.max.x < -self.width() / 2.0 || bounds.min.x > self.width() / 2.0
+            || bounds.max.z < -self.depth() / 2.0 || bounds.min.z > self.depth() / 2.0
+            || first_column > last_column || first_row > last_row {
+            return None;
+        }
+        Some((first_column..last_column + 1, first_row..last_row + 1))
+    }
+
+    /// Everywhere `shape` (placed in the heightfields own space) sinks into the ground
+    pub(crate) fn contacts(&self, shape: &CollisionShape, position: Vector3<f32>, rotation: Quaternion<f32>) -> Vec<TerrainContact> {
+        match shape {
+            CollisionShape::Sphere { radius } => self.sphere_contacts(position, *radius),
+            CollisionShape::Capsule { radius, half_height } => {
+                //the capsule is done as a line of spheres down its middle, close enough together that the
+                //gaps between them are way smaller than a cell
+                let count = ((half_height * 2.0 / radius).ceil() as usize).max(1) + 1;
+                (0..count).flat_map(|i| {
+                    let along = -half_height + half_height * 2.0 * i as f32 / (count - 1) as f32;
+                    self.sphere_contacts(position + rotation.rotate_vector(Vector3::new(0.0, along, 0.0)), *radius)
+                }).collect()
+            },
+            CollisionShape::Box { width, height, depth } => {
+                self.box_contacts(position, rotation, Vector3::new(width / 2.0, height / 2.0, depth / 2.0))
+            },
+            CollisionShape::OBB { half_extents, .. } => self.box_contacts(position, rotation, *half_extents),
+            CollisionShape::ConvexHull(hull) => {
+                //just the corners, same as the first half of box_contacts
+                let bounds = Aabb::of_shape(shape, position, rotation);
+                let Some((columns, rows)) = self.cells_in(&bounds) else { return Vec::new() };
+                let corners: Vec<Vector3<f32>> = hull.vertices().iter().map(|vertex| position + rotation.rotate_vector(*vertex)).collect();
+                self.corner_contacts(columns, rows, &corners)
+            },
+            _ => Vec::new(),
+        }
+    }
+
+    fn sphere_contacts(&self, center: Vector3<f32>, radius: f32) -> Vec<TerrainContact> {
+        let bounds = Aabb::from_center(center, Vector3::new(radius, radius, radius));
+        let Some((columns, rows)) = self.cells_in(&bounds) else { return Vec::new() };
+
+        let mut contacts = Vec::new();
+        for row in rows {
+            for column in columns.clone() {
+                for triangle in self.cell_triangles(column, row) {
+                    let face_normal = triangle_normal(&triangle);
+                    let above = (center - triangle[0]).dot(face_normal);
+                    let closest = closest_point_on_triangle(center, &triangle);
+
+                    let (normal, penetration) = if above < 0.0 {
+                        // sunk past the surface, only the triangle its actually under gets to push it back up
+                        if !inside_from_above(center, &triangle) {
+                            continue;
+                        }
+                        (face_normal, radius - above)
+                    } else {
+                        let offset = center - closest;
+                        let distance = offset.magnitude();
+                        if distance >= radius {
+                            continue;
+                        }
+                        let normal = if distance > 1e-6 { offset / distance } else { face_normal };
+                        (normal, radius - distance)
+                    };
+                    contacts.push(TerrainContact {
+                        point: center - normal * (radius - penetration / 2.0),
+                        normal,
+                        penetration,
+                    });
+                }
+            }
+        }
+        contacts
+    }
+
+    //corners of the box that are under a triangle, and grid points that poke up into the box
+    //(an edge of the box lying across a ridge with neither of those is missed, the next frame usually catches a corner)
+    fn box_contacts(&self, center: Vector3<f32>, rotation: Quaternion<f32>, half_extents: Vector3<f32>) -> Vec<TerrainContact> {
+        let bounds = Aabb::of_shape(&CollisionShape::OBB { half_extents, rotation }, center, rotation);
+        let Some((columns, rows)) = self.cells_in(&bounds) else { return Vec::new() };
+
+        let corners: Vec<Vector3<f32>> = (0..8).map(|i| {
+            let sign = |bit: usize| if i & bit == 0 { -1.0 } else { 1.0 };
+            center + rotation.rotate_vector(Vector3::new(half_extents.x * sign(1), half_extents.y * sign(2), half_extents.z * sign(4)))
+        }).collect();
+
+        let mut contacts = self.corner_contacts(columns.clone(), rows.clone(), &corners);
+
+        // the face of the box thats most facing down is what a spike would be poking into
+        let to_local = rotation.invert();
+        let up = to_local.rotate_vector(Vector3::new(0.0, 1.0, 0.0));
+        let axis = (0..3).max_by(|a, b| up[*a].abs().total_cmp(&up[*b].abs())).unwrap_or(1);
+        let mut push = Vector3::new(0.0, 0.0, 0.0);
+        push[axis] = up[axis].signum();
+        let normal = rotation.rotate_vector(push);
+        for row in rows.start..rows.end + 1 {
+            for column in columns.start..columns.end + 1 {
+                let local = to_local.rotate_vector(self.vertex(column, row) - center);
+                if local.x.abs() < half_extents.x && local.y.abs() < half_extents.y && local.z.abs() < half_extents.z {
+                    let depth = local[axis] * push[axis] + half_extents[axis];
+                    let point = self.vertex(column, row) - normal * (depth / 2.0);
+                    contacts.push(TerrainContact { point, normal, penetration: depth });
+                }
+            }
+        }
+        contacts
+    }
+
+    //corners that have sunk under a triangle of the given cells
+    fn corner_contacts(&self, columns: Range<usize>, rows: Range<usize>, corners: &[Vector3<f32>]) -> Vec<TerrainContact> {
+        let mut contacts = Vec::new();
+        for row in rows {
+            for column in columns.clone() {
+                for triangle in self.cell_triangles(column, row) {
+                    let normal = triangle_normal(&triangle);
+                    for corner in corners {
+                        let depth = (triangle[0] - corner).dot(normal);
+                        if depth > 0.0 && inside_from_above(*corner, &triangle) {
+                            contacts.push(TerrainContact { point: corner + normal * (depth / 2.0), normal, penetration: depth });
+                        }
+                    }
+                }
+            }
+        }
+        contacts
+    }
+
+    /// First place the ray (local space, `direction` normalized) crosses the surface, walking the cells under it in order
+    pub fn raycast(&self, origin: Vector3<f32>, direction: Vector3<f32>, max_distance: f32) -> Option<(f32, Vector3<f32>)> {
+        // clip the ray to the terrains box first so the walk starts and ends on the grid
+        let bounds = self.local_bounds();
+        let (mut enter, mut exit) = (0.0_f32, max_distance);
+        for axis in 0..3 {
+            if direction[axis].abs() < 1e-8 {
+                if origin[axis] < bounds.min[axis] || origin[axis] > bounds.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let t1 = (bounds.min[axis] - origin[axis]) / direction[axis];
+            let t2 = (bounds.max[axis] - origin[axis]) / direction[axis];
+            enter = enter.max(t1.min(t2));
+            exit = exit.min(t1.max(t2));
+        }
+        if enter > exit {
+            return None;
+        }
+
+        let start = origin + direction * enter;
+        let (half_width, half_depth) = (self.width() / 2.0, self.depth() / 2.0);
+        let (mut column, mut row, _, _) = self.cell_at(start.x.clamp(-half_width, half_width), start.z.clamp(-half_depth, half_depth))?;
+        let step_column: isize = if direction.x > 0.0 { 1 } else { -1 };
+        let step_row: isize = if direction.z > 0.0 { 1 } else { -1 };
+        //distance along the ray to the next column/row line, and how far apart those lines are along the ray
+        let next_line = |index: usize, step: isize, half: f32, from: f32, along: f32| {
+            if along.abs() < 1e-8 {
+                return f32::INFINITY;
+            }
+            let line = (index as f32 + if step > 0 { 1.0 } else { 0.0 }) * self.spacing - half;
+            (line - from) / along
+        };
+        let mut next_column = enter + next_line(column, step_column, half_width, start.x, direction.x);
+        let mut next_row = enter + next_line(row, step_row, half_depth, start.z, direction.z);
+        let column_step = if direction.x.abs() < 1e-8 { f32::INFINITY } else { self.spacing / direction.x.abs() };
+        let row_step = if direction.z.abs() < 1e-8 { f32::INFINITY } else { self.spacing / direction.z.abs() };
+
+        loop {
+            let cell_exit = next_column.min(next_row).min(exit);
+            let hit = self.cell_triangles(column, row).iter()
+                .filter_map(|triangle| ray_triangle(origin, direction, triangle))
+                .filter(|(distance, _)| *distance <= cell_exit + 1e-4 && *distance <= max_distance)
+                .min_by(|a, b| a.0.total_cmp(&b.0));
+            if hit.is_some() {
+                return hit;
+            }
+            if cell_exit >= exit {
+                return None;
+            }
+
+            if next_column < next_row {
+                column = column.checked_add_signed(step_column).filter(|c| *c < self.columns - 1)?;
+                next_column += column_step;
+            } else {
+                row = row.checked_add_signed(step_row).filter(|r| *r < self.rows - 1)?;
+                next_row += row_step;
+            }
+        }
+    }
+}
+
+fn triangle_normal(triangle: &[Vector3<f32>; 3]) -> Vector3<f32> {
+    (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]).normalize()
+}
+
+//is the point straight above or below the triangle (looking down y)
+fn inside_from_above(point: Vector3<f32>, triangle: &[Vector3<f32>; 3]) -> bool {
+    let edge = |a: Vector3<f32>, b: Vector3<f32>| (b.x - a.x) * (point.z - a.z) - (b.z - a.z) * (point.x - a.x);
+    let d0 = edge(triangle[0], triangle[1]);
+    let d1 = edge(triangle[1], triangle[2]);
+    let d2 = edge(triangle[2], triangle[0]);
+    (d0 >= 0.0 && d1 >= 0.0 && d2 >= 0.0) || (d0 <= 0.0 && d1 <= 0.0 && d2 <= 0.0)
+}
+
+//closest point on a triangle to p, from real time collision detection (ericson) 5.1.5
+fn closest_point_on_triangle(p: Vector3<f32>, [a, b, c]: &[Vector3<f32>; 3]) -> Vector3<f32> {
+    let (a, b, c) = (*a, *b, *c);
+    let ab = b - a;
+    let ac = c - a;
+    let ap = p - a;
+    let d1 = ab.dot(ap);
+    let d2 = ac.dot(ap);
+    if d1 <= 0.0 && d2 <= 0.0 {
+        return a;
+    }
+
+    let bp = p - b;
+    let d3 = ab.dot(bp);
+    let d4 = ac.dot(bp);
+    if d3 >= 0.0 && d4 <= d3 {
+        return b;
+    }
+
+    let vc = d1 * d4 - d3 * d2;
+    if vc <= 0.0 && d1 >= 0.0 && d3 <= 0.0 {
+        return a + ab * (d1 / (d1 - d3));
+    }
+
+    let cp = p - c;
+    let d5 = ab.dot(cp);
+    let d6 = ac.dot(cp);
+    if d6 >= 0.0 && d5 <= d6 {
+        return c;
+    }
+
+    let vb = d5 * d2 - d1 * d6;
+    if vb <= 0.0 && d2 >= 0.0 && d6 <= 0.0 {
+        return a + ac * (d2 / (d2 - d6));
+    }
+
+    let va = d3 * d6 - d5 * d4;
+    if va <= 0.0 && (d4 - d3) >= 0.0 && (d5 - d6) >= 0.0 {
+        return b + (c - b) * ((d4 - d3) / ((d4 - d3) + (d5 - d6)));
+    }
+
+    let denominator = 1.0 / (va + vb + vc);
+    a + ab * (vb * denominator) + ac * (vc * denominator)
+}
+
+//moller trumbore, hits from either side and the normal always faces back at the ray
+fn ray_triangle(origin: Vector3<f32>, direction: Vector3<f32>, [a, b, c]: &[Vector3<f32>; 3]) -> Option<(f32, Vector3<f32>)> {
+    let ab = b - a;
+    let ac = c - a;
+    let p = direction.cross(ac);
+    let determinant = ab.dot(p);
+    if determinant.abs() < 1e-10 {
+        return None;
+    }
+    let inverse = 1.0 / determinant;
+    let to_origin = origin - a;
+    let u = to_origin.dot(p) * inverse;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+    let q = to_origin.cross(ab);
+    let v = direction.dot(q) * inverse;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let distance = ac.dot(q) * inverse;
+    if distance < 0.0 {
+        return None;
+    }
+    let normal = ab.cross(ac).normalize();
+    Some((distance, if normal.dot(direction) > 0.0 { -normal } else { normal }))
+}
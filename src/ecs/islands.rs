@@ -0,0 +1,141 @@
+use std::collections::HashMap;
+
+use super::contact_manifold::ContactManifold;
+use super::joints::Joint;
+use super::physics::PhysicsEntity;
+use super::world::ComponentStorage;
+
+//bodies that sit still long enough get put to sleep and skipped by the integrator and the solver
+//they sleep and wake as a whole island (everything touching or jointed together) so a pile never ends up half asleep
+
+#[derive(Debug, Clone, Copy)]
+pub struct SleepSettings {
+    pub enabled: bool,
+    pub linear_threshold: f32,  // m/s, slower than this counts as resting
+    pub angular_threshold: f32, // rad/s
+    pub time_to_sleep: f32,     // seconds a whole island has to stay resting before it sleeps
+}
+
+impl Default for SleepSettings {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            linear_threshold: 0.05,
+            angular_threshold: 0.05,
+            time_to_sleep: 0.5,
+        }
+    }
+}
+
+impl SleepSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_thresholds(mut self, linear: f32, angular: f32) -> Self {
+        self.linear_threshold = linear;
+        self.angular_threshold = angular;
+        self
+    }
+
+    pub fn with_time_to_sleep(mut self, seconds: f32) -> Self {
+        self.time_to_sleep = seconds;
+        self
+    }
+
+    pub fn disabled(mut self) -> Self {
+        self.enabled = false;
+        self
+    }
+}
+
+/// Raised by the physics system whenever a body goes to sleep or wakes back up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SleepEvent {
+    FellAsleep(u32),
+    WokeUp(u32),
+}
+
+//only bodies that actually get simulated end up in islands, static and kinematic ones dont join anything
+//(otherwise everything on the floor would be one giant island)
+pub(crate) fn is_island_body(rigidbody: &PhysicsEntity) -> bool {
+    !rigidbody.is_static() && !rigidbody.is_kinematic
+}
+
+/// Groups dynamic bodies that touch or are jointed together, each island is sorted and so is the list
+pub(crate) fn build_islands(
+    rigidbodies: &ComponentStorage<PhysicsEntity>,
+    manifolds: &[&mut ContactManifold],
+    joints: &HashMap<u32, Joint>,
+) -> Vec<Vec<u32>> {
+    let mut islands = UnionFind::new();
+    for (entity_id, rigidbody) in rigidbodies.iter() {
+        if is_island_body(rigidbody) {
+            islands.add(*entity_id);
+        }
+    }
+
+    let links = manifolds.iter().map(|manifold| (manifold.entity_a, Some(manifold.entity_b)))
+        .chain(joints.values().filter(|joint| !joint.broken).map(|joint| (joint.entity_a, joint.entity_b)));
+    for (a, b) in links {
+        if let Some(b) = b {
+            if islands.contains(a) && islands.contains(b) {
+                islands.union(a, b);
+            }
+        }
+    }
+
+    islands.groups()
+}
+
+//plain union find, path halving on find and the smaller id always ends up the root so groups come out the same every run
+struct UnionFind {
+    parent: HashMap<u32, u32>,
+}
+
+impl UnionFind {
+    fn new() -> Self {
+        Self { parent: HashMap::new() }
+    }
+
+    fn add(&mut self, id: u32) {
+        self.parent.insert(id, id);
+    }
+
+    fn contains(&self, id: u32) -> bool {
+        self.parent.contains_key(&id)
+    }
+
+    fn find(&mut self, mut id: u32) -> u32 {
+        while self.parent[&id] != id {
+            let grandparent = self.parent[&self.parent[&id]];
+            self.parent.insert(id, grandparent);
+            id = grandparent;
+        }
+        id
+    }
+
+    fn union(&mut self, a: u32, b: u32) {
+        let (root_a, root_b) = (self.find(a), self.find(b));
+        if root_a != root_b {
+            self.parent.insert(root_a.max(root_b), root_a.min(root_b));
+        }
+    }
+
+    fn groups(mut self) -> Vec<Vec<u32>> {
+        let mut ids: Vec<u32> = self.parent.keys().copied().collect();
+        ids.sort();
+
+        let mut groups: Vec<Vec<u32>> = Vec::new();
+        let mut group_lookup: HashMap<u32, usize> = HashMap::new();
+        for id in ids {
+            let root = self.find(id);
+            let index = *group_lookup.entry(root).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[index].push(id);
+        }
+        groups
+    }
+}
@@ -0,0 +1,455 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Zero};
+
+//joints turn into a handful of rows every step, each row stops the bodies moving one way (or pushes them back)
+//the solver doesnt know what a hinge is, it just solves rows alongside the contacts
+
+/// How many rows a single joint can make, the accumulated impulse for each one is kept for warm starting
+pub const JOINT_SLOTS: usize = 8;
+
+//which slot each kind of row lives in so warm starting lines up between frames
+const LINEAR_SLOT: usize = 0; // 0..3
+const ANGULAR_SLOT: usize = 3; // 3..6
+const LIMIT_SLOT: usize = 6;
+const MOTOR_SLOT: usize = 7;
+
+#[derive(Debug, Clone, Copy)]
+pub struct JointMotor {
+    pub target_speed: f32, // radians per second A turns around the hinge axis (relative to B or the world)
+    pub max_torque: f32,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum JointKind {
+    /// keeps the anchors between min and max apart, min == max is a rigid rod, min 0 is a rope
+    Distance { min_length: f32, max_length: f32 },
+    /// anchors stay together, free to rotate any way
+    BallSocket,
+    /// anchors stay together and the bodies can only turn around `axis` (in A's local space), doors, wheels, ect
+    /// limits and the motor go by how far A has turned relative to B, so a body pinned to the world just spins at the motor speed
+    Hinge { axis: Vector3<f32>, limits: Option<(f32, f32)>, motor: Option<JointMotor> },
+    /// anchors stay together and B's copy of `axis` (in A's local space) has to stay within `swing` radians of A's,
+    /// twisting around it is kept between the twist limits. shoulders, hips and necks
+    ConeTwist { axis: Vector3<f32>, swing: f32, twist: (f32, f32) },
+    /// no rotation, B can only slide along `axis` (in A's local space), limits are how far along it
+    Slider { axis: Vector3<f32>, limits: Option<(f32, f32)> },
+    /// welds the two together
+    Fixed,
+    /// a soft distance joint, good for suspension
+    Spring { rest_length: f32, stiffness: f32, damping: f32 },
+}
+
+#[derive(Debug, Clone)]
+pub struct Joint {
+    pub entity_a: u32,
+    pub entity_b: Option<u32>, // None pins entity_a to the world
+    pub anchor_a: Vector3<f32>, // in A's local space
+    pub anchor_b: Vector3<f32>, // in B's local space, or a world position when pinned to the world
+    pub kind: JointKind,
+    pub break_force: Option<f32>,
+    pub break_torque: Option<f32>,
+    pub broken: bool,
+    pub collide_connected: bool, // false stops the two bodies colliding with each other, for overlapping limbs and such
+
+    // rotation of B relative to A the first time the joint got solved, hinges/sliders/fixed hold onto this
+    reference_rotation: Option<Quaternion<f32>>,
+    pub(crate) impulses: [f32; JOINT_SLOTS],
+}
+
+impl Joint {
+    pub fn new(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>, kind: JointKind) -> Self {
+        Self {
+            entity_a,
+            entity_b,
+            anchor_a,
+            anchor_b,
+            kind,
+            break_force: None,
+            break_torque: None,
+            broken: false,
+            collide_connected: true,
+            reference_rotation: None,
+            impulses: [0.0; JOINT_SLOTS],
+        }
+    }
+
+    pub fn distance(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>, length: f32) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::Distance { min_length: length, max_length: length })
+    }
+
+    pub fn rope(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>, max_length: f32) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::Distance { min_length: 0.0, max_length })
+    }
+
+    pub fn ball_socket(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::BallSocket)
+    }
+
+    pub fn hinge(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>, axis: Vector3<f32>) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::Hinge { axis: axis.normalize(), limits: None, motor: None })
+    }
+
+    /// `swing` is how far (radians) B's axis can tip away from A's in any direction, twist defaults to the same either way
+    pub fn cone_twist(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>, axis: Vector3<f32>, swing: f32) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::ConeTwist { axis: axis.normalize(), swing, twist: (-swing, swing) })
+    }
+
+    pub fn slider(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>, axis: Vector3<f32>) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::Slider { axis: axis.normalize(), limits: None })
+    }
+
+    pub fn fixed(entity_a: u32, entity_b: Option<u32>, anchor_a: Vector3<f32>, anchor_b: Vector3<f32>) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::Fixed)
+    }
+
+    pub fn spring(
+        entity_a: u32, entity_b: Option<u32>,
+        anchor_a: Vector3<f32>, anchor_b: Vector3<f32>,
+        rest_length: f32, stiffness: f32, damping: f32,
+    ) -> Self {
+        Self::new(entity_a, entity_b, anchor_a, anchor_b, JointKind::Spring { rest_length, stiffness, damping })
+    }
+
+    /// Angle limits for hinges (radians), the twist limits for cone twists or distance limits for sliders,
+    /// does nothing on the other kinds
+    pub fn with_limits(mut self, lower: f32, upper: f32) -> Self {
+        match &mut self.kind {
+            JointKind::Hinge { limits, .. } | JointKind::Slider { limits, .. } => *limits = Some((lower, upper)),
+            JointKind::ConeTwist { twist, .. } => *twist = (lower, upper),
+            _ => {},
+        }
+        self
+    }
+
+    /// Only hinges have motors
+    pub fn with_motor(mut self, target_speed: f32, max_torque: f32) -> Self {
+        if let JointKind::Hinge { motor, .. } = &mut self.kind {
+            *motor = Some(JointMotor { target_speed, max_torque });
+        }
+        self
+    }
+
+    /// Snaps once the anchors get pulled apart harder than this (newtons)
+    pub fn with_break_force(mut self, break_force: f32) -> Self {
+        self.break_force = Some(break_force);
+        self
+    }
+
+    /// Snaps once the bodies get twisted against the joint harder than this (newton meters), only the kinds that
+    /// hold rotation (hinge, cone twist, slider, fixed) ever feel any
+    pub fn with_break_torque(mut self, break_torque: f32) -> Self {
+        self.break_torque = Some(break_torque);
+        self
+    }
+
+    pub fn with_collide_connected(mut self, collide_connected: bool) -> Self {
+        self.collide_connected = collide_connected;
+        self
+    }
+
+    /// Rotation of B relative to A (A^-1 * B) that counts as straight for the hinge/cone/slider/fixed limits,
+    /// without this its whatever they are the first time the joint gets solved
+    pub fn with_reference_rotation(mut self, rotation: Quaternion<f32>) -> Self {
+        self.reference_rotation = Some(rotation);
+        self
+    }
+
+    pub fn set_motor_speed(&mut self, target_speed: f32) {
+        if let JointKind::Hinge { motor: Some(motor), .. } = &mut self.kind {
+            motor.target_speed = target_speed;
+        }
+    }
+
+    /// Rough force the joint is holding, worked out from the linear impulses of the last step. Turning doesnt count,
+    /// thats `applied_torque`
+    pub fn applied_force(&self, delta_time: f32) -> f32 {
+        if delta_time <= 0.0 {
+            return 0.0;
+        }
+        let linear = &self.impulses[LINEAR_SLOT..LINEAR_SLOT + 3];
+        linear.iter().map(|i| i * i).sum::<f32>().sqrt() / delta_time
+    }
+
+    /// Rough torque the joint is holding, from the rows that stop it turning. Angle limits count but a hinge motor doesnt,
+    /// thats the joint doing the pushing
+    pub fn applied_torque(&self, delta_time: f32) -> f32 {
+        if delta_time <= 0.0 {
+            return 0.0;
+        }
+        let mut angular = self.impulses[ANGULAR_SLOT..ANGULAR_SLOT + 3].to_vec();
+        if matches!(self.kind, JointKind::Hinge { .. } | JointKind::ConeTwist { .. }) {
+            angular.push(self.impulses[LIMIT_SLOT]);
+        }
+        angular.iter().map(|i| i * i).sum::<f32>().sqrt() / delta_time
+    }
+
+    // past either threshold this step
+    pub(crate) fn overloaded(&self, delta_time: f32) -> bool {
+        self.break_force.is_some_and(|force| self.applied_force(delta_time) > force)
+            || self.break_torque.is_some_and(|torque| self.applied_torque(delta_time) > torque)
+    }
+
+    /// Builds this steps rows. `frame_b` is the world (identity at the origin) when the joint is pinned to it
+    pub(crate) fn build_rows(&mut self, frame_a: JointFrame, frame_b: JointFrame, delta_time: f32) -> Vec<JointRow> {
+        let reference = *self.reference_rotation.get_or_insert_with(|| frame_a.rotation.invert() * frame_b.rotation);
+
+        let point_a = frame_a.position + frame_a.rotation.rotate_vector(self.anchor_a);
+        let point_b = frame_b.position + frame_b.rotation.rotate_vector(self.anchor_b);
+        let r_a = point_a - frame_a.position;
+        let r_b = point_b - frame_b.position;
+        let separation = point_b - point_a;
+
+        let mut rows = Vec::new();
+        match self.kind {
+            JointKind::Distance { min_length, max_length } => {
+                let length = separation.magnitude();
+                if length > 0.0001 {
+                    let direction = separation / length;
+                    let row = JointRow::linear(LINEAR_SLOT, direction, r_a, r_b);
+                    if min_length == max_length {
+                        rows.push(row.with_error(length - max_length));
+                    } else if length > max_length {
+                        rows.push(row.with_error(length - max_length).with_bounds(f32::MIN, 0.0));
+                    } else if length < min_length {
+                        rows.push(row.with_error(length - min_length).with_bounds(0.0, f32::MAX));
+                    }
+                }
+            },
+            JointKind::BallSocket => {
+                point_rows(&mut rows, separation, r_a, r_b);
+            },
+            JointKind::Hinge { axis, limits, motor } => {
+                point_rows(&mut rows, separation, r_a, r_b);
+
+                let axis_a = frame_a.rotation.rotate_vector(axis);
+                let axis_b = (frame_b.rotation * reference.invert()).rotate_vector(axis);
+                let (tangent_1, tangent_2) = perpendiculars(axis_a);
+                let error = axis_a.cross(axis_b);
+                for (i, tangent) in [tangent_1, tangent_2].into_iter().enumerate() {
+                    rows.push(JointRow::angular(ANGULAR_SLOT + i, tangent).with_error(error.dot(tangent)));
+                }
+
+                // flipped axis so the rows measure A turning relative to B
+                let angle = self.twist_angle(frame_a.rotation, frame_b.rotation, axis);
+                if let Some(row) = limit_row(JointRow::angular(LIMIT_SLOT, -axis_a), angle, limits) {
+                    rows.push(row);
+                }
+                if let Some(motor) = motor {
+                    let max_impulse = motor.max_torque * delta_time;
+                    rows.push(
+                        JointRow::angular(MOTOR_SLOT, -axis_a)
+                            .with_bias(-motor.target_speed)
+                            .with_bounds(-max_impulse, max_impulse),
+                    );
+                }
+            },
+            JointKind::ConeTwist { axis, swing, twist } => {
+                point_rows(&mut rows, separation, r_a, r_b);
+
+                // swinging is B's axis tipping away from A's, only pushed back once its past the edge of the cone
+                let axis_a = frame_a.rotation.rotate_vector(axis);
+                let axis_b = (frame_b.rotation * reference.invert()).rotate_vector(axis);
+                let tip = axis_a.cross(axis_b);
+                let angle = tip.magnitude().atan2(axis_a.dot(axis_b));
+                if angle > swing && tip.magnitude2() > 1e-12 {
+                    rows.push(
+                        JointRow::angular(ANGULAR_SLOT, tip.normalize())
+                            .with_error(angle - swing)
+                            .with_bounds(f32::MIN, 0.0),
+                    );
+                }
+
+                let angle = self.twist_angle(frame_a.rotation, frame_b.rotation, axis);
+                if let Some(row) = limit_row(JointRow::angular(LIMIT_SLOT, -axis_a), angle, Some(twist)) {
+                    rows.push(row);
+                }
+            },
+            JointKind::Slider { axis, limits } => {
+                let axis_a = frame_a.rotation.rotate_vector(axis);
+                let (tangent_1, tangent_2) = perpendiculars(axis_a);
+
+                // measured at A's anchor, A turning would move the error too (a separation x t part) but the angular
+                // lock already stops that and leaving it out keeps these rows from fighting the lock when B's anchor is far off
+                for (i, tangent) in [tangent_1, tangent_2].into_iter().enumerate() {
+                    rows.push(
+                        JointRow::linear(LINEAR_SLOT + i, tangent, r_a, r_b - separation)
+                            .with_error(separation.dot(tangent)),
+                    );
+                }
+                angular_lock_rows(&mut rows, rotation_error(frame_a.rotation, frame_b.rotation, reference));
+
+                let row = JointRow::linear(LIMIT_SLOT, axis_a, r_a + separation, r_b);
+                if let Some(row) = limit_row(row, separation.dot(axis_a), limits) {
+                    rows.push(row);
+                }
+            },
+            JointKind::Fixed => {
+                point_rows(&mut rows, separation, r_a, r_b);
+                angular_lock_rows(&mut rows, rotation_error(frame_a.rotation, frame_b.rotation, reference));
+            },
+            JointKind::Spring { rest_length, stiffness, damping } => {
+                let length = separation.magnitude();
+                if length > 0.0001 && delta_time > 0.0 {
+                    // soft constraint, the stiffness and damping turn into a bit of give (softness) plus a bias
+                    let softness = 1.0 / (delta_time * (damping + delta_time * stiffness));
+                    let bias = (length - rest_length) * delta_time * stiffness * softness;
+                    rows.push(
+                        JointRow::linear(LINEAR_SLOT, separation / length, r_a, r_b)
+                            .with_bias(bias)
+                            .with_softness(softness),
+                    );
+                }
+            },
+        }
+
+        // rows that went away this frame (limits that stopped touching) shouldnt warm start next time they show up
+        for slot in 0..JOINT_SLOTS {
+            if !rows.iter().any(|row| row.slot == slot) {
+                self.impulses[slot] = 0.0;
+            }
+        }
+        for row in rows.iter_mut() {
+            row.impulse = self.impulses[row.slot];
+        }
+
+        rows
+    }
+
+    //how far A has turned relative to B around the hinge axis since the joint was made
+    fn twist_angle(&self, rotation_a: Quaternion<f32>, rotation_b: Quaternion<f32>, axis: Vector3<f32>) -> f32 {
+        let reference = self.reference_rotation.unwrap_or(Quaternion::new(1.0, 0.0, 0.0, 0.0));
+        let mut deviation = rotation_a.invert() * rotation_b * reference.invert();
+        if deviation.s < 0.0 {
+            deviation = -deviation;
+        }
+        -2.0 * deviation.v.dot(axis).atan2(deviation.s)
+    }
+}
+
+/// Where a joints body is this step
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JointFrame {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+}
+
+/// One row of a joint, `jacobian` is (linear a, angular a, linear b, angular b)
+/// the solver pushes until jacobian . velocities == -bias, then the position pass moves the bodies until `error` is gone
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct JointRow {
+    pub slot: usize,
+    pub linear_a: Vector3<f32>,
+    pub angular_a: Vector3<f32>,
+    pub linear_b: Vector3<f32>,
+    pub angular_b: Vector3<f32>,
+    pub bias: f32,
+    pub error: Option<f32>, // how far off the row is right now, motors and springs have none and never get moved back
+    pub softness: f32,
+    pub lower: f32,
+    pub upper: f32,
+    pub impulse: f32,
+}
+
+impl JointRow {
+    //keeps the two points from moving apart along `direction`
+    fn linear(slot: usize, direction: Vector3<f32>, r_a: Vector3<f32>, r_b: Vector3<f32>) -> Self {
+        Self {
+            slot,
+            linear_a: -direction,
+            angular_a: -r_a.cross(direction),
+            linear_b: direction,
+            angular_b: r_b.cross(direction),
+            bias: 0.0,
+            error: None,
+            softness: 0.0,
+            lower: f32::MIN,
+            upper: f32::MAX,
+            impulse: 0.0,
+        }
+    }
+
+    //keeps the two bodies from turning relative to each other around `axis`
+    fn angular(slot: usize, axis: Vector3<f32>) -> Self {
+        Self {
+            slot,
+            linear_a: Vector3::zero(),
+            angular_a: -axis,
+            linear_b: Vector3::zero(),
+            angular_b: axis,
+            bias: 0.0,
+            error: None,
+            softness: 0.0,
+            lower: f32::MIN,
+            upper: f32::MAX,
+            impulse: 0.0,
+        }
+    }
+
+    fn with_bias(mut self, bias: f32) -> Self {
+        self.bias = bias;
+        self
+    }
+
+    fn with_error(mut self, error: f32) -> Self {
+        self.error = Some(error);
+        self
+    }
+
+    fn with_softness(mut self, softness: f32) -> Self {
+        self.softness = softness;
+        self
+    }
+
+    fn with_bounds(mut self, lower: f32, upper: f32) -> Self {
+        self.lower = lower;
+        self.upper = upper;
+        self
+    }
+}
+
+//three rows that pin the two anchors together
+fn point_rows(rows: &mut Vec<JointRow>, separation: Vector3<f32>, r_a: Vector3<f32>, r_b: Vector3<f32>) {
+    let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+    for (i, axis) in axes.into_iter().enumerate() {
+        rows.push(JointRow::linear(LINEAR_SLOT + i, axis, r_a, r_b).with_error(separation.dot(axis)));
+    }
+}
+
+//three rows that stop any relative rotation, `error` is how far B has turned away from where it should be
+fn angular_lock_rows(rows: &mut Vec<JointRow>, error: Vector3<f32>) {
+    let axes = [Vector3::unit_x(), Vector3::unit_y(), Vector3::unit_z()];
+    for (i, axis) in axes.into_iter().enumerate() {
+        rows.push(JointRow::angular(ANGULAR_SLOT + i, axis).with_error(error.dot(axis)));
+    }
+}
+
+//a limit only turns into a row once its actually being hit
+fn limit_row(row: JointRow, value: f32, limits: Option<(f32, f32)>) -> Option<JointRow> {
+    let (lower, upper) = limits?;
+    if value <= lower {
+        Some(row.with_error(value - lower).with_bounds(0.0, f32::MAX))
+    } else if value >= upper {
+        Some(row.with_error(value - upper).with_bounds(f32::MIN, 0.0))
+    } else {
+        None
+    }
+}
+
+//small angle rotation (axis * angle, world space) taking B from where the reference says it should be to where it is
+fn rotation_error(rotation_a: Quaternion<f32>, rotation_b: Quaternion<f32>, reference: Quaternion<f32>) -> Vector3<f32> {
+    let mut error = rotation_b * (rotation_a * reference).invert();
+    if error.s < 0.0 {
+        error = -error;
+    }
+    error.v * 2.0
+}
+
+fn perpendiculars(axis: Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    let helper = if axis.x.abs() < 0.57735 {
+        Vector3::unit_x()
+    } else {
+        Vector3::unit_y()
+    };
+    let tangent_1 = axis.cross(helper).normalize();
+    let tangent_2 = axis.cross(tangent_1);
+    (tangent_1, tangent_2)
+}
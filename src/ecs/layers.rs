@@ -0,0 +1,104 @@
+//collision layers as bitmasks, every collider says which layers its in (membership) and which layers it wants to hit (filter)
+//two colliders only collide if each ones membership is in the others filter, so either side can opt out
+//up to 32 layers since its a u32, layer 0 is "default" and everything starts in it
+
+/// Every layer at once, the default filter so things hit everything unless told not to
+pub const ALL_LAYERS: u32 = u32::MAX;
+
+/// The layer colliders are in until they get put somewhere else
+pub const DEFAULT_LAYER: u32 = 1;
+
+/// Bit for layer `index` (0 to 31)
+pub fn layer_bit(index: u32) -> u32 {
+    assert!(index < 32, "only 32 collision layers, got layer {}", index);
+    1 << index
+}
+
+/// Names for layer bits so game code can say "player" instead of remembering which bit that was
+#[derive(Debug, Clone)]
+pub struct CollisionLayers {
+    names: Vec<String>, // index in here is the bit
+}
+
+impl Default for CollisionLayers {
+    fn default() -> Self {
+        Self { names: vec!["default".to_string()] }
+    }
+}
+
+impl CollisionLayers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Gives `name` the next free bit and returns its mask, registering the same name twice just hands back the same mask.
+    /// None once all 32 are used up
+    pub fn register(&mut self, name: &str) -> Option<u32> {
+        if let Some(mask) = self.mask(name) {
+            return Some(mask);
+        }
+        if self.names.len() >= 32 {
+            return None;
+        }
+        self.names.push(name.to_string());
+        Some(layer_bit(self.names.len() as u32 - 1))
+    }
+
+    pub fn mask(&self, name: &str) -> Option<u32> {
+        self.names.iter().position(|n| n == name).map(|index| layer_bit(index as u32))
+    }
+
+    /// All the named layers or'd together, names that were never registered are ignored
+    pub fn mask_of(&self, names: &[&str]) -> u32 {
+        names.iter().filter_map(|name| self.mask(name)).fold(0, |mask, bit| mask | bit)
+    }
+
+    pub fn name(&self, index: u32) -> Option<&str> {
+        self.names.get(index as usize).map(|name| name.as_str())
+    }
+}
+
+/// What a raycast or overlap query is allowed to hit
+#[derive(Debug, Clone, Copy)]
+pub struct QueryFilter {
+    pub mask: u32,               // colliders count if theyre in any of these layers
+    pub include_triggers: bool,
+    pub exclude: Option<u32>,    // usually whoever is asking, so a ray from inside the player doesnt hit the player
+}
+
+impl Default for QueryFilter {
+    fn default() -> Self {
+        Self {
+            mask: ALL_LAYERS,
+            include_triggers: false,
+            exclude: None,
+        }
+    }
+}
+
+impl QueryFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    pub fn with_triggers(mut self) -> Self {
+        self.include_triggers = true;
+        self
+    }
+
+    pub fn excluding(mut self, entity_id: u32) -> Self {
+        self.exclude = Some(entity_id);
+        self
+    }
+
+    pub(crate) fn accepts(&self, entity_id: u32, membership: u32, is_trigger: bool) -> bool {
+        membership & self.mask != 0
+            && (self.include_triggers || !is_trigger)
+            && self.exclude != Some(entity_id)
+    }
+}
@@ -0,0 +1,180 @@
+use std::collections::HashMap;
+
+//what surfaces are made of, how grippy and bouncy they are. Colliders point at a material by name and when two touch
+//their materials get mixed by the combine modes, unless theres an override for that exact pair (ice on rubber, ect)
+//colliders without a material go by their rigidbodies restitution/friction like before, and things with neither get
+//the default material
+
+/// How two materials values get mixed. When both sides want different modes the one further down this list wins
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum CombineMode {
+    Average,
+    Min,
+    Multiply,
+    Max,
+}
+
+impl CombineMode {
+    pub fn combine(self, a: f32, b: f32) -> f32 {
+        match self {
+            CombineMode::Average => (a + b) / 2.0,
+            CombineMode::Min => a.min(b),
+            CombineMode::Multiply => a * b,
+            CombineMode::Max => a.max(b),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PhysicsMaterial {
+    pub static_friction: f32,  // holding still, has to be beaten before anything slides
+    pub dynamic_friction: f32, // once its sliding
+    pub restitution: f32,
+    pub friction_combine: CombineMode,
+    pub restitution_combine: CombineMode,
+}
+
+impl Default for PhysicsMaterial {
+    fn default() -> Self {
+        // what a collider without a rigidbody always used to get
+        Self::new(0.5, 0.5, 0.0)
+    }
+}
+
+impl PhysicsMaterial {
+    pub fn new(static_friction: f32, dynamic_friction: f32, restitution: f32) -> Self {
+        Self {
+            static_friction,
+            dynamic_friction,
+            restitution,
+            friction_combine: CombineMode::Average,
+            restitution_combine: CombineMode::Average,
+        }
+    }
+
+    pub fn with_friction_combine(mut self, mode: CombineMode) -> Self {
+        self.friction_combine = mode;
+        self
+    }
+
+    pub fn with_restitution_combine(mut self, mode: CombineMode) -> Self {
+        self.restitution_combine = mode;
+        self
+    }
+}
+
+/// The mixed values for one touching pair, this is what the solver actually uses
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ContactMaterial {
+    pub static_friction: f32,
+    pub dynamic_friction: f32,
+    pub restitution: f32,
+}
+
+impl Default for ContactMaterial {
+    fn default() -> Self {
+        Self::combine(&PhysicsMaterial::default(), &PhysicsMaterial::default())
+    }
+}
+
+impl ContactMaterial {
+    pub fn new(static_friction: f32, dynamic_friction: f32, restitution: f32) -> Self {
+        Self { static_friction, dynamic_friction, restitution }
+    }
+
+    pub fn combine(a: &PhysicsMaterial, b: &PhysicsMaterial) -> Self {
+        let friction = a.friction_combine.max(b.friction_combine);
+        let restitution = a.restitution_combine.max(b.restitution_combine);
+        Self {
+            static_friction: friction.combine(a.static_friction, b.static_friction),
+            dynamic_friction: friction.combine(a.dynamic_friction, b.dynamic_friction),
+            restitution: restitution.combine(a.restitution, b.restitution),
+        }
+    }
+}
+
+/// A material name squashed down to a number so colliders can keep it without allocating (and stay cheap to copy)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MaterialId(u64);
+
+impl MaterialId {
+    //fnv-1a, the same name always gives the same id on every machine
+    pub fn new(name: &str) -> Self {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in name.bytes() {
+            hash ^= byte as u64;
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        Self(hash)
+    }
+}
+
+/// Every material by name plus the per pair overrides
+#[derive(Debug, Clone, Default)]
+pub struct PhysicsMaterials {
+    materials: HashMap<MaterialId, (String, PhysicsMaterial)>,
+    overrides: HashMap<(MaterialId, MaterialId), ContactMaterial>,
+    pub default_material: PhysicsMaterial,
+}
+
+impl PhysicsMaterials {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds (or replaces) a material, colliders pick it up with `Collider::with_material(name)`
+    pub fn register(&mut self, name: &str, material: PhysicsMaterial) -> MaterialId {
+        let id = MaterialId::new(name);
+        self.materials.insert(id, (name.to_string(), material));
+        id
+    }
+
+    pub fn get(&self, name: &str) -> Option<&PhysicsMaterial> {
+        self.get_by_id(MaterialId::new(name))
+    }
+
+    pub fn get_by_id(&self, id: MaterialId) -> Option<&PhysicsMaterial> {
+        self.materials.get(&id).map(|(_, material)| material)
+    }
+
+    pub fn name(&self, id: MaterialId) -> Option<&str> {
+        self.materials.get(&id).map(|(name, _)| name.as_str())
+    }
+
+    pub fn remove(&mut self, name: &str) -> Option<PhysicsMaterial> {
+        self.materials.remove(&MaterialId::new(name)).map(|(_, material)| material)
+    }
+
+    /// Exact values for when `a` touches `b` (either way round), skips the combine modes completely
+    pub fn set_pair(&mut self, a: &str, b: &str, contact: ContactMaterial) {
+        self.overrides.insert(pair_key(MaterialId::new(a), MaterialId::new(b)), contact);
+    }
+
+    pub fn clear_pair(&mut self, a: &str, b: &str) {
+        self.overrides.remove(&pair_key(MaterialId::new(a), MaterialId::new(b)));
+    }
+
+    /// What two touching colliders end up with. `fallback_a`/`fallback_b` are used for a side whose collider has no
+    /// material (or one that was never registered), usually made from its rigidbody
+    pub fn resolve(
+        &self,
+        material_a: Option<MaterialId>,
+        fallback_a: Option<PhysicsMaterial>,
+        material_b: Option<MaterialId>,
+        fallback_b: Option<PhysicsMaterial>,
+    ) -> ContactMaterial {
+        if let (Some(a), Some(b)) = (material_a, material_b) {
+            if let Some(contact) = self.overrides.get(&pair_key(a, b)) {
+                return *contact;
+            }
+        }
+        let side = |material: Option<MaterialId>, fallback: Option<PhysicsMaterial>| {
+            material.and_then(|id| self.get_by_id(id).copied()).or(fallback).unwrap_or(self.default_material)
+        };
+        ContactMaterial::combine(&side(material_a, fallback_a), &side(material_b, fallback_b))
+    }
+}
+
+fn pair_key(a: MaterialId, b: MaterialId) -> (MaterialId, MaterialId) {
+    (a.min(b), a.max(b))
+}
@@ -3,4 +3,26 @@ pub mod player;
 pub mod components;
 pub mod UI_components;
 pub mod collision_system;
-pub mod physics;
\ No newline at end of file
+pub mod physics;
+pub mod gjk;
+pub mod contact_manifold;
+pub mod solver;
+pub mod joints;
+pub mod islands;
+pub mod ccd;
+pub mod character;
+pub mod layers;
+pub mod queries;
+pub mod aabb;
+pub mod heightfield;
+pub mod snapshot;
+pub mod force_fields;
+pub mod water;
+pub mod vehicle;
+pub mod materials;
+pub mod convex_hull;
+pub mod stats;
+pub mod ragdoll;
+pub mod cloth;
+pub mod particles;
+pub mod navmesh;
\ No newline at end of file
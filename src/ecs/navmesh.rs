@@ -0,0 +1,999 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap, VecDeque};
+
+use cgmath::{InnerSpace, Rotation, Vector3, Zero};
+
+use super::aabb::Aabb;
+use super::collision_system::{CollisionShape, CollisionSystem};
+use super::components::Velocity;
+use super::layers::QueryFilter;
+use super::physics::PhysicsSystem;
+use super::world::{ComponentStorage, MovementSystem};
+use crate::model::transform::WorldCoords;
+
+//the bake goes like recast but a lot dumber: every triangle gets squashed into columns of solid spans on a grid, the
+//tops of walkable spans with enough headroom become floor cells, cells an agent can step between get linked up and
+//anything closer than the agent radius to an edge gets shaved off. Whats left gets merged into rectangles, those are
+//the polygons paths go through. Rectangles follow walls in steps of cell_size so keep it under the agent radius
+//paths are A* over the polygons then pulled tight through the shared edges (the funnel algorithm)
+//everything is in world space with y up, an agents position is where its feet are
+
+const WEST: usize = 0;
+const EAST: usize = 1;
+const NORTH: usize = 2; // -z
+const SOUTH: usize = 3; // +z
+const STEPS: [(i64, i64); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+// big rectangles are fine for the funnel but the height across one is just blended from its corners
+const MAX_POLY_CELLS: usize = 32;
+
+#[derive(Debug, Clone, Copy)]
+pub struct NavMeshSettings {
+    pub cell_size: f32,      // x/z size of a voxel, smaller hugs walls closer but bakes slower
+    pub cell_height: f32,    // y size of a voxel
+    pub agent_radius: f32,
+    pub agent_height: f32,   // places with less headroom than this arent walkable
+    pub max_step: f32,       // tallest ledge an agent walks up without a ramp
+    pub max_slope: f32,      // radians, anything steeper is a wall
+    pub filter: QueryFilter, // which colliders `add_static_colliders` picks up
+}
+
+impl Default for NavMeshSettings {
+    fn default() -> Self {
+        Self {
+            cell_size: 0.2,
+            cell_height: 0.1,
+            agent_radius: 0.4,
+            agent_height: 1.8,
+            max_step: 0.35,
+            max_slope: std::f32::consts::FRAC_PI_4,
+            filter: QueryFilter::new(),
+        }
+    }
+}
+
+impl NavMeshSettings {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_cell_size(mut self, size: f32, height: f32) -> Self {
+        self.cell_size = size;
+        self.cell_height = height;
+        self
+    }
+
+    pub fn with_agent(mut self, radius: f32, height: f32) -> Self {
+        self.agent_radius = radius.max(0.0);
+        self.agent_height = height.max(0.0);
+        self
+    }
+
+    pub fn with_max_step(mut self, step: f32) -> Self {
+        self.max_step = step.max(0.0);
+        self
+    }
+
+    pub fn with_max_slope(mut self, radians: f32) -> Self {
+        self.max_slope = radians.clamp(0.0, std::f32::consts::FRAC_PI_2);
+        self
+    }
+
+    pub fn with_filter(mut self, filter: QueryFilter) -> Self {
+        self.filter = filter;
+        self
+    }
+}
+
+/// Collects level geometry then bakes it with `build`
+pub struct NavMeshBuilder {
+    settings: NavMeshSettings,
+    triangles: Vec<[Vector3<f32>; 3]>,
+}
+
+impl NavMeshBuilder {
+    pub fn new(settings: NavMeshSettings) -> Self {
+        Self { settings, triangles: Vec::new() }
+    }
+
+    /// World space, counter clockwise seen from the side thats walked on
+    pub fn add_triangle(&mut self, a: Vector3<f32>, b: Vector3<f32>, c: Vector3<f32>) {
+        self.triangles.push([a, b, c]);
+    }
+
+    /// A level mesh, same arrays that go into `Mesh::new`: `stride` floats a vertex with the position first, placed by `coords`
+    pub fn add_mesh(&mut self, vertices: &[f32], stride: usize, indices: &[i32], coords: &WorldCoords) -> Result<(), String> {
+        if stride < 3 {
+            return Err(format!("vertex stride has to fit a position, got {}", stride));
+        }
+        if !indices.len().is_multiple_of(3) {
+            return Err(format!("mesh has {} indices, thats not whole triangles", indices.len()));
+        }
+        let vertex_count = vertices.len() / stride;
+        let model = coords.get_model_matrix();
+        let mut corners = [Vector3::zero(); 3];
+        for triangle in indices.chunks(3) {
+            for (corner, &index) in corners.iter_mut().zip(triangle) {
+                let index = usize::try_from(index).ok().filter(|index| *index < vertex_count)
+                    .ok_or(format!("mesh index {} is out of range for {} vertices", index, vertex_count))?;
+                let local = Vector3::new(vertices[index * stride], vertices[index * stride + 1], vertices[index * stride + 2]);
+                *corner = (model * local.extend(1.0)).truncate();
+            }
+            self.triangles.push(corners);
+        }
+        Ok(())
+    }
+
+    /// Every collider that isnt going anywhere, ones with no rigidbody or a static one. Triggers are skipped
+    pub fn add_static_colliders(&mut self, movement_system: &MovementSystem, collision_system: &CollisionSystem, physics_system: &PhysicsSystem) {
+        let everywhere = Aabb::new(Vector3::new(f32::MIN, f32::MIN, f32::MIN), Vector3::new(f32::MAX, f32::MAX, f32::MAX));
+        for (entity_id, position, rotation, shape) in collision_system.parts_in_bounds(movement_system, &everywhere, &self.settings.filter) {
+            if physics_system.get_rigidbody(entity_id).is_some_and(|rigidbody| !rigidbody.is_static()) {
+                continue;
+            }
+            self.triangles.extend(shape_triangles(&shape).into_iter().map(|triangle| triangle.map(|corner| position + rotation.rotate_vector(corner))));
+        }
+    }
+
+    pub fn triangle_count(&self) -> usize {
+        self.triangles.len()
+    }
+
+    pub fn build(&self) -> Result<NavMesh, String> {
+        let settings = self.settings;
+        if settings.cell_size <= 0.0 || settings.cell_height <= 0.0 {
+            return Err(format!("navmesh cells need a size, got {} by {}", settings.cell_size, settings.cell_height));
+        }
+        if self.triangles.is_empty() {
+            return Err("theres nothing to bake a navmesh from".to_string());
+        }
+
+        let mut grid = VoxelGrid::new(&self.triangles, &settings)?;
+        let walkable = settings.max_slope.cos();
+        for triangle in &self.triangles {
+            grid.rasterize(triangle, walkable);
+        }
+        let mut cells = grid.floor_cells();
+        link_cells(&mut cells, &grid);
+        erode(&mut cells, &settings);
+        Ok(build_polygons(&cells, &grid, settings))
+    }
+}
+
+// outward faces wound counter clockwise, in the shapes own space
+fn shape_triangles(shape: &CollisionShape) -> Vec<[Vector3<f32>; 3]> {
+    match shape {
+        CollisionShape::Box { width, height, depth } => box_triangles(Vector3::new(*width, *height, *depth) * 0.5),
+        CollisionShape::OBB { half_extents, .. } => box_triangles(*half_extents),
+        CollisionShape::Sphere { radius } => round_triangles(*radius, 0.0),
+        CollisionShape::Capsule { radius, half_height } => round_triangles(*radius, *half_height),
+        CollisionShape::Heightfield(heightfield) => heightfield.triangles().collect(),
+        CollisionShape::ConvexHull(hull) => hull.faces().iter().map(|face| face.map(|index| hull.vertices()[index])).collect(),
+        // flat 2d shapes dont stand on anything and compounds come in already split up
+        _ => Vec::new(),
+    }
+}
+
+fn box_triangles(half: Vector3<f32>) -> Vec<[Vector3<f32>; 3]> {
+    let (x, y, z) = (Vector3::unit_x() * half.x, Vector3::unit_y() * half.y, Vector3::unit_z() * half.z);
+    // (out, u, v) with u cross v pointing out so the corners go round counter clockwise
+    let faces = [(x, y, z), (-x, z, y), (y, z, x), (-y, x, z), (z, x, y), (-z, y, x)];
+    faces.iter().flat_map(|&(out, u, v)| {
+        let corners = [out - u - v, out + u - v, out + u + v, out - u + v];
+        [[corners[0], corners[1], corners[2]], [corners[0], corners[2], corners[3]]]
+    }).collect()
+}
+
+// a coarse lat-long sphere, for capsules the top half goes up by half_height and the bottom half down
+fn round_triangles(radius: f32, half_height: f32) -> Vec<[Vector3<f32>; 3]> {
+    const SEGMENTS: usize = 8;
+    let mut rings = Vec::new();
+    for i in 0..=SEGMENTS {
+        let angle = i as f32 / SEGMENTS as f32 * std::f32::consts::PI;
+        if i <= SEGMENTS / 2 {
+            rings.push((angle, half_height));
+        }
+        if i >= SEGMENTS / 2 {
+            rings.push((angle, -half_height));
+        }
+    }
+    let point = |(angle, offset): (f32, f32), j: usize| {
+        let around = j as f32 / SEGMENTS as f32 * std::f32::consts::TAU;
+        Vector3::new(angle.sin() * around.cos(), angle.cos(), angle.sin() * around.sin()) * radius + Vector3::new(0.0, offset, 0.0)
+    };
+    let mut triangles = Vec::new();
+    for pair in rings.windows(2) {
+        for j in 0..SEGMENTS {
+            let (a, b) = (point(pair[0], j), point(pair[0], j + 1));
+            let (c, d) = (point(pair[1], j), point(pair[1], j + 1));
+            triangles.push([a, b, c]);
+            triangles.push([b, d, c]);
+        }
+    }
+    triangles
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Span {
+    min: i32,
+    max: i32,
+    top: f32,       // the exact height of the highest surface, max is that rounded up to a cell
+    walkable: bool, // goes by whatever surface is on top
+}
+
+struct VoxelGrid {
+    origin: Vector3<f32>,
+    width: usize,
+    depth: usize,
+    cell_size: f32,
+    cell_height: f32,
+    climb: i32,  // max_step in cells
+    height: i32, // agent_height in cells
+    columns: Vec<Vec<Span>>, // z major, sorted bottom to top and never overlapping
+}
+
+impl VoxelGrid {
+    fn new(triangles: &[[Vector3<f32>; 3]], settings: &NavMeshSettings) -> Result<Self, String> {
+        let corners = triangles.iter().flatten();
+        let min = corners.clone().fold(Vector3::new(f32::MAX, f32::MAX, f32::MAX), |min, corner| Vector3::new(min.x.min(corner.x), min.y.min(corner.y), min.z.min(corner.z)));
+        let max = corners.fold(Vector3::new(f32::MIN, f32::MIN, f32::MIN), |max, corner| Vector3::new(max.x.max(corner.x), max.y.max(corner.y), max.z.max(corner.z)));
+        let width = (((max.x - min.x) / settings.cell_size).ceil() as usize).max(1);
+        let depth = (((max.z - min.z) / settings.cell_size).ceil() as usize).max(1);
+        if width.saturating_mul(depth) > 4_000_000 {
+            return Err(format!("navmesh grid would be {}x{} cells, use a bigger cell_size", width, depth));
+        }
+        Ok(Self {
+            origin: min,
+            width,
+            depth,
+            cell_size: settings.cell_size,
+            cell_height: settings.cell_height,
+            climb: (settings.max_step / settings.cell_height).floor() as i32,
+            height: (settings.agent_height / settings.cell_height).ceil() as i32,
+            columns: vec![Vec::new(); width * depth],
+        })
+    }
+
+    // clips the triangle to every cell it covers and adds the height range thats left as a span
+    fn rasterize(&mut self, triangle: &[Vector3<f32>; 3], walkable_cos: f32) {
+        let normal = (triangle[1] - triangle[0]).cross(triangle[2] - triangle[0]);
+        let area = normal.magnitude();
+        if area < 1e-9 {
+            return;
+        }
+        let walkable = normal.y / area >= walkable_cos;
+
+        let cell = |value: f32, origin: f32, count: usize| (((value - origin) / self.cell_size).floor().max(0.0) as usize).min(count - 1);
+        let (low_x, high_x) = triangle.iter().fold((f32::MAX, f32::MIN), |(low, high), corner| (low.min(corner.x), high.max(corner.x)));
+        let (low_z, high_z) = triangle.iter().fold((f32::MAX, f32::MIN), |(low, high), corner| (low.min(corner.z), high.max(corner.z)));
+        for z in cell(low_z, self.origin.z, self.depth)..=cell(high_z, self.origin.z, self.depth) {
+            let row_start = self.origin.z + z as f32 * self.cell_size;
+            let row = clip(&clip(triangle, 2, row_start, true), 2, row_start + self.cell_size, false);
+            if row.len() < 3 {
+                continue;
+            }
+            for x in cell(low_x, self.origin.x, self.width)..=cell(high_x, self.origin.x, self.width) {
+                let column_start = self.origin.x + x as f32 * self.cell_size;
+                let piece = clip(&clip(&row, 0, column_start, true), 0, column_start + self.cell_size, false);
+                if piece.len() < 3 {
+                    continue;
+                }
+                let (low_y, high_y) = piece.iter().fold((f32::MAX, f32::MIN), |(low, high), corner| (low.min(corner.y), high.max(corner.y)));
+                // the nudges stop a floor sitting right on a cell boundary rounding up a whole cell
+                let min = ((low_y - self.origin.y) / self.cell_height + 1e-3).floor() as i32;
+                let max = (((high_y - self.origin.y) / self.cell_height - 1e-3).ceil() as i32).max(min);
+                let climb = self.climb;
+                add_span(&mut self.columns[z * self.width + x], Span { min, max, top: high_y, walkable }, climb);
+            }
+        }
+    }
+
+    // tops of walkable spans with room to stand
+    fn floor_cells(&self) -> Vec<FloorCell> {
+        let mut cells = Vec::new();
+        for z in 0..self.depth {
+            for x in 0..self.width {
+                let spans = &self.columns[z * self.width + x];
+                for (i, span) in spans.iter().enumerate() {
+                    let ceiling = spans.get(i + 1).map_or(i32::MAX, |above| above.min);
+                    if span.walkable && ceiling.saturating_sub(span.max) >= self.height {
+                        cells.push(FloorCell { x, z, floor: span.max, surface: span.top, ceiling, links: [None; 4], alive: true });
+                    }
+                }
+            }
+        }
+        cells
+    }
+}
+
+// keeps the side of the polygon at or above (or below) `value` along `axis`, one side of sutherland hodgman
+fn clip(polygon: &[Vector3<f32>], axis: usize, value: f32, keep_above: bool) -> Vec<Vector3<f32>> {
+    let inside = |point: &Vector3<f32>| if keep_above { point[axis] >= value } else { point[axis] <= value };
+    let mut clipped = Vec::with_capacity(polygon.len() + 1);
+    for (i, current) in polygon.iter().enumerate() {
+        let next = &polygon[(i + 1) % polygon.len()];
+        if inside(current) {
+            clipped.push(*current);
+        }
+        if inside(current) != inside(next) {
+            let t = (value - current[axis]) / (next[axis] - current[axis]);
+            clipped.push(current + (next - current) * t);
+        }
+    }
+    clipped
+}
+
+// overlapping spans become one, if their tops are within a step of each other either being walkable is enough
+fn add_span(spans: &mut Vec<Span>, mut span: Span, climb: i32) {
+    let mut i = 0;
+    while i < spans.len() {
+        let other = spans[i];
+        if other.min > span.max {
+            break;
+        }
+        if other.max < span.min {
+            i += 1;
+            continue;
+        }
+        if (other.max - span.max).abs() <= climb {
+            span.walkable |= other.walkable;
+        } else if other.max > span.max {
+            span.walkable = other.walkable;
+        }
+        span.min = span.min.min(other.min);
+        span.max = span.max.max(other.max);
+        span.top = span.top.max(other.top);
+        spans.remove(i);
+    }
+    spans.insert(i, span);
+}
+
+#[derive(Debug, Clone, Copy)]
+struct FloorCell {
+    x: usize,
+    z: usize,
+    floor: i32,
+    surface: f32,
+    ceiling: i32,
+    links: [Option<usize>; 4], // the cell walked onto going WEST, EAST, NORTH, SOUTH
+    alive: bool,
+}
+
+fn link_cells(cells: &mut [FloorCell], grid: &VoxelGrid) {
+    let mut by_column: HashMap<(usize, usize), Vec<usize>> = HashMap::new();
+    for (index, cell) in cells.iter().enumerate() {
+        by_column.entry((cell.x, cell.z)).or_default().push(index);
+    }
+    for index in 0..cells.len() {
+        let cell = cells[index];
+        for (direction, (step_x, step_z)) in STEPS.iter().enumerate() {
+            let (x, z) = (cell.x as i64 + step_x, cell.z as i64 + step_z);
+            if x < 0 || z < 0 {
+                continue;
+            }
+            let Some(candidates) = by_column.get(&(x as usize, z as usize)) else { continue };
+            // the closest floor in height that can be stepped onto without hitting your head on the way
+            cells[index].links[direction] = candidates.iter().copied()
+                .filter(|&other| {
+                    let other = &cells[other];
+                    (other.floor - cell.floor).abs() <= grid.climb
+                        && other.ceiling.min(cell.ceiling).saturating_sub(other.floor.max(cell.floor)) >= grid.height
+                })
+                .min_by_key(|&other| (cells[other].floor - cell.floor).abs());
+        }
+    }
+}
+
+// shaves off every cell closer than the agent radius to an edge, a drop or a wall
+fn erode(cells: &mut [FloorCell], settings: &NavMeshSettings) {
+    if settings.agent_radius <= 0.0 {
+        return;
+    }
+    // how many cells in from the edge each cell is, 1 for the ones on it
+    let mut distance = vec![u32::MAX; cells.len()];
+    let mut queue = VecDeque::new();
+    for (index, cell) in cells.iter().enumerate() {
+        if cell.links.iter().any(|link| link.is_none()) {
+            distance[index] = 1;
+            queue.push_back(index);
+        }
+    }
+    while let Some(index) = queue.pop_front() {
+        for next in cells[index].links.iter().flatten().copied() {
+            if distance[next] > distance[index] + 1 {
+                distance[next] = distance[index] + 1;
+                queue.push_back(next);
+            }
+        }
+    }
+
+    for (cell, distance) in cells.iter_mut().zip(&distance) {
+        // the middle of the cell is half a cell further in than its count
+        cell.alive = (*distance as f32 - 0.5) * settings.cell_size >= settings.agent_radius - 1e-4;
+    }
+    let alive: Vec<bool> = cells.iter().map(|cell| cell.alive).collect();
+    for cell in cells.iter_mut() {
+        for link in &mut cell.links {
+            *link = link.filter(|&other| alive[other]);
+        }
+    }
+}
+
+// greedy rectangles, grab the first free cell then go as far along +x as possible and then as many rows down +z as fit
+fn build_polygons(cells: &[FloorCell], grid: &VoxelGrid, settings: NavMeshSettings) -> NavMesh {
+    let mut owner: Vec<Option<usize>> = vec![None; cells.len()];
+    let mut rectangles: Vec<Vec<Vec<usize>>> = Vec::new();
+
+    for start in 0..cells.len() {
+        if !cells[start].alive || owner[start].is_some() {
+            continue;
+        }
+        // a rectangle only takes cells that keep to one slope, a step in the middle would get blended into a ramp.
+        let even = |from: usize, to: usize, rise: Option<f32>| rise.is_none_or(|rise| (cells[to].surface - cells[from].surface - rise).abs() <= grid.cell_height / 2.0);
+        let free = |index: usize, owner: &[Option<usize>]| owner[index].is_none();
+
+        let mut row = vec![start];
+        while row.len() < MAX_POLY_CELLS {
+            let last = *row.last().unwrap();
+            let rise = (row.len() > 1).then(|| cells[row[1]].surface - cells[row[0]].surface);
+            match cells[last].links[EAST] {
+                Some(next) if free(next, &owner) && even(last, next, rise) => row.push(next),
+                _ => break,
+            }
+        }
+        let mut rows = vec![row];
+        while rows.len() < MAX_POLY_CELLS {
+            let previous = rows.last().unwrap();
+            let rise = (rows.len() > 1).then(|| cells[rows[1][0]].surface - cells[rows[0][0]].surface);
+            let next: Option<Vec<usize>> = previous.iter()
+                .map(|&index| cells[index].links[SOUTH].filter(|&below| free(below, &owner) && even(index, below, rise)))
+                .collect();
+            // the new row has to be joined up along x too or it could be bits of two different floors
+            match next {
+                Some(next) if next.windows(2).all(|pair| cells[pair[0]].links[EAST] == Some(pair[1])) => rows.push(next),
+                _ => break,
+            }
+        }
+        for index in rows.iter().flatten() {
+            owner[*index] = Some(rectangles.len());
+        }
+        rectangles.push(rows);
+    }
+
+    let mut polygons: Vec<NavPoly> = rectangles.iter().map(|rows| {
+        let first = &cells[rows[0][0]];
+        let (columns, depth) = (rows[0].len(), rows.len());
+        let corner = |row: &Vec<usize>, end: bool, x: usize, z: usize| {
+            let cell = &cells[if end { *row.last().unwrap() } else { row[0] }];
+            Vector3::new(grid.origin.x + x as f32 * grid.cell_size, cell.surface, grid.origin.z + z as f32 * grid.cell_size)
+        };
+        let (x0, z0) = (first.x, first.z);
+        let (x1, z1) = (x0 + columns, z0 + depth);
+        let (top, bottom) = (&rows[0], rows.last().unwrap());
+        // counter clockwise seen from above
+        let vertices = [corner(top, false, x0, z0), corner(bottom, false, x0, z1), corner(bottom, true, x1, z1), corner(top, true, x1, z0)];
+        let center = vertices.iter().fold(Vector3::zero(), |sum, vertex| sum + vertex) / 4.0;
+        NavPoly { vertices, center, links: Vec::new() }
+    }).collect();
+
+    // walk round the edge of every rectangle and see whose cells are on the other side
+    for (poly, rows) in rectangles.iter().enumerate() {
+        let west: Vec<usize> = rows.iter().map(|row| row[0]).collect();
+        let east: Vec<usize> = rows.iter().map(|row| *row.last().unwrap()).collect();
+        let sides = [(WEST, west), (EAST, east), (NORTH, rows[0].clone()), (SOUTH, rows.last().unwrap().clone())];
+        let mut shared: Vec<(usize, Vector3<f32>, Vector3<f32>)> = Vec::new();
+        for (direction, edge) in sides {
+            // neighbour poly -> the cell edges between them as (start, end) points
+            let mut touching: Vec<(usize, Vector3<f32>, Vector3<f32>)> = Vec::new();
+            for index in edge {
+                let Some(other) = cells[index].links[direction] else { continue };
+                let Some(other_poly) = owner[other] else { continue };
+                if other_poly == poly {
+                    continue;
+                }
+                let (start, end) = cell_edge(&cells[index], &cells[other], direction, grid);
+                match touching.iter_mut().find(|(neighbour, ..)| *neighbour == other_poly) {
+                    Some(portal) => portal.2 = end,
+                    None => touching.push((other_poly, start, end)),
+                }
+            }
+            shared.extend(touching);
+        }
+        polygons[poly].links = shared.into_iter().map(|(poly, a, b)| NavLink { poly, a, b }).collect();
+    }
+
+    NavMesh { polygons, settings }
+}
+
+// the side of `cell` facing `direction`, at the height halfway between it and the cell across, ordered along +x or +z
+fn cell_edge(cell: &FloorCell, other: &FloorCell, direction: usize, grid: &VoxelGrid) -> (Vector3<f32>, Vector3<f32>) {
+    let y = (cell.surface + other.surface) / 2.0;
+    let (x0, z0) = (grid.origin.x + cell.x as f32 * grid.cell_size, grid.origin.z + cell.z as f32 * grid.cell_size);
+    let (x1, z1) = (x0 + grid.cell_size, z0 + grid.cell_size);
+    match direction {
+        WEST => (Vector3::new(x0, y, z0), Vector3::new(x0, y, z1)),
+        EAST => (Vector3::new(x1, y, z0), Vector3::new(x1, y, z1)),
+        NORTH => (Vector3::new(x0, y, z0), Vector3::new(x1, y, z0)),
+        _ => (Vector3::new(x0, y, z1), Vector3::new(x1, y, z1)),
+    }
+}
+
+/// A shared edge to walk through into `poly`
+#[derive(Debug, Clone, Copy)]
+pub struct NavLink {
+    pub poly: usize,
+    pub a: Vector3<f32>,
+    pub b: Vector3<f32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct NavPoly {
+    pub vertices: [Vector3<f32>; 4], // a rectangle in x/z, counter clockwise seen from above
+    pub center: Vector3<f32>,
+    pub links: Vec<NavLink>,
+}
+
+impl NavPoly {
+    fn contains(&self, x: f32, z: f32) -> bool {
+        let (min, max) = (self.vertices[0], self.vertices[2]);
+        x >= min.x && x <= max.x && z >= min.z && z <= max.z
+    }
+
+    /// The closest point on the polygon, its height blended from the corners
+    pub fn closest_point(&self, point: Vector3<f32>) -> Vector3<f32> {
+        let (min, max) = (self.vertices[0], self.vertices[2]);
+        let x = point.x.clamp(min.x, max.x);
+        let z = point.z.clamp(min.z, max.z);
+        let u = if max.x > min.x { (x - min.x) / (max.x - min.x) } else { 0.0 };
+        let v = if max.z > min.z { (z - min.z) / (max.z - min.z) } else { 0.0 };
+        let [top_left, bottom_left, bottom_right, top_right] = self.vertices.map(|vertex| vertex.y);
+        let top = top_left + (top_right - top_left) * u;
+        let bottom = bottom_left + (bottom_right - bottom_left) * u;
+        Vector3::new(x, top + (bottom - top) * v, z)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct NavMesh {
+    polygons: Vec<NavPoly>,
+    settings: NavMeshSettings,
+}
+
+impl NavMesh {
+    pub fn polygons(&self) -> &[NavPoly] {
+        &self.polygons
+    }
+
+    pub fn settings(&self) -> &NavMeshSettings {
+        &self.settings
+    }
+
+    /// Which polygon `point` is standing on (or nearest to) and the closest spot on it
+    pub fn closest_point(&self, point: Vector3<f32>) -> Option<(usize, Vector3<f32>)> {
+        // a floor a little above the feet is the one being stood on over one far below
+        let distance = |poly: &NavPoly| {
+            let closest = poly.closest_point(point);
+            let below = if closest.y > point.y + self.settings.max_step { (closest.y - point.y) * 2.0 } else { 0.0 };
+            (closest - point).magnitude() + below
+        };
+        self.polygons.iter().enumerate()
+            .map(|(index, poly)| (index, distance(poly), poly.closest_point(point)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _, closest)| (index, closest))
+    }
+
+    /// Whether `point` is over some polygon within a step of its surface
+    pub fn is_walkable(&self, point: Vector3<f32>) -> bool {
+        self.polygons.iter().any(|poly| poly.contains(point.x, point.z) && (poly.closest_point(point).y - point.y).abs() <= self.settings.max_step)
+    }
+
+    /// Corners of the shortest way from `start` to `end` along the mesh, both ends snapped onto it first. None if
+    /// theres no way between them
+    pub fn find_path(&self, start: Vector3<f32>, end: Vector3<f32>) -> Option<Vec<Vector3<f32>>> {
+        let (start_poly, start) = self.closest_point(start)?;
+        let (end_poly, end) = self.closest_point(end)?;
+        let corridor = self.corridor(start_poly, end_poly, start, end)?;
+        Some(self.string_pull(&corridor, start, end))
+    }
+
+    // A* over the polygons, every step goes through the middle of the edge it crosses
+    fn corridor(&self, start_poly: usize, end_poly: usize, start: Vector3<f32>, end: Vector3<f32>) -> Option<Vec<(usize, Option<NavLink>)>> {
+        let mut cost = vec![f32::INFINITY; self.polygons.len()];
+        let mut entry = vec![start; self.polygons.len()];
+        let mut came_from: Vec<Option<(usize, NavLink)>> = vec![None; self.polygons.len()];
+        let mut open = BinaryHeap::new();
+        cost[start_poly] = 0.0;
+        open.push(Open { estimate: (end - start).magnitude(), poly: start_poly });
+
+        while let Some(Open { estimate, poly }) = open.pop() {
+            if poly == end_poly {
+                break;
+            }
+            if estimate > cost[poly] + (end - entry[poly]).magnitude() + 1e-4 {
+                continue; // already got here a cheaper way
+            }
+            for link in &self.polygons[poly].links {
+                let through = (link.a + link.b) / 2.0;
+                let next_cost = cost[poly] + (through - entry[poly]).magnitude();
+                if next_cost < cost[link.poly] {
+                    cost[link.poly] = next_cost;
+                    entry[link.poly] = through;
+                    came_from[link.poly] = Some((poly, *link));
+                    open.push(Open { estimate: next_cost + (end - through).magnitude(), poly: link.poly });
+                }
+            }
+        }
+        if !cost[end_poly].is_finite() {
+            return None;
+        }
+
+        let mut corridor = vec![(end_poly, None)];
+        let mut poly = end_poly;
+        while let Some((previous, link)) = came_from[poly] {
+            corridor.push((previous, Some(link)));
+            poly = previous;
+        }
+        corridor.reverse();
+        Some(corridor)
+    }
+
+    // the simple stupid funnel algorithm, keeps narrowing a wedge from the last corner through each edge and adds a
+    // corner whenever one side crosses over the other
+    fn string_pull(&self, corridor: &[(usize, Option<NavLink>)], start: Vector3<f32>, end: Vector3<f32>) -> Vec<Vector3<f32>> {
+        let mut portals = vec![(start, start)];
+        for (poly, link) in corridor {
+            let Some(link) = link else { continue };
+            let from = self.polygons[*poly].center;
+            // (left, right) as seen walking through it
+            if cross(from, link.a, link.b) < 0.0 {
+                portals.push((link.b, link.a));
+            } else {
+                portals.push((link.a, link.b));
+            }
+        }
+        portals.push((end, end));
+
+        let mut path = vec![start];
+        let (mut apex, mut left, mut right) = (start, start, start);
+        let (mut left_index, mut right_index) = (0, 0);
+        let mut i = 1;
+        while i < portals.len() {
+            let (next_left, next_right) = portals[i];
+            if cross(apex, right, next_right) <= 0.0 {
+                if same_spot(apex, right) || cross(apex, left, next_right) > 0.0 {
+                    right = next_right;
+                    right_index = i;
+                } else {
+                    // the right side went past the left, the left corner is on the path
+                    path.push(left);
+                    apex = left;
+                    (right, right_index) = (apex, left_index);
+                    i = left_index + 1;
+                    continue;
+                }
+            }
+            if cross(apex, left, next_left) >= 0.0 {
+                if same_spot(apex, left) || cross(apex, right, next_left) < 0.0 {
+                    left = next_left;
+                    left_index = i;
+                } else {
+                    path.push(right);
+                    apex = right;
+                    (left, left_index) = (apex, right_index);
+                    i = right_index + 1;
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        if !path.last().is_some_and(|last| same_spot(*last, end)) {
+            path.push(end);
+        }
+        path
+    }
+}
+
+// twice the signed area of the triangle in x/z, which side of apex->a that b is on
+fn cross(apex: Vector3<f32>, a: Vector3<f32>, b: Vector3<f32>) -> f32 {
+    (b.x - apex.x) * (a.z - apex.z) - (a.x - apex.x) * (b.z - apex.z)
+}
+
+fn same_spot(a: Vector3<f32>, b: Vector3<f32>) -> bool {
+    (a - b).magnitude2() < 1e-8
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Open {
+    estimate: f32,
+    poly: usize,
+}
+
+impl PartialEq for Open {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Open {}
+
+impl PartialOrd for Open {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Open {
+    // flipped so the heap pops the cheapest first, ties go to the lower poly so paths come out the same every run
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.estimate.total_cmp(&self.estimate).then_with(|| other.poly.cmp(&self.poly))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NavStatus {
+    Idle,
+    Moving,
+    Arrived,
+    NoPath, // the destination couldnt be reached from where it was
+}
+
+/// Walks an entity to a destination over the navmesh by setting its `Velocity` each update. Meant for entities
+/// moved by the movement system or a kinematic body, a dynamic body would fight it with gravity
+#[derive(Debug, Clone)]
+pub struct NavAgent {
+    pub speed: f32,
+    pub stopping_distance: f32, // how close counts as there
+    destination: Option<Vector3<f32>>,
+    path: Vec<Vector3<f32>>,
+    next: usize,
+    status: NavStatus,
+}
+
+impl NavAgent {
+    pub fn new(speed: f32) -> Self {
+        Self { speed, stopping_distance: 0.1, destination: None, path: Vec::new(), next: 0, status: NavStatus::Idle }
+    }
+
+    pub fn with_stopping_distance(mut self, distance: f32) -> Self {
+        self.stopping_distance = distance.max(0.0);
+        self
+    }
+
+    /// Plans a new path on the next update
+    pub fn set_destination(&mut self, destination: Vector3<f32>) {
+        self.destination = Some(destination);
+        self.path.clear();
+        self.next = 0;
+        self.status = NavStatus::Moving;
+    }
+
+    pub fn stop(&mut self) {
+        self.destination = None;
+        self.path.clear();
+        self.status = NavStatus::Idle;
+    }
+
+    pub fn destination(&self) -> Option<Vector3<f32>> {
+        self.destination
+    }
+
+    pub fn status(&self) -> NavStatus {
+        self.status
+    }
+
+    /// The corners still to go through, the last one is the destination on the mesh
+    pub fn remaining_path(&self) -> &[Vector3<f32>] {
+        &self.path[self.next.min(self.path.len())..]
+    }
+
+    // the velocity to have this step, planning first if theres no path yet
+    fn steer(&mut self, navmesh: Option<&NavMesh>, position: Vector3<f32>, delta_time: f32) -> Vector3<f32> {
+        if self.status != NavStatus::Moving {
+            return Vector3::zero();
+        }
+        let Some(destination) = self.destination else { return Vector3::zero() };
+        if self.path.is_empty() {
+            match navmesh.and_then(|navmesh| navmesh.find_path(position, destination)) {
+                Some(path) => {
+                    self.path = path;
+                    self.next = 1; // the first one is where it already is
+                },
+                None => {
+                    self.status = NavStatus::NoPath;
+                    return Vector3::zero();
+                },
+            }
+        }
+
+        // corners go by once the feet are basically on them, only the last one uses the stopping distance
+        while self.next < self.path.len() {
+            let last = self.next + 1 == self.path.len();
+            let reach = if last { self.stopping_distance } else { 0.05 };
+            if (self.path[self.next] - position).magnitude() > reach.max(1e-3) {
+                break;
+            }
+            self.next += 1;
+        }
+        let Some(target) = self.path.get(self.next) else {
+            self.status = NavStatus::Arrived;
+            return Vector3::zero();
+        };
+        let offset = target - position;
+        let distance = offset.magnitude();
+        // dont overshoot the corner in one step
+        offset / distance * self.speed.min(distance / delta_time)
+    }
+}
+
+/// Holds the baked navmesh and every agent walking on it
+pub struct NavigationSystem {
+    navmesh: Option<NavMesh>,
+    agents: ComponentStorage<NavAgent>,
+}
+
+impl Default for NavigationSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NavigationSystem {
+    pub fn new() -> Self {
+        Self {
+            navmesh: None,
+            agents: ComponentStorage::new(),
+        }
+    }
+
+    /// Swaps the mesh, every agent thats walking plans again on the next update
+    pub fn set_navmesh(&mut self, navmesh: NavMesh) {
+        self.navmesh = Some(navmesh);
+        for (_, agent) in self.agents.iter_mut() {
+            if let Some(destination) = agent.destination.filter(|_| agent.status != NavStatus::Idle) {
+                agent.set_destination(destination);
+            }
+        }
+    }
+
+    pub fn navmesh(&self) -> Option<&NavMesh> {
+        self.navmesh.as_ref()
+    }
+
+    pub fn add_agent(&mut self, entity_id: u32, agent: NavAgent) {
+        self.agents.insert(entity_id, agent);
+    }
+
+    pub fn get_agent(&self, entity_id: u32) -> Option<&NavAgent> {
+        self.agents.get(entity_id)
+    }
+
+    pub fn get_agent_mut(&mut self, entity_id: u32) -> Option<&mut NavAgent> {
+        self.agents.get_mut(entity_id)
+    }
+
+    pub fn remove_agent(&mut self, entity_id: u32) {
+        self.agents.remove(entity_id);
+    }
+
+    /// Sets every agents velocity towards its next corner, goes before the movement update that moves them
+    pub fn update(&mut self, movement_system: &mut MovementSystem, delta_time: f32) {
+        if delta_time <= 0.0 {
+            return;
+        }
+        for entity_id in self.agents.sorted_ids() {
+            let Some(position) = movement_system.get_coords(entity_id).map(|coords| coords.position) else { continue };
+            let Some(agent) = self.agents.get_mut(entity_id) else { continue };
+            if agent.status == NavStatus::Idle {
+                continue;
+            }
+            let velocity = agent.steer(self.navmesh.as_ref(), position, delta_time);
+            match movement_system.get_velocity_mut(entity_id) {
+                Some(current) => current.set_linear(velocity),
+                None => {
+                    let mut current = Velocity { direction: Vector3::zero(), speed: 0.0 };
+                    current.set_linear(velocity);
+                    movement_system.add_velocity(entity_id, current);
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::collision_system::Collider;
+
+    fn level(walls: &[(Vector3<f32>, Vector3<f32>)]) -> (MovementSystem, CollisionSystem, PhysicsSystem) {
+        let mut movement = MovementSystem::new();
+        let mut collision = CollisionSystem::new();
+        // a 10x10 floor with its top at y = 0
+        movement.add_coords(1, WorldCoords::new(0.0, -0.5, 0.0, 0.0));
+        collision.add_collider(1, Collider::bounding_box(10.0, 1.0, 10.0));
+        for (i, (center, size)) in walls.iter().enumerate() {
+            let entity_id = i as u32 + 2;
+            movement.add_coords(entity_id, WorldCoords::new(center.x, center.y, center.z, 0.0));
+            collision.add_collider(entity_id, Collider::bounding_box(size.x, size.y, size.z));
+        }
+        (movement, collision, PhysicsSystem::new())
+    }
+
+    fn bake(walls: &[(Vector3<f32>, Vector3<f32>)]) -> NavMesh {
+        let (movement, collision, physics) = level(walls);
+        let mut builder = NavMeshBuilder::new(NavMeshSettings::new());
+        builder.add_static_colliders(&movement, &collision, &physics);
+        builder.build().unwrap()
+    }
+
+    #[test]
+    fn path_goes_round_a_wall_and_keeps_its_distance() {
+        // a wall across the middle with a gap at +z
+        let navmesh = bake(&[(Vector3::new(0.0, 1.0, -1.0), Vector3::new(0.5, 2.0, 8.0))]);
+        let (start, end) = (Vector3::new(-3.0, 0.0, -2.0), Vector3::new(3.0, 0.0, -2.0));
+        let path = navmesh.find_path(start, end).expect("theres a way round the wall");
+
+        assert!((path[0] - start).magnitude() < 1e-3 && (path.last().unwrap() - end).magnitude() < 1e-3);
+        // pulled tight it only needs the corners round the end of the wall, the shaved edge is a staircase so theres a few
+        assert!(path.len() <= 8, "path has {} corners: {:?}", path.len(), path);
+        let length: f32 = path.windows(2).map(|pair| (pair[1] - pair[0]).magnitude()).sum();
+        assert!(length < 14.0, "path is {} long", length);
+        for pair in path.windows(2) {
+            for step in 0..=20 {
+                let point = pair[0] + (pair[1] - pair[0]) * (step as f32 / 20.0);
+                let outside_x = (point.x.abs() - 0.25).max(0.0);
+                let outside_z = (point.z - 3.0).max(-5.0 - point.z).max(0.0);
+                let clearance = (outside_x * outside_x + outside_z * outside_z).sqrt();
+                assert!(clearance >= 0.4 - 0.05, "path gets {} from the wall at {:?}", clearance, point);
+                assert!(point.y.abs() < 0.15);
+            }
+        }
+    }
+
+    #[test]
+    fn steps_and_ledges() {
+        // a low step you can walk up and a tall block you cant
+        let navmesh = bake(&[
+            (Vector3::new(-2.5, 0.1, 0.0), Vector3::new(3.0, 0.2, 3.0)),
+            (Vector3::new(2.5, 0.5, 0.0), Vector3::new(3.0, 1.0, 3.0)),
+        ]);
+        let on_step = Vector3::new(-2.5, 0.2, 0.0);
+        let path = navmesh.find_path(Vector3::new(0.0, 0.0, 4.0), on_step).unwrap();
+        assert!((path.last().unwrap() - on_step).magnitude() < 0.1);
+        assert!(navmesh.is_walkable(on_step));
+
+        // the top of the tall block is walkable but cut off, and you cant path from the floor onto it
+        let on_block = Vector3::new(2.5, 1.0, 0.0);
+        assert!(navmesh.is_walkable(on_block));
+        assert!(navmesh.find_path(Vector3::new(0.0, 0.0, 4.0), on_block).is_none());
+    }
+
+    #[test]
+    fn agent_walks_to_its_destination() {
+        let (mut movement, collision, physics) = level(&[(Vector3::new(0.0, 1.0, -1.0), Vector3::new(0.5, 2.0, 8.0))]);
+        let mut builder = NavMeshBuilder::new(NavMeshSettings::new());
+        builder.add_static_colliders(&movement, &collision, &physics);
+        let mut navigation = NavigationSystem::new();
+        navigation.set_navmesh(builder.build().unwrap());
+
+        movement.add_coords(10, WorldCoords::new(-3.0, 0.0, -2.0, 0.0));
+        let mut agent = NavAgent::new(3.0);
+        agent.set_destination(Vector3::new(3.0, 0.0, -2.0));
+        navigation.add_agent(10, agent);
+
+        let step = 1.0 / 60.0;
+        for _ in 0..600 {
+            navigation.update(&mut movement, step);
+            movement.update(step);
+        }
+        let agent = navigation.get_agent(10).unwrap();
+        assert_eq!(agent.status(), NavStatus::Arrived);
+        let position = movement.get_coords(10).unwrap().position;
+        assert!((position - Vector3::new(3.0, 0.0, -2.0)).magnitude() <= 0.11, "agent stopped at {:?}", position);
+        assert_eq!(movement.get_velocity(10).unwrap().speed, 0.0);
+
+        // somewhere it cant get to
+        navigation.get_agent_mut(10).unwrap().set_destination(Vector3::new(0.0, 10.0, 20.0));
+        navigation.update(&mut movement, step);
+        assert_ne!(navigation.get_agent(10).unwrap().status(), NavStatus::NoPath, "far away points snap onto the mesh");
+    }
+}
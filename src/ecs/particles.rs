@@ -0,0 +1,578 @@
+use std::ops::{Add, Mul};
+
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Vector4, Zero};
+
+use super::collision_system::CollisionSystem;
+use super::layers::QueryFilter;
+use super::physics::PhysicsSystem;
+use super::world::{ComponentStorage, MovementSystem};
+
+//cpu particles, every emitter keeps its own particles in world space and steps them itself so none of this needs a gl
+//context. The renderer only ever sees the instance buffer from `ParticleSystem::instances`, one camera facing quad per
+//particle, so the shapes of things are all just color and size over the particles life
+
+/// Floats per particle in the instance buffer: position xyz, size, then rgba
+pub const PARTICLE_INSTANCE_FLOATS: usize = 8;
+
+/// Keys over 0 to 1 (how far through its life a particle is), straight lines between them and flat past the ends
+#[derive(Debug, Clone, PartialEq)]
+pub struct Curve<T> {
+    keys: Vec<(f32, T)>,
+}
+
+impl<T: Copy + Add<Output = T> + Mul<f32, Output = T>> Curve<T> {
+    pub fn constant(value: T) -> Self {
+        Self { keys: vec![(0.0, value)] }
+    }
+
+    pub fn linear(start: T, end: T) -> Self {
+        Self { keys: vec![(0.0, start), (1.0, end)] }
+    }
+
+    pub fn with_key(mut self, time: f32, value: T) -> Self {
+        let time = time.clamp(0.0, 1.0);
+        let index = self.keys.partition_point(|(key_time, _)| *key_time <= time);
+        self.keys.insert(index, (time, value));
+        self
+    }
+
+    pub fn sample(&self, time: f32) -> T {
+        let index = self.keys.partition_point(|(key_time, _)| *key_time <= time);
+        if index == 0 {
+            return self.keys[0].1;
+        }
+        if index == self.keys.len() {
+            return self.keys[index - 1].1;
+        }
+        let (time_a, a) = self.keys[index - 1];
+        let (time_b, b) = self.keys[index];
+        let t = if time_b > time_a { (time - time_a) / (time_b - time_a) } else { 1.0 };
+        a * (1.0 - t) + b * t
+    }
+}
+
+/// A bunch of particles all at once, `time` is seconds into each cycle of the emitter
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Burst {
+    pub time: f32,
+    pub count: u32,
+}
+
+/// Which way new particles go, anywhere up to `angle` radians off `direction` (in the emitters space)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct VelocityCone {
+    pub direction: Vector3<f32>,
+    pub angle: f32,
+    pub speed: (f32, f32), // picked evenly between the two
+}
+
+/// Particles stop at colliders instead of going through, checked with a ray along each particles move
+#[derive(Debug, Clone)]
+pub struct ParticleCollision {
+    pub bounce: f32,   // how much of the speed into the surface comes back out
+    pub friction: f32, // 0 to 1, how much of the sliding along it is lost each hit
+    pub kill_on_hit: bool, // sparks bounce, rain drops just go away
+    pub filter: QueryFilter,
+}
+
+impl Default for ParticleCollision {
+    fn default() -> Self {
+        Self { bounce: 0.3, friction: 0.2, kill_on_hit: false, filter: QueryFilter::new() }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Particle {
+    pub position: Vector3<f32>,
+    pub velocity: Vector3<f32>,
+    pub age: f32,
+    pub lifetime: f32,
+}
+
+impl Particle {
+    /// 0 when it spawns, 1 when it dies
+    pub fn life(&self) -> f32 {
+        (self.age / self.lifetime).clamp(0.0, 1.0)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticleEmitter {
+    pub rate: f32,            // particles per second on top of the bursts
+    pub bursts: Vec<Burst>,
+    pub duration: f32,        // length of one cycle, bursts go off by it
+    pub looping: bool,
+    pub lifetime: (f32, f32),
+    pub cone: VelocityCone,
+    pub spawn_radius: f32,    // new particles start anywhere inside this ball around the emitter
+    pub color: Curve<Vector4<f32>>,
+    pub size: Curve<f32>,
+    pub gravity_scale: f32,   // of the physics worlds gravity, smoke wants a little negative
+    pub drag: f32,            // fraction of the velocity lost per second
+    pub collision: Option<ParticleCollision>,
+    pub max_particles: usize,
+    pub playing: bool,
+    particles: Vec<Particle>,
+    time: f32,
+    spawn_debt: f32, // the bit of a particle the rate owes that didnt make a whole one yet
+    seed: u32,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParticleEmitter {
+    pub fn new() -> Self {
+        Self {
+            rate: 10.0,
+            bursts: Vec::new(),
+            duration: 1.0,
+            looping: true,
+            lifetime: (1.0, 1.0),
+            cone: VelocityCone { direction: Vector3::unit_y(), angle: 0.3, speed: (1.0, 2.0) },
+            spawn_radius: 0.0,
+            color: Curve::constant(Vector4::new(1.0, 1.0, 1.0, 1.0)),
+            size: Curve::constant(0.1),
+            gravity_scale: 0.0,
+            drag: 0.0,
+            collision: None,
+            max_particles: 1000,
+            playing: true,
+            particles: Vec::new(),
+            time: 0.0,
+            spawn_debt: 0.0,
+            seed: 0x9E37_79B9,
+        }
+    }
+
+    pub fn with_rate(mut self, rate: f32) -> Self {
+        self.rate = rate.max(0.0);
+        self
+    }
+
+    pub fn with_burst(mut self, time: f32, count: u32) -> Self {
+        self.bursts.push(Burst { time, count });
+        self
+    }
+
+    /// How long one cycle is, a one shot (not looping) stops making new ones once its through
+    pub fn with_duration(mut self, duration: f32, looping: bool) -> Self {
+        self.duration = duration.max(1e-3);
+        self.looping = looping;
+        self
+    }
+
+    pub fn with_lifetime(mut self, min: f32, max: f32) -> Self {
+        self.lifetime = (min.max(1e-3), max.max(min).max(1e-3));
+        self
+    }
+
+    pub fn with_cone(mut self, direction: Vector3<f32>, angle: f32, min_speed: f32, max_speed: f32) -> Self {
+        let direction = if direction.magnitude2() > 1e-12 { direction.normalize() } else { Vector3::unit_y() };
+        self.cone = VelocityCone { direction, angle: angle.clamp(0.0, std::f32::consts::PI), speed: (min_speed, max_speed.max(min_speed)) };
+        self
+    }
+
+    pub fn with_spawn_radius(mut self, radius: f32) -> Self {
+        self.spawn_radius = radius.max(0.0);
+        self
+    }
+
+    pub fn with_color(mut self, color: Curve<Vector4<f32>>) -> Self {
+        self.color = color;
+        self
+    }
+
+    pub fn with_size(mut self, size: Curve<f32>) -> Self {
+        self.size = size;
+        self
+    }
+
+    pub fn with_gravity_scale(mut self, scale: f32) -> Self {
+        self.gravity_scale = scale;
+        self
+    }
+
+    pub fn with_drag(mut self, drag: f32) -> Self {
+        self.drag = drag.max(0.0);
+        self
+    }
+
+    pub fn with_collision(mut self, collision: ParticleCollision) -> Self {
+        self.collision = Some(collision);
+        self
+    }
+
+    pub fn with_max_particles(mut self, max: usize) -> Self {
+        self.max_particles = max;
+        self
+    }
+
+    /// Two emitters with the same settings and seed spray the exact same way
+    pub fn with_seed(mut self, seed: u32) -> Self {
+        self.seed = seed.max(1); // xorshift gets stuck on 0
+        self
+    }
+
+    pub fn particles(&self) -> &[Particle] {
+        &self.particles
+    }
+
+    /// Carries on after `stop`, a one shot thats already played through goes again from the start
+    pub fn play(&mut self) {
+        if !self.looping && self.time >= self.duration {
+            self.time = 0.0;
+        }
+        self.playing = true;
+    }
+
+    /// Stops making new ones, whats already out lives out its life
+    pub fn stop(&mut self) {
+        self.playing = false;
+    }
+
+    /// Goes back to the start of the cycle and throws away every particle
+    pub fn restart(&mut self) {
+        self.particles.clear();
+        self.time = 0.0;
+        self.spawn_debt = 0.0;
+        self.playing = true;
+    }
+
+    /// Done playing and every particle is gone, one shot effects can get removed then
+    pub fn is_finished(&self) -> bool {
+        !self.playing && self.particles.is_empty()
+    }
+
+    /// Spawns `count` right now at `position`, on top of whatever the rate and bursts do
+    pub fn emit(&mut self, count: u32, position: Vector3<f32>, rotation: Quaternion<f32>) {
+        for _ in 0..count {
+            if self.particles.len() >= self.max_particles {
+                return;
+            }
+            let particle = self.spawn(position, rotation);
+            self.particles.push(particle);
+        }
+    }
+
+    fn spawn(&mut self, position: Vector3<f32>, rotation: Quaternion<f32>) -> Particle {
+        let offset = if self.spawn_radius > 0.0 {
+            // uniform inside the ball, cube root so they dont bunch up in the middle
+            self.random_direction(-1.0) * (self.random().cbrt() * self.spawn_radius)
+        } else {
+            Vector3::zero()
+        };
+        let direction = self.random_in_cone();
+        let speed = self.random_between(self.cone.speed);
+        let lifetime = self.random_between(self.lifetime);
+        Particle {
+            position: position + rotation.rotate_vector(offset),
+            velocity: rotation.rotate_vector(direction) * speed,
+            age: 0.0,
+            lifetime,
+        }
+    }
+
+    /// Ages, moves and spawns, `position` and `rotation` are where the emitter is this step
+    pub fn step(&mut self, position: Vector3<f32>, rotation: Quaternion<f32>, gravity: Vector3<f32>, collision: Option<(&MovementSystem, &CollisionSystem)>, delta_time: f32) {
+        if delta_time <= 0.0 {
+            return;
+        }
+
+        let acceleration = gravity * self.gravity_scale;
+        let keep = (1.0 - self.drag * delta_time).max(0.0);
+        for particle in &mut self.particles {
+            particle.age += delta_time;
+            particle.velocity = (particle.velocity + acceleration * delta_time) * keep;
+        }
+        self.particles.retain(|particle| particle.age < particle.lifetime);
+
+        match (&self.collision, collision) {
+            (Some(settings), Some((movement_system, collision_system))) => {
+                for particle in &mut self.particles {
+                    move_colliding(particle, settings, movement_system, collision_system, delta_time);
+                }
+                self.particles.retain(|particle| particle.age < particle.lifetime);
+            },
+            _ => {
+                for particle in &mut self.particles {
+                    particle.position += particle.velocity * delta_time;
+                }
+            },
+        }
+
+        if self.playing {
+            self.spawn_for(delta_time, position, rotation);
+        }
+    }
+
+    fn spawn_for(&mut self, delta_time: f32, position: Vector3<f32>, rotation: Quaternion<f32>) {
+        let start = self.time;
+        let end = start + delta_time;
+        let mut count = 0;
+        // every cycle the step runs through, a long hitch can skip past a whole loop
+        let first_cycle = (start / self.duration).floor() as i64;
+        let last_cycle = (end / self.duration).floor() as i64;
+        for cycle in first_cycle..=last_cycle {
+            if !self.looping && cycle > 0 {
+                break;
+            }
+            let cycle_start = cycle as f32 * self.duration;
+            for burst in &self.bursts {
+                let at = cycle_start + burst.time;
+                if at >= start && at < end {
+                    count += burst.count;
+                }
+            }
+        }
+
+        // the rate only runs while the cycle is going, a one shot stops at the end of its duration
+        let active = if self.looping { delta_time } else { (self.duration - start).clamp(0.0, delta_time) };
+        self.spawn_debt += self.rate * active;
+        let from_rate = self.spawn_debt.floor();
+        self.spawn_debt -= from_rate;
+        count += from_rate as u32;
+
+        self.emit(count, position, rotation);
+        self.time = end;
+        if !self.looping && self.time >= self.duration {
+            self.playing = false;
+        }
+    }
+
+    /// Every particle as instance floats (see `PARTICLE_INSTANCE_FLOATS`), in the order they were spawned
+    pub fn write_instances(&self, instances: &mut Vec<f32>) {
+        for particle in &self.particles {
+            instances.extend_from_slice(&instance(particle, self.color.sample(particle.life()), self.size.sample(particle.life())));
+        }
+    }
+
+    // xorshift32, only has to look random and come out the same every run
+    fn random(&mut self) -> f32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        (self.seed >> 8) as f32 / (1u32 << 24) as f32
+    }
+
+    fn random_between(&mut self, (min, max): (f32, f32)) -> f32 {
+        min + (max - min) * self.random()
+    }
+
+    // uniform over the cap of the sphere from straight up down to `lowest` (the cosine of the widest angle)
+    fn random_direction(&mut self, lowest: f32) -> Vector3<f32> {
+        let height = 1.0 - self.random() * (1.0 - lowest);
+        let around = self.random() * std::f32::consts::TAU;
+        let out = (1.0 - height * height).max(0.0).sqrt();
+        Vector3::new(out * around.cos(), height, out * around.sin())
+    }
+
+    fn random_in_cone(&mut self) -> Vector3<f32> {
+        let local = self.random_direction(self.cone.angle.cos());
+        // turn the cap around +y so its centered on the cones direction
+        let direction = self.cone.direction;
+        if direction.y < -0.9999 {
+            return -local;
+        }
+        Quaternion::from_arc(Vector3::unit_y(), direction, None).rotate_vector(local)
+    }
+}
+
+//moves along the ray to wherever it hits first, bounces off and carries on with whatever time is left over
+fn move_colliding(particle: &mut Particle, settings: &ParticleCollision, movement_system: &MovementSystem, collision_system: &CollisionSystem, delta_time: f32) {
+    let mut remaining = delta_time;
+    // a couple of bounces a step is plenty, a particle wedged in a corner just stops there
+    for _ in 0..3 {
+        let travel = particle.velocity * remaining;
+        let distance = travel.magnitude();
+        if distance < 1e-6 {
+            return;
+        }
+        let Some(hit) = collision_system.raycast(movement_system, particle.position, travel / distance, distance, &settings.filter) else {
+            particle.position += travel;
+            return;
+        };
+        if settings.kill_on_hit {
+            particle.position = hit.point;
+            particle.age = particle.lifetime;
+            return;
+        }
+        particle.position = hit.point + hit.normal * 1e-3;
+        let into = particle.velocity.dot(hit.normal);
+        let sliding = particle.velocity - hit.normal * into;
+        particle.velocity = sliding * (1.0 - settings.friction) - hit.normal * (into * settings.bounce);
+        remaining *= 1.0 - hit.distance / distance;
+    }
+}
+
+fn instance(particle: &Particle, color: Vector4<f32>, size: f32) -> [f32; PARTICLE_INSTANCE_FLOATS] {
+    let position = particle.position;
+    [position.x, position.y, position.z, size, color.x, color.y, color.z, color.w]
+}
+
+pub struct ParticleSystem {
+    emitters: ComponentStorage<ParticleEmitter>,
+}
+
+impl Default for ParticleSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ParticleSystem {
+    pub fn new() -> Self {
+        Self {
+            emitters: ComponentStorage::new(),
+        }
+    }
+
+    pub fn add_emitter(&mut self, entity_id: u32, emitter: ParticleEmitter) {
+        self.emitters.insert(entity_id, emitter);
+    }
+
+    pub fn get_emitter(&self, entity_id: u32) -> Option<&ParticleEmitter> {
+        self.emitters.get(entity_id)
+    }
+
+    pub fn get_emitter_mut(&mut self, entity_id: u32) -> Option<&mut ParticleEmitter> {
+        self.emitters.get_mut(entity_id)
+    }
+
+    pub fn remove_emitter(&mut self, entity_id: u32) {
+        self.emitters.remove(entity_id);
+    }
+
+    pub fn particle_count(&self) -> usize {
+        self.emitters.iter().map(|(_, emitter)| emitter.particles.len()).sum()
+    }
+
+    /// Steps every emitter from wherever its entity is, emitters without coords sit still
+    pub fn update(&mut self, movement_system: &MovementSystem, collision_system: &CollisionSystem, physics_system: &PhysicsSystem, delta_time: f32) {
+        for entity_id in self.emitters.sorted_ids() {
+            let Some(coords) = movement_system.get_coords(entity_id) else { continue };
+            let (position, rotation) = (coords.position, coords.rotation);
+            if let Some(emitter) = self.emitters.get_mut(entity_id) {
+                emitter.step(position, rotation, physics_system.gravity, Some((movement_system, collision_system)), delta_time);
+            }
+        }
+    }
+
+    /// Every live particle from every emitter as instance floats, furthest from `eye` first. Weighted OIT doesnt need
+    /// the order to blend right but the weights are tuned for far to near and it keeps the overdraw cheaper to read
+    pub fn instances(&self, eye: Vector3<f32>) -> Vec<f32> {
+        let mut sorted: Vec<(f32, [f32; PARTICLE_INSTANCE_FLOATS])> = Vec::with_capacity(self.particle_count());
+        for entity_id in self.emitters.sorted_ids() {
+            let Some(emitter) = self.emitters.get(entity_id) else { continue };
+            for particle in &emitter.particles {
+                let packed = instance(particle, emitter.color.sample(particle.life()), emitter.size.sample(particle.life()));
+                sorted.push(((particle.position - eye).magnitude2(), packed));
+            }
+        }
+        sorted.sort_by(|a, b| b.0.total_cmp(&a.0));
+        sorted.into_iter().flat_map(|(_, instance)| instance).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ecs::collision_system::Collider;
+    use crate::model::transform::WorldCoords;
+
+    const STEP: f32 = 1.0 / 60.0;
+
+    fn still() -> Quaternion<f32> {
+        Quaternion::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    #[test]
+    fn rate_and_bursts_spawn_the_right_amount() {
+        let mut emitter = ParticleEmitter::new().with_rate(30.0).with_burst(0.5, 20).with_lifetime(10.0, 10.0).with_duration(1.0, false);
+        for _ in 0..120 {
+            emitter.step(Vector3::zero(), still(), Vector3::zero(), None, STEP);
+        }
+        // one second of 30 a second plus the burst, then the one shot is over
+        assert_eq!(emitter.particles().len(), 50);
+        assert!(!emitter.playing);
+
+        let mut looping = ParticleEmitter::new().with_rate(0.0).with_burst(0.0, 5).with_lifetime(10.0, 10.0).with_duration(0.5, true);
+        for _ in 0..60 {
+            looping.step(Vector3::zero(), still(), Vector3::zero(), None, STEP);
+        }
+        assert_eq!(looping.particles().len(), 10);
+    }
+
+    #[test]
+    fn particles_stay_in_the_cone_and_die_on_time() {
+        let direction = Vector3::new(1.0, 0.0, 0.0);
+        let mut emitter = ParticleEmitter::new().with_rate(0.0).with_cone(direction, 0.2, 2.0, 3.0).with_lifetime(0.5, 0.5).with_seed(7);
+        emitter.emit(200, Vector3::zero(), still());
+        for particle in emitter.particles() {
+            let speed = particle.velocity.magnitude();
+            assert!((2.0..=3.0).contains(&speed));
+            assert!(particle.velocity.normalize().dot(direction) >= 0.2f32.cos() - 1e-4);
+        }
+        for _ in 0..31 {
+            emitter.step(Vector3::zero(), still(), Vector3::zero(), None, STEP);
+        }
+        assert!(emitter.particles().is_empty());
+    }
+
+    #[test]
+    fn curves_and_instances() {
+        let size = Curve::linear(1.0, 3.0).with_key(0.5, 0.0);
+        assert_eq!(size.sample(0.0), 1.0);
+        assert_eq!(size.sample(0.25), 0.5);
+        assert_eq!(size.sample(0.5), 0.0);
+        assert_eq!(size.sample(2.0), 3.0);
+
+        let fade = Curve::linear(Vector4::new(1.0, 0.5, 0.0, 1.0), Vector4::new(1.0, 0.5, 0.0, 0.0));
+        let mut emitter = ParticleEmitter::new().with_rate(0.0).with_lifetime(1.0, 1.0).with_cone(Vector3::unit_z(), 0.0, 1.0, 1.0).with_color(fade);
+        emitter.emit(1, Vector3::zero(), still());
+        for _ in 0..30 {
+            emitter.step(Vector3::zero(), still(), Vector3::zero(), None, STEP);
+        }
+        let mut instances = Vec::new();
+        emitter.write_instances(&mut instances);
+        assert_eq!(instances.len(), PARTICLE_INSTANCE_FLOATS);
+        assert!((instances[2] - 0.5).abs() < 1e-3 && (instances[7] - 0.5).abs() < 1e-3);
+
+        let mut movement = MovementSystem::new();
+        movement.add_coords(1, WorldCoords::new(0.0, 0.0, 0.0, 0.0));
+        movement.add_coords(2, WorldCoords::new(0.0, 0.0, -10.0, 0.0));
+        let mut system = ParticleSystem::new();
+        for entity_id in [1, 2] {
+            system.add_emitter(entity_id, ParticleEmitter::new().with_rate(60.0).with_cone(Vector3::unit_y(), 0.0, 0.0, 0.0));
+        }
+        system.update(&movement, &CollisionSystem::new(), &PhysicsSystem::new(), STEP);
+        let instances = system.instances(Vector3::new(0.0, 0.0, 5.0));
+        assert_eq!(instances.len(), 2 * PARTICLE_INSTANCE_FLOATS);
+        // the far one comes first
+        assert_eq!(instances[2], -10.0);
+    }
+
+    #[test]
+    fn falling_sparks_bounce_off_the_floor() {
+        let mut movement = MovementSystem::new();
+        let mut collision = CollisionSystem::new();
+        movement.add_coords(1, WorldCoords::new(0.0, -0.5, 0.0, 0.0));
+        collision.add_collider(1, Collider::bounding_box(10.0, 1.0, 10.0));
+
+        let mut emitter = ParticleEmitter::new().with_rate(0.0).with_lifetime(5.0, 5.0).with_cone(Vector3::unit_y(), 0.5, 1.0, 2.0)
+            .with_gravity_scale(1.0).with_collision(ParticleCollision { bounce: 0.5, ..Default::default() });
+        emitter.emit(50, Vector3::new(0.0, 1.0, 0.0), still());
+        let gravity = Vector3::new(0.0, -9.81, 0.0);
+        let mut bounced = false;
+        for _ in 0..180 {
+            emitter.step(Vector3::zero(), still(), gravity, Some((&movement, &collision)), STEP);
+            bounced |= emitter.particles().iter().any(|particle| particle.position.y < 0.05 && particle.velocity.y > 0.1);
+            assert!(emitter.particles().iter().all(|particle| particle.position.y > -1e-3));
+        }
+        assert!(bounced);
+        assert_eq!(emitter.particles().len(), 50);
+    }
+}
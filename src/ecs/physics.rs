@@ -1,9 +1,23 @@
-use cgmath::{InnerSpace, Quaternion, Rotation3, Vector3, Zero};
+use cgmath::{InnerSpace, Matrix, Matrix3, One, Quaternion, Rotation3, SquareMatrix, Vector3, Zero};
 // use serde::{Serialize, Deserialize};
-use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::time::Instant;
+use std::f32::consts::PI;
 
 use crate::model::transform::WorldCoords;
-use super::collision_system::{Collider, CollisionShape, CollisionEvent};
+use super::collision_system::{compound_children, Collider, CollisionShape, CollisionSystem, LocalTransform};
+use super::contact_manifold::ContactManifold;
+use super::convex_hull::outer;
+use super::islands::{build_islands, is_island_body, SleepEvent, SleepSettings};
+use super::layers::{ALL_LAYERS, DEFAULT_LAYER};
+use super::joints::Joint;
+use super::force_fields::{FieldSample, ForceField, ForceFieldKind};
+use super::water::{Buoyancy, WaterSurface};
+use super::snapshot::{BodyState, PhysicsSnapshot};
+use super::solver::{solve_constraints, SolverSettings};
+use super::stats::PhysicsStats;
 use super::components::Velocity;
 use super::world::{MovementSystem, ComponentStorage};
 
@@ -128,8 +142,10 @@ impl PhysicsEntityData {
             collider: Collider {
                 shape,
                 is_trigger: true,
-                layer: 0,
+                membership: DEFAULT_LAYER,
+                filter: ALL_LAYERS,
                 offset: Vector3::new(0.0, 0.0, 0.0),
+                material: None,
             },
             mass: None,
             velocity: None,
@@ -161,7 +177,13 @@ impl PhysicsEntityData {
     }
     
     pub fn with_layer(mut self, layer: u32) -> Self {
-        self.collider.layer = layer;
+        self.collider = self.collider.with_layer(layer);
+        self
+    }
+
+    /// Overrides `with_restitution`/`with_friction` with a registered material, see `PhysicsMaterials`
+    pub fn with_material(mut self, name: &str) -> Self {
+        self.collider = self.collider.with_material(name);
         self
     }
 }
@@ -203,13 +225,23 @@ pub struct PhysicsEntity {
     pub angular_damping: f32,            // Rotational drag (0.0 = no damping, 1.0 = full)
     
     // Inertia (resistance to rotation)
-    pub inertia_tensor: Vector3<f32>,    // Diagonal inertia tensor (simplified)
-    pub inverse_inertia: Vector3<f32>,   // Cached inverse for performance
+    // full 3x3 tensors in the bodies own space (around the entities position), turned into world space every step
+    // set them with set_inertia_tensor so the inverse stays in sync
+    pub inertia_tensor: Matrix3<f32>,
+    pub inverse_inertia: Matrix3<f32>,
     
     // Constraints
     pub is_kinematic: bool, // Moves but not affected by forces
     pub lock_rotation: bool,
     pub lock_axis: Vector3<bool>, // Lock movement on specific axes
+
+    // Continuous collision, for small fast things that would otherwise go straight through walls
+    pub ccd: bool,
+
+    // Sleeping
+    pub can_sleep: bool,
+    pub(crate) sleeping: bool,
+    pub(crate) sleep_timer: f32, // how long its been resting for
 }
 
 impl PhysicsEntity {
@@ -226,11 +258,15 @@ impl PhysicsEntity {
             torque: Vector3::zero(),
             angular_impulse: Vector3::zero(),
             angular_damping: 0.05,
-            inertia_tensor: Vector3::new(1.0, 1.0, 1.0),
-            inverse_inertia: Vector3::new(1.0, 1.0, 1.0),
+            inertia_tensor: Matrix3::identity(),
+            inverse_inertia: Matrix3::identity(),
             is_kinematic: false,
             lock_rotation: false,
             lock_axis: Vector3::new(false, false, false),
+            can_sleep: true,
+            ccd: false,
+            sleeping: false,
+            sleep_timer: 0.0,
         }
     }
 
@@ -239,11 +275,11 @@ impl PhysicsEntity {
         
         // Moment of inertia for solid sphere: I = (2/5) * m * r²
         let inertia = (2.0 / 5.0) * mass * radius * radius;
-        let inertia_tensor = Vector3::new(inertia, inertia, inertia);
+        let inertia_tensor = Matrix3::from_diagonal(Vector3::new(inertia, inertia, inertia));
         let inverse_inertia = if mass > 0.0 {
-            Vector3::new(1.0 / inertia, 1.0 / inertia, 1.0 / inertia)
+            Matrix3::from_diagonal(Vector3::new(1.0 / inertia, 1.0 / inertia, 1.0 / inertia))
         } else {
-            Vector3::zero()
+            Matrix3::zero()
         };
         
         Self {
@@ -266,6 +302,10 @@ impl PhysicsEntity {
             is_kinematic: false,
             lock_rotation: false,
             lock_axis: Vector3::new(false, false, false),
+            can_sleep: true,
+            ccd: false,
+            sleeping: false,
+            sleep_timer: 0.0,
         }
     }
 
@@ -277,11 +317,11 @@ impl PhysicsEntity {
         let inertia_y = (1.0 / 12.0) * mass * (width * width + depth * depth);
         let inertia_z = (1.0 / 12.0) * mass * (width * width + height * height);
         
-        let inertia_tensor = Vector3::new(inertia_x, inertia_y, inertia_z);
+        let inertia_tensor = Matrix3::from_diagonal(Vector3::new(inertia_x, inertia_y, inertia_z));
         let inverse_inertia = if mass > 0.0 {
-            Vector3::new(1.0 / inertia_x, 1.0 / inertia_y, 1.0 / inertia_z)
+            Matrix3::from_diagonal(Vector3::new(1.0 / inertia_x, 1.0 / inertia_y, 1.0 / inertia_z))
         } else {
-            Vector3::zero()
+            Matrix3::zero()
         };
         
         Self {
@@ -304,8 +344,30 @@ impl PhysicsEntity {
             is_kinematic: false,
             lock_rotation: false,
             lock_axis: Vector3::new(false, false, false),
+            can_sleep: true,
+            ccd: false,
+            sleeping: false,
+            sleep_timer: 0.0,
         }
     }
+
+    /// Rigidbody with its inertia worked out from the collider, any shape with a volume works (offsets, rotated OBBs,
+    /// compounds and hulls included). The mass is spread evenly through the volume and the tensor is taken around the
+    /// entities position since thats what it spins around, so keep the center of mass near the origin.
+    /// 2d shapes and terrain have no volume and keep the default inertia
+    pub fn from_collider(mass: f32, collider: &Collider) -> Self {
+        let mut body = Self::new(mass);
+        if let Some(inertia) = collider_inertia(mass, collider) {
+            body.set_inertia_tensor(inertia);
+        }
+        body
+    }
+
+    /// Rigidbody for a compound collider, the mass is split between the children by volume and their inertias are added up
+    /// around the entities position (parallel axis), so build the compound with its center of mass at the origin
+    pub fn compound(mass: f32, children: &[(Collider, LocalTransform)]) -> Self {
+        Self::from_collider(mass, &Collider::compound(children.to_vec()))
+    }
     
     pub fn static_body() -> Self {
         Self {
@@ -320,11 +382,15 @@ impl PhysicsEntity {
             torque: Vector3::zero(),
             angular_impulse: Vector3::zero(),
             angular_damping: 0.05,
-            inertia_tensor: Vector3::zero(),
-            inverse_inertia: Vector3::zero(),
+            inertia_tensor: Matrix3::zero(),
+            inverse_inertia: Matrix3::zero(),
             is_kinematic: false,
             lock_rotation: true,
             lock_axis: Vector3::new(false, false, false),
+            can_sleep: true,
+            ccd: false,
+            sleeping: false,
+            sleep_timer: 0.0,
         }
     }
     
@@ -341,11 +407,15 @@ impl PhysicsEntity {
             torque: Vector3::zero(),
             angular_impulse: Vector3::zero(),
             angular_damping: 0.05,
-            inertia_tensor: Vector3::new(1.0, 1.0, 1.0),
-            inverse_inertia: Vector3::new(1.0, 1.0, 1.0),
+            inertia_tensor: Matrix3::identity(),
+            inverse_inertia: Matrix3::identity(),
             is_kinematic: true,
             lock_rotation: true,
             lock_axis: Vector3::new(false, false, false),
+            can_sleep: true,
+            ccd: false,
+            sleeping: false,
+            sleep_timer: 0.0,
         }
     }
     
@@ -379,6 +449,38 @@ impl PhysicsEntity {
         self
     }
     
+    pub fn with_ccd(mut self, ccd: bool) -> Self {
+        self.ccd = ccd;
+        self
+    }
+
+    pub fn with_can_sleep(mut self, can_sleep: bool) -> Self {
+        self.can_sleep = can_sleep;
+        self
+    }
+
+    pub fn with_inertia_tensor(mut self, inertia: Matrix3<f32>) -> Self {
+        self.set_inertia_tensor(inertia);
+        self
+    }
+
+    /// `inertia` is in the bodies own space, a tensor that cant be inverted (or a body with no mass) gets no rotation from forces
+    pub fn set_inertia_tensor(&mut self, inertia: Matrix3<f32>) {
+        self.inertia_tensor = inertia;
+        self.inverse_inertia = if self.mass > 0.0 { inertia.invert().unwrap_or(Matrix3::zero()) } else { Matrix3::zero() };
+    }
+
+    /// Sleeping bodies arent integrated or solved until something wakes their island
+    pub fn is_sleeping(&self) -> bool {
+        self.sleeping
+    }
+
+    //anything pushed onto the accumulators wakes a sleeping body up next update
+    fn is_nudged(&self) -> bool {
+        self.force != Vector3::zero() || self.impulse != Vector3::zero()
+            || self.torque != Vector3::zero() || self.angular_impulse != Vector3::zero()
+    }
+
     pub fn apply_force(&mut self, force: Vector3<f32>) {
         if !self.is_kinematic && self.inverse_mass > 0.0 {
             self.force += force;
@@ -406,13 +508,126 @@ impl PhysicsEntity {
             self.angular_impulse += impulse;
         }
     }
+
+    /// The inverse inertia in world space, inverse_inertia is in the bodies own space so it has to be
+    /// turned with the body (R * I^-1 * R^T) before it can be used with world space torques
+    pub fn world_inverse_inertia(&self, rotation: Quaternion<f32>) -> Matrix3<f32> {
+        let rotation = Matrix3::from(rotation);
+        rotation * self.inverse_inertia * rotation.transpose()
+    }
+
+    pub fn world_inertia(&self, rotation: Quaternion<f32>) -> Matrix3<f32> {
+        let rotation = Matrix3::from(rotation);
+        rotation * self.inertia_tensor * rotation.transpose()
+    }
+
+    //anything not spun around one of its main axes wobbles by itself with no torque at all (a thrown book, a tennis racket
+    //flipping over), thats the w x Iw term. its done implicitly in body space with one newton step like bullet does, the
+    //plain explicit version keeps adding energy until fast spinners explode
+    fn gyroscopic_step(&self, rotation: Quaternion<f32>, delta_time: f32) -> Vector3<f32> {
+        let turn = Matrix3::from(rotation);
+        let spin = turn.transpose() * self.angular_velocity;
+        let momentum = self.inertia_tensor * spin;
+        let residual = spin.cross(momentum) * delta_time;
+        let jacobian = self.inertia_tensor + (skew(spin) * self.inertia_tensor - skew(momentum)) * delta_time;
+        match jacobian.invert() {
+            Some(inverse) => turn * (spin - inverse * residual),
+            None => self.angular_velocity,
+        }
+    }
+}
+
+//the matrix that does `v x` when multiplied by something
+fn skew(v: Vector3<f32>) -> Matrix3<f32> {
+    Matrix3::new(
+        0.0, v.z, -v.y,
+        -v.z, 0.0, v.x,
+        v.y, -v.x, 0.0,
+    )
+}
+
+//inertia of a whole collider around the entities position, every solid piece gets its share of `mass` by volume and is
+//moved out from its own center of mass with the parallel axis theorem
+pub(crate) fn collider_inertia(mass: f32, collider: &Collider) -> Option<Matrix3<f32>> {
+    let identity = WorldCoords { position: Vector3::zero(), rotation: Quaternion::one(), scale: Vector3::new(1.0, 1.0, 1.0) };
+    let (position, rotation) = CollisionSystem::world_pose(collider, &identity);
+    let pieces: Vec<(Vector3<f32>, Quaternion<f32>, &Collider)> = match &collider.shape {
+        CollisionShape::Compound(children) => compound_children(children, position, rotation)
+            .map(|(_, position, rotation, child)| (position, rotation, child))
+            .collect(),
+        _ => vec![(position, rotation, collider)],
+    };
+    let parts: Vec<(f32, Vector3<f32>, Matrix3<f32>)> = pieces.into_iter()
+        .filter_map(|(position, rotation, piece)| {
+            let (volume, center, unit_inertia) = unit_mass_properties(&piece.shape)?;
+            let turn = Matrix3::from(rotation);
+            Some((volume, position + turn * center, turn * unit_inertia * turn.transpose()))
+        })
+        .collect();
+    let total_volume: f32 = parts.iter().map(|(volume, ..)| volume).sum();
+    if total_volume <= 0.0 {
+        return None;
+    }
+
+    let mut inertia = Matrix3::zero();
+    for (volume, center, local) in parts {
+        let part_mass = mass * volume / total_volume;
+        inertia += (local + Matrix3::identity() * center.magnitude2() - outer(center, center)) * part_mass;
+    }
+    Some(inertia)
 }
 
 
+//volume of a shape, its center of mass and its inertia per unit of mass around that center, all in its own space
+//None for the 2d shapes, terrain and compounds (those get split into their children first)
+pub(crate) fn unit_mass_properties(shape: &CollisionShape) -> Option<(f32, Vector3<f32>, Matrix3<f32>)> {
+    let box_properties = |size: Vector3<f32>| {
+        let (x, y, z) = (size.x * size.x, size.y * size.y, size.z * size.z);
+        (size.x * size.y * size.z, Vector3::zero(), Matrix3::from_diagonal(Vector3::new(y + z, x + z, x + y) / 12.0))
+    };
+    match shape {
+        CollisionShape::Sphere { radius } => {
+            let inertia = 0.4 * radius * radius;
+            Some((4.0 / 3.0 * PI * radius.powi(3), Vector3::zero(), Matrix3::from_value(inertia)))
+        },
+        CollisionShape::Box { width, height, depth } => Some(box_properties(Vector3::new(*width, *height, *depth))),
+        CollisionShape::OBB { half_extents, .. } => Some(box_properties(*half_extents * 2.0)),
+        CollisionShape::Capsule { radius, half_height } => {
+            //a cylinder plus the two half spheres on the ends (which together make one sphere)
+            let length = half_height * 2.0;
+            let r2 = radius * radius;
+            let cylinder = PI * r2 * length;
+            let caps = 4.0 / 3.0 * PI * radius.powi(3);
+            let volume = cylinder + caps;
+            let (cylinder, caps) = (cylinder / volume, caps / volume);
+            let around_y = cylinder * r2 / 2.0 + caps * 0.4 * r2;
+            let across = cylinder * (length * length / 12.0 + r2 / 4.0) + caps * (0.4 * r2 + length * length / 4.0 + 3.0 * length * radius / 8.0);
+            Some((volume, Vector3::zero(), Matrix3::from_diagonal(Vector3::new(across, around_y, across))))
+        },
+        CollisionShape::ConvexHull(hull) => Some(hull.mass_properties()),
+        CollisionShape::Circle { .. } | CollisionShape::Rectangle { .. } | CollisionShape::Compound(..) | CollisionShape::Heightfield(..) => None,
+    }
+}
+
 //todo this is erm rather scuffed amd simple and not what i really want like as you can see right now it only stores rigidbodys which is of course not really ideally what i want
 pub struct PhysicsSystem {
     rigidbodies: ComponentStorage<PhysicsEntity>,
     pub gravity: Vector3<f32>,
+    pub solver: SolverSettings,
+    joints: HashMap<u32, Joint>,
+    next_joint_id: u32,
+    broken_joints: Vec<u32>,//joints that snapped during the last step
+    pub sleep: SleepSettings,
+    sleep_events: Vec<SleepEvent>,
+    // deterministic mode, the world only ever steps by fixed_timestep and saves up whatever real time is left over
+    pub deterministic: bool,
+    pub fixed_timestep: f32,
+    accumulator: f32,
+    force_fields: HashMap<u32, ForceField>, // keyed by the trigger entity they live on
+    time: f32,                              // how long its been simulating, the wind gusts run off this
+    pub water: Option<WaterSurface>,
+    buoyancy: ComponentStorage<Buoyancy>,
+    stats: PhysicsStats, // from the last step
 }
 
 impl PhysicsSystem {
@@ -420,6 +635,20 @@ impl PhysicsSystem {
         Self {
             rigidbodies: ComponentStorage::new(),
             gravity: Vector3::new(0.0, -9.81, 0.0), // Default Earth gravity
+            solver: SolverSettings::new(),
+            joints: HashMap::new(),
+            next_joint_id: 0,
+            broken_joints: Vec::new(),
+            sleep: SleepSettings::new(),
+            deterministic: false,
+            fixed_timestep: 1.0 / 60.0,
+            accumulator: 0.0,
+            force_fields: HashMap::new(),
+            time: 0.0,
+            water: None,
+            buoyancy: ComponentStorage::new(),
+            stats: PhysicsStats::default(),
+            sleep_events: Vec::new(),
         }
     }
     
@@ -427,7 +656,282 @@ impl PhysicsSystem {
         self.gravity = gravity;
         self
     }
+
+    pub fn with_solver_settings(mut self, solver: SolverSettings) -> Self {
+        self.solver = solver;
+        self
+    }
+
+    pub fn with_sleep_settings(mut self, sleep: SleepSettings) -> Self {
+        self.sleep = sleep;
+        self
+    }
     
+    /// Same inputs give bit for bit the same results, for replays and lockstep. Everything is stepped by
+    /// `fixed_timestep` no matter how long the frame took, see `fixed_steps`
+    pub fn with_deterministic(mut self, fixed_timestep: f32) -> Self {
+        self.deterministic = true;
+        self.fixed_timestep = fixed_timestep;
+        self
+    }
+
+    /// How many fixed steps fit in `delta_time` plus whatever was left over from last frame, the rest waits for next frame.
+    /// The leftover is kept as a whole number of microseconds so it doesnt pick up rounding from odd frame times
+    pub fn fixed_steps(&mut self, delta_time: f32) -> u32 {
+        let step = (self.fixed_timestep as f64 * 1_000_000.0).round();
+        let total = (self.accumulator as f64 + (delta_time as f64 * 1_000_000.0).round()).max(0.0);
+        let steps = (total / step).floor();
+        self.accumulator = (total - steps * step) as f32;
+        steps as u32
+    }
+
+    /// Hash of every bodies position, rotation and velocity (the exact bits), two runs that hash the same are the same
+    pub fn state_hash(&self, movement_system: &MovementSystem) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        for entity_id in self.rigidbodies.sorted_ids() {
+            entity_id.hash(&mut hasher);
+            let mut floats: Vec<f32> = Vec::new();
+            if let Some(coords) = movement_system.get_coords(entity_id) {
+                floats.extend([coords.position.x, coords.position.y, coords.position.z]);
+                floats.extend([coords.rotation.s, coords.rotation.v.x, coords.rotation.v.y, coords.rotation.v.z]);
+            }
+            if let Some(velocity) = movement_system.get_velocity(entity_id) {
+                floats.extend([velocity.direction.x, velocity.direction.y, velocity.direction.z, velocity.speed]);
+            }
+            if let Some(rigidbody) = self.rigidbodies.get(entity_id) {
+                floats.extend([rigidbody.angular_velocity.x, rigidbody.angular_velocity.y, rigidbody.angular_velocity.z]);
+                rigidbody.sleeping.hash(&mut hasher);
+            }
+            for value in floats {
+                value.to_bits().hash(&mut hasher);
+            }
+        }
+        hasher.finish()
+    }
+
+    /// Copies out every rigidbody (velocities, forces, sleep state), the coords and velocity of its entity, the joints
+    /// and the collision systems contact cache, enough for `restore` to put the world back exactly
+    pub fn snapshot(&self, movement_system: &MovementSystem, collision_system: &CollisionSystem) -> PhysicsSnapshot {
+        let bodies = self.rigidbodies.sorted_ids().into_iter().filter_map(|entity_id| {
+            let rigidbody = self.rigidbodies.get(entity_id)?.clone();
+            let coords = movement_system.get_coords(entity_id).map(|coords| (coords.position, coords.rotation, coords.scale));
+            let velocity = movement_system.get_velocity(entity_id).map(|velocity| (velocity.direction, velocity.speed));
+            Some(BodyState { entity_id, rigidbody, coords, velocity })
+        }).collect();
+
+        let mut joints: Vec<(u32, Joint)> = self.joints.iter().map(|(id, joint)| (*id, joint.clone())).collect();
+        joints.sort_by_key(|(id, _)| *id);
+
+        PhysicsSnapshot {
+            bodies,
+            joints,
+            next_joint_id: self.next_joint_id,
+            accumulator: self.accumulator,
+            time: self.time,
+            water_time: self.water.map(|water| water.time),
+            contacts: collision_system.contact_cache(),
+        }
+    }
+
+    /// Makes the trigger collider on `entity_id` push on whatever is inside it, see `apply_force_fields`
+    pub fn add_force_field(&mut self, entity_id: u32, field: ForceField) {
+        self.force_fields.insert(entity_id, field);
+    }
+
+    pub fn get_force_field(&self, entity_id: u32) -> Option<&ForceField> {
+        self.force_fields.get(&entity_id)
+    }
+
+    pub fn get_force_field_mut(&mut self, entity_id: u32) -> Option<&mut ForceField> {
+        self.force_fields.get_mut(&entity_id)
+    }
+
+    pub fn remove_force_field(&mut self, entity_id: u32) -> Option<ForceField> {
+        self.force_fields.remove(&entity_id)
+    }
+
+    /// Adds every force field's push to the force accumulator of the bodies inside it, run right before `update`.
+    /// Gravity zones swap out the world gravity (the highest priority one if a body is in a few), the rest stack up.
+    /// Sleeping bodies are skipped for gravity and drag so a resting body in a low gravity room can still fall asleep
+    pub fn apply_force_fields(&mut self, movement_system: &MovementSystem, collision_system: &CollisionSystem) {
+        let mut field_ids: Vec<u32> = self.force_fields.iter().filter(|(_, field)| field.enabled).map(|(id, _)| *id).collect();
+        field_ids.sort_unstable();
+
+        // (priority, field id, gravity) for the zone each body is going by
+        let mut gravity_zones: HashMap<u32, (i32, u32, Vector3<f32>)> = HashMap::new();
+        for field_id in field_ids {
+            let field = self.force_fields[&field_id];
+            let Some(center) = movement_system.get_coords(field_id).map(|coords| coords.position) else { continue };
+
+            for entity_id in collision_system.inside_trigger(movement_system, field_id) {
+                let Some(rigidbody) = self.rigidbodies.get_mut(entity_id) else { continue };
+                if rigidbody.is_static() || rigidbody.is_kinematic {
+                    continue;
+                }
+                if let ForceFieldKind::Gravity { gravity } = field.kind {
+                    let current = gravity_zones.get(&entity_id);
+                    if current.is_none_or(|(priority, ..)| field.priority > *priority) {
+                        gravity_zones.insert(entity_id, (field.priority, field_id, gravity));
+                    }
+                    continue;
+                }
+                if rigidbody.sleeping && matches!(field.kind, ForceFieldKind::Drag { .. }) {
+                    continue;
+                }
+
+                let Some(coords) = movement_system.get_coords(entity_id) else { continue };
+                let velocity = movement_system.get_velocity(entity_id).map_or(Vector3::zero(), |velocity| velocity.linear());
+                let acceleration = field.acceleration(center, coords.position, velocity, self.time);
+                rigidbody.apply_force(acceleration * rigidbody.mass);
+            }
+        }
+
+        // update adds the world gravity on its own, so this cancels it and puts the zones in instead
+        let mut zoned: Vec<(u32, Vector3<f32>)> = gravity_zones.into_iter().map(|(entity_id, (_, _, gravity))| (entity_id, gravity)).collect();
+        zoned.sort_by_key(|(entity_id, _)| *entity_id);
+        for (entity_id, gravity) in zoned {
+            let world_gravity = self.gravity;
+            if let Some(rigidbody) = self.rigidbodies.get_mut(entity_id).filter(|rigidbody| !rigidbody.sleeping) {
+                rigidbody.apply_force((gravity - world_gravity) * rigidbody.mass);
+            }
+        }
+    }
+
+    /// Same rules as `apply_force_fields` but for a single point moving at `velocity` instead of a rigidbody
+    pub fn sample_fields(
+        &self,
+        movement_system: &MovementSystem,
+        collision_system: &CollisionSystem,
+        position: Vector3<f32>,
+        velocity: Vector3<f32>,
+    ) -> FieldSample {
+        let mut sample = FieldSample { gravity: self.gravity, acceleration: Vector3::zero(), air: Vector3::zero() };
+        let mut field_ids: Vec<u32> = self.force_fields.iter().filter(|(_, field)| field.enabled).map(|(id, _)| *id).collect();
+        field_ids.sort_unstable();
+
+        let mut gravity_priority = None;
+        for field_id in field_ids {
+            let field = self.force_fields[&field_id];
+            let Some(center) = movement_system.get_coords(field_id).map(|coords| coords.position) else { continue };
+            if !collision_system.contains_point(movement_system, field_id, position) {
+                continue;
+            }
+            match field.kind {
+                ForceFieldKind::Gravity { gravity } => {
+                    if gravity_priority.is_none_or(|priority| field.priority > priority) {
+                        sample.gravity = gravity;
+                        gravity_priority = Some(field.priority);
+                    }
+                },
+                ForceFieldKind::Wind { .. } | ForceFieldKind::Drag { .. } => {
+                    sample.air += field.acceleration(center, position, velocity, self.time);
+                },
+                _ => sample.acceleration += field.acceleration(center, position, velocity, self.time),
+            }
+        }
+        sample
+    }
+
+    pub fn with_water(mut self, water: WaterSurface) -> Self {
+        self.water = Some(water);
+        self
+    }
+
+    /// Makes `entity_id` float in `water`, see `Buoyancy::for_collider` for making one that fits its collider
+    pub fn add_buoyancy(&mut self, entity_id: u32, buoyancy: Buoyancy) {
+        self.buoyancy.insert(entity_id, buoyancy);
+    }
+
+    pub fn get_buoyancy_mut(&mut self, entity_id: u32) -> Option<&mut Buoyancy> {
+        self.buoyancy.get_mut(entity_id)
+    }
+
+    pub fn remove_buoyancy(&mut self, entity_id: u32) {
+        self.buoyancy.remove(entity_id);
+    }
+
+    //each sample point under the water pushes up against gravity with the weight of the water it displaces, at the
+    //point itself so it turns the body too. Drag scales with how much of the body is under
+    fn apply_buoyancy(&mut self, movement_system: &MovementSystem) {
+        let Some(water) = self.water else { return };
+        let up = -self.gravity;
+        for entity_id in self.buoyancy.sorted_ids() {
+            let Some(buoyancy) = self.buoyancy.get(entity_id) else { continue };
+            let Some(rigidbody) = self.rigidbodies.get_mut(entity_id) else { continue };
+            if rigidbody.is_static() || rigidbody.is_kinematic || rigidbody.sleeping || buoyancy.points.is_empty() {
+                continue;
+            }
+            let Some(coords) = movement_system.get_coords(entity_id) else { continue };
+
+            let point_volume = buoyancy.volume / buoyancy.points.len() as f32;
+            let mut submerged = 0.0;
+            for (point, fraction) in buoyancy.submerged(&water, coords.position, coords.rotation) {
+                if fraction <= 0.0 {
+                    continue;
+                }
+                submerged += fraction;
+                let force = up * (water.density * point_volume * fraction);
+                rigidbody.apply_force(force);
+                rigidbody.apply_torque((point - coords.position).cross(force));
+            }
+            if submerged <= 0.0 {
+                continue;
+            }
+
+            let submerged = submerged / buoyancy.points.len() as f32;
+            let velocity = movement_system.get_velocity(entity_id).map_or(Vector3::zero(), |velocity| velocity.linear());
+            rigidbody.apply_force(-velocity * (buoyancy.linear_drag * submerged * rigidbody.mass));
+            let drag = -buoyancy.angular_drag * submerged;
+            let spin = rigidbody.world_inertia(coords.rotation) * rigidbody.angular_velocity;
+            rigidbody.apply_torque(spin * drag);
+        }
+    }
+
+    /// Rewinds to `snapshot`. Rigidbodies and joints added since then are dropped and removed ones come back,
+    /// entities without a rigidbody are left alone. Bodies spawned after the snapshot lose their coords, velocity and
+    /// collider too, their ids get handed back so the caller can get rid of the rest of them (models, names)
+    pub fn restore(&mut self, snapshot: &PhysicsSnapshot, movement_system: &mut MovementSystem, collision_system: &mut CollisionSystem) -> Vec<u32> {
+        let mut spawned = self.rigidbodies.sorted_ids();
+        spawned.retain(|entity_id| !snapshot.bodies.iter().any(|body| body.entity_id == *entity_id));
+        for entity_id in &spawned {
+            movement_system.remove_coords(*entity_id);
+            movement_system.remove_velocity(*entity_id);
+            collision_system.remove_collider(*entity_id);
+            self.buoyancy.remove(*entity_id);
+        }
+
+        self.rigidbodies = ComponentStorage::new();
+        for body in &snapshot.bodies {
+            self.rigidbodies.insert(body.entity_id, body.rigidbody.clone());
+            if let Some((position, rotation, scale)) = body.coords {
+                match movement_system.get_coords_mut(body.entity_id) {
+                    Some(coords) => {
+                        coords.position = position;
+                        coords.rotation = rotation;
+                        coords.scale = scale;
+                    },
+                    None => movement_system.add_coords(body.entity_id, WorldCoords { position, rotation, scale }),
+                }
+            }
+            match body.velocity {
+                Some((direction, speed)) => movement_system.add_velocity(body.entity_id, Velocity { direction, speed }),
+                None => movement_system.remove_velocity(body.entity_id),
+            }
+        }
+
+        self.joints = snapshot.joints.iter().cloned().collect();
+        self.next_joint_id = snapshot.next_joint_id;
+        self.accumulator = snapshot.accumulator;
+        self.time = snapshot.time;
+        if let (Some(water), Some(time)) = (&mut self.water, snapshot.water_time) {
+            water.time = time;
+        }
+        self.broken_joints.clear();
+        self.sleep_events.clear();
+        collision_system.restore_contact_cache(&snapshot.contacts);
+        spawned
+    }
+
     pub fn add_rigidbody(&mut self, entity_id: u32, rigidbody: PhysicsEntity) {
         self.rigidbodies.insert(entity_id, rigidbody);
     }
@@ -442,14 +946,94 @@ impl PhysicsSystem {
     
     pub fn remove_rigidbody(&mut self, entity_id: u32) {
         self.rigidbodies.remove(entity_id);
+        // a joint hanging off nothing would just pin the other side in place
+        self.joints.retain(|_, joint| joint.entity_a != entity_id && joint.entity_b != Some(entity_id));
+    }
+
+    /// Adds a joint and hands back its id
+    pub fn add_joint(&mut self, joint: Joint) -> u32 {
+        let id = self.next_joint_id;
+        self.next_joint_id += 1;
+        self.joints.insert(id, joint);
+        id
+    }
+
+    pub fn get_joint(&self, joint_id: u32) -> Option<&Joint> {
+        self.joints.get(&joint_id)
+    }
+
+    pub fn get_joint_mut(&mut self, joint_id: u32) -> Option<&mut Joint> {
+        self.joints.get_mut(&joint_id)
+    }
+
+    pub fn remove_joint(&mut self, joint_id: u32) -> Option<Joint> {
+        self.joints.remove(&joint_id)
+    }
+
+    /// Bodies that fell asleep or woke up during the last step
+    pub fn get_sleep_events(&self) -> &[SleepEvent] {
+        &self.sleep_events
+    }
+
+    /// Wakes a body straight away, the rest of its island wakes with it when the constraints are solved
+    pub fn wake_up(&mut self, entity_id: u32) {
+        if let Some(rigidbody) = self.rigidbodies.get_mut(entity_id) {
+            if rigidbody.sleeping {
+                rigidbody.sleeping = false;
+                rigidbody.sleep_timer = 0.0;
+                self.sleep_events.push(SleepEvent::WokeUp(entity_id));
+            }
+        }
+    }
+
+    /// Joints that went over their break force during the last step
+    /// they stay in the system (marked broken) until removed
+    pub fn get_broken_joints(&self) -> &[u32] {
+        &self.broken_joints
+    }
+
+    //pairs held by a joint that said they shouldnt collide, (lower id, higher id) like the collision system keys them
+    pub(crate) fn non_colliding_pairs(&self) -> HashSet<(u32, u32)> {
+        self.joints.values()
+            .filter(|joint| !joint.collide_connected && !joint.broken)
+            .filter_map(|joint| {
+                let other = joint.entity_b?;
+                Some((joint.entity_a.min(other), joint.entity_a.max(other)))
+            })
+            .collect()
     }
     
     /// Main physics update - applies forces and integrates velocity
     pub fn update(&mut self, movement_system: &mut MovementSystem, delta_time: f32) {
-        for (entity_id, rigidbody) in self.rigidbodies.iter_mut() {
+        let started = Instant::now();
+        self.sleep_events.clear();
+        self.time += delta_time;
+        self.apply_buoyancy(movement_system);
+        if let Some(water) = &mut self.water {
+            water.advance(delta_time);
+        }
+        debug_assert!(
+            !self.deterministic || delta_time == self.fixed_timestep,
+            "deterministic physics only steps by the fixed timestep ({}), got {}", self.fixed_timestep, delta_time
+        );
+
+        for entity_id in self.rigidbodies.sorted_ids() {
+            let entity_id = &entity_id;
+            let Some(rigidbody) = self.rigidbodies.get_mut(*entity_id) else { continue };
             if rigidbody.is_static() {
                 continue;
             }
+
+            if rigidbody.sleeping {
+                // a force or someone setting its velocity directly wakes it, otherwise it just stays put
+                let moved = movement_system.get_velocity(*entity_id).is_some_and(|v| v.speed > 0.0);
+                if !rigidbody.is_nudged() && !moved {
+                    continue;
+                }
+                rigidbody.sleeping = false;
+                rigidbody.sleep_timer = 0.0;
+                self.sleep_events.push(SleepEvent::WokeUp(*entity_id));
+            }
             
             // === LINEAR PHYSICS ===
             let velocity = movement_system.get_velocity_mut(*entity_id);
@@ -501,34 +1085,32 @@ impl PhysicsSystem {
             
             // === ANGULAR PHYSICS (NEW) ===
             if !rigidbody.is_kinematic && !rigidbody.lock_rotation {
+                // angular velocity, torque and impulses are all world space so the inertia has to be too
+                let rotation = movement_system.get_coords(*entity_id).map(|c| c.rotation).unwrap_or(Quaternion::one());
+                let inverse_inertia = rigidbody.world_inverse_inertia(rotation);
+
                 // Apply angular impulses (instant angular velocity changes)
-                rigidbody.angular_velocity += Vector3::new(
-                    rigidbody.angular_impulse.x * rigidbody.inverse_inertia.x,
-                    rigidbody.angular_impulse.y * rigidbody.inverse_inertia.y,
-                    rigidbody.angular_impulse.z * rigidbody.inverse_inertia.z,
-                );
+                rigidbody.angular_velocity += inverse_inertia * rigidbody.angular_impulse;
                 rigidbody.angular_impulse = Vector3::zero();
                 
                 // Apply torques (T = I * α -> α = T / I)
-                let angular_acceleration = Vector3::new(
-                    rigidbody.torque.x * rigidbody.inverse_inertia.x,
-                    rigidbody.torque.y * rigidbody.inverse_inertia.y,
-                    rigidbody.torque.z * rigidbody.inverse_inertia.z,
-                );
+                let angular_acceleration = inverse_inertia * rigidbody.torque;
                 rigidbody.angular_velocity += angular_acceleration * delta_time;
                 rigidbody.torque = Vector3::zero();
+                rigidbody.angular_velocity = rigidbody.gyroscopic_step(rotation, delta_time);
                 
                 // Apply angular damping
                 rigidbody.angular_velocity *= 1.0 - rigidbody.angular_damping;
                 
                 // Integrate rotation (update the actual rotation)
+                // the delta goes on the left because the angular velocity is in world space not the bodies space
                 if rigidbody.angular_velocity.magnitude2() > 0.0001 {
                     if let Some(coords) = movement_system.get_coords_mut(*entity_id) {
                         let angle = rigidbody.angular_velocity.magnitude() * delta_time;
                         if angle > 0.0001 {
                             let axis = rigidbody.angular_velocity.normalize();
                             let rotation_delta = Quaternion::from_axis_angle(axis, cgmath::Rad(angle));
-                            coords.set_rotation_from_quaternion(coords.rotation * rotation_delta);
+                            coords.set_rotation_from_quaternion((rotation_delta * coords.rotation).normalize());
                         }
                     }
                 }
@@ -544,354 +1126,332 @@ impl PhysicsSystem {
             rigidbody.impulse = Vector3::zero();
             
         }
-    }
-    
-    /// Resolve collision with proper physics (called by collision system)
-pub fn resolve_collision(
-    &mut self,
-    movement_system: &mut MovementSystem,
-    collision: &CollisionEvent,
-) {
-    let rb_a = self.rigidbodies.get(collision.entity_a);
-    let rb_b = self.rigidbodies.get(collision.entity_b);
-    
-    if rb_a.is_none() && rb_b.is_none() {
-        self.simple_position_resolution(movement_system, collision);
-        return;
-    }
 
-    let (inv_mass_a, rest_a, fric_a, is_static_a) = if let Some(rb) = rb_a {
-        (rb.inverse_mass, rb.restitution, rb.friction, rb.is_static())
-    } else {
-        (0.0, 0.0, 0.5, true)
-    };
-    
-    let (inv_mass_b, rest_b, fric_b, is_static_b) = if let Some(rb) = rb_b {
-        (rb.inverse_mass, rb.restitution, rb.friction, rb.is_static())
-    } else {
-        (0.0, 0.0, 0.5, true)
-    };
-
-    let mut normal = collision.normal.normalize();
+        let mut stats = PhysicsStats::default();
+        for (_, rigidbody) in self.rigidbodies.iter() {
+            if rigidbody.is_kinematic {
+                stats.kinematic_bodies += 1;
+            } else if rigidbody.is_static() {
+                stats.static_bodies += 1;
+            } else {
+                stats.dynamic_bodies += 1;
+                stats.sleeping_bodies += rigidbody.sleeping as usize;
+            }
+        }
+        stats.integrate_time = started.elapsed();
+        self.stats = stats;
+    }
 
-    if inv_mass_a == 0.0 && inv_mass_b == 0.0 {
-        self.simple_position_resolution(movement_system, collision);
-        return;
+    /// Counts and timings from the last step, the collision half is in `CollisionSystem::stats`
+    pub fn stats(&self) -> &PhysicsStats {
+        &self.stats
     }
     
-    // === POSITION CORRECTION ===
-    let total_inv_mass = inv_mass_a + inv_mass_b;
-    if total_inv_mass > 0.0 {
-        let correction_percent = 0.8;
-        let slop = 0.01;
-        
-        let correction_magnitude = (collision.penetration - slop).max(0.0) / total_inv_mass * correction_percent;
-        let correction = normal * correction_magnitude;
-        
-        if inv_mass_a > 0.0 {
-            if let Some(coords) = movement_system.get_coords_mut(collision.entity_a) {
-                coords.position += correction * inv_mass_a;
+    /// Resolve every contact manifold and joint of the step (called by collision system)
+    pub fn solve_constraints(&mut self, movement_system: &mut MovementSystem, manifolds: &mut [&mut ContactManifold], delta_time: f32) {
+        let started = Instant::now();
+        // pairs where neither side has a rigidbody still get pushed apart, they just dont get any impulses
+        for manifold in manifolds.iter() {
+            if !self.rigidbodies.contains(manifold.entity_a) && !self.rigidbodies.contains(manifold.entity_b) {
+                self.simple_position_resolution(movement_system, manifold);
             }
         }
-        
-        if inv_mass_b > 0.0 {
-            if let Some(coords) = movement_system.get_coords_mut(collision.entity_b) {
-                coords.position -= correction * inv_mass_b;
-            }
+
+        let islands = build_islands(&self.rigidbodies, manifolds, &self.joints);
+        self.stats.islands = islands.len();
+        if self.sleep.enabled {
+            self.wake_islands(movement_system, manifolds, &islands);
         }
-    }
-    
-    // === VELOCITY RESOLUTION ===
-    let vel_a = movement_system.get_velocity_mut(collision.entity_a)
-        .map(|v| v.direction * v.speed)
-        .unwrap_or(Vector3::zero());
-        
-    let vel_b = movement_system.get_velocity_mut(collision.entity_b)
-        .map(|v| v.direction * v.speed)
-        .unwrap_or(Vector3::zero());
 
-    let relative_velocity = vel_a - vel_b;
-    let velocity_along_normal = relative_velocity.dot(normal);
-    
-    // Don't resolve if objects are separating
-    if velocity_along_normal > 0.0 {
-        return;
+        // anything only touching sleeping or static stuff gets left alone
+        let mut manifolds: Vec<&mut ContactManifold> = manifolds.iter_mut()
+            .filter(|manifold| self.is_awake(manifold.entity_a) || self.is_awake(manifold.entity_b))
+            .map(|manifold| &mut **manifold)
+            .collect();
+
+        let rigidbodies = &self.rigidbodies;
+        let awake = |entity_id: u32| rigidbodies.get(entity_id).is_some_and(|rb| !rb.is_static() && !rb.sleeping);
+        let mut joints: Vec<(&u32, &mut Joint)> = self.joints.iter_mut()
+            .filter(|(_, joint)| !joint.broken && (awake(joint.entity_a) || joint.entity_b.is_some_and(awake)))
+            .collect();
+        joints.sort_by_key(|(id, _)| **id);
+        let joint_ids: Vec<u32> = joints.iter().map(|(id, _)| **id).collect();
+        let mut joints: Vec<&mut Joint> = joints.into_iter().map(|(_, joint)| joint).collect();
+
+        self.stats.joints = joints.len();
+        solve_constraints(&self.solver, &mut self.rigidbodies, movement_system, &mut manifolds, &mut joints, delta_time, &mut self.stats);
+
+        self.broken_joints = joint_ids.into_iter().zip(joints.iter()).filter(|(_, joint)| joint.broken).map(|(id, _)| id).collect();
+
+        if self.sleep.enabled {
+            self.update_sleep(movement_system, &islands, delta_time);
+        }
+        self.stats.solver_time = started.elapsed();
     }
-    
-    let restitution = (rest_a + rest_b) / 2.0;
-    let impulse_scalar = -(1.0 + restitution) * velocity_along_normal / total_inv_mass;
-    let impulse = normal * impulse_scalar;
-    
-    // Apply normal impulse
-    if inv_mass_a > 0.0 {
-        if let Some(vel) = movement_system.get_velocity_mut(collision.entity_a) {
-            let new_vel = vel.direction * vel.speed + impulse * inv_mass_a;
-            let speed = new_vel.magnitude();
-            if speed > 0.001 {
-                vel.direction = new_vel.normalize();
-                vel.speed = speed;
-            } else {
-                vel.speed = 0.0;
-                vel.direction = Vector3::zero();
-            }
+
+    //bodies without a rigidbody count as awake, theyre moved by something else (the player, scripts, ect)
+    fn is_awake(&self, entity_id: u32) -> bool {
+        match self.rigidbodies.get(entity_id) {
+            Some(rigidbody) => !rigidbody.is_static() && !rigidbody.sleeping,
+            None => true,
         }
     }
-    
-    if inv_mass_b > 0.0 {
-        if let Some(vel) = movement_system.get_velocity_mut(collision.entity_b) {
-            let new_vel = vel.direction * vel.speed - impulse * inv_mass_b;
-            let speed = new_vel.magnitude();
-            if speed > 0.001 {
-                vel.direction = new_vel.normalize();
-                vel.speed = speed;
-            } else {
-                vel.speed = 0.0;
-                vel.direction = Vector3::zero();
+
+    /// An island wakes up as a whole if any body in it is awake or something thats moving (a kinematic body or
+    /// a collider without a rigidbody) is touching it
+    fn wake_islands(&mut self, movement_system: &MovementSystem, manifolds: &[&mut ContactManifold], islands: &[Vec<u32>]) {
+        let mut pushed: HashSet<u32> = HashSet::new();
+        for manifold in manifolds.iter() {
+            for (mover, other) in [(manifold.entity_a, manifold.entity_b), (manifold.entity_b, manifold.entity_a)] {
+                if self.is_mover(movement_system, mover) {
+                    pushed.insert(other);
+                }
             }
         }
-    }
-    
-    // === FRICTION (SIMPLIFIED FOR ROLLING) ===
-    let friction = (fric_a + fric_b) / 2.0;
-    
-    if friction < 0.001 {
-        return;
-    }
-    
-    // Get updated velocities after normal impulse
-    let vel_a = movement_system.get_velocity_mut(collision.entity_a)
-        .map(|v| v.direction * v.speed)
-        .unwrap_or(Vector3::zero());
-        
-    let vel_b = movement_system.get_velocity_mut(collision.entity_b)
-        .map(|v| v.direction * v.speed)
-        .unwrap_or(Vector3::zero());
-    
-    // Get positions AT THE TIME OF COLLISION
-    // CRITICAL: The collision_point was calculated using old positions
-    // We need to recalculate r vectors based on CURRENT positions
-    let pos_a = movement_system.get_coords(collision.entity_a)
-        .map(|c| c.position)
-        .unwrap_or(Vector3::zero());
-    let pos_b = movement_system.get_coords(collision.entity_b)
-        .map(|c| c.position)
-        .unwrap_or(Vector3::zero());
-    
-    // OPTION 1: Recalculate contact point based on current positions
-    // Assume entity B is the sphere (you should check this properly)
-    // For now, let's use whichever gives us a reasonable radius
-    let r_a = collision.collision_point - pos_a;
-    let r_b = collision.collision_point - pos_b;
-    
-    // Determine which is the sphere based on which r vector is closer to a reasonable radius
-    let (sphere_entity, sphere_pos, sphere_r, obb_entity, obb_pos, obb_r) = 
-        if r_b.magnitude() < 5.0 && r_b.magnitude() > 1.0 {
-            // B is probably the sphere
-            (collision.entity_b, pos_b, r_b, collision.entity_a, pos_a, r_a)
-        } else if r_a.magnitude() < 5.0 && r_a.magnitude() > 1.0 {
-            // A is probably the sphere
-            (collision.entity_a, pos_a, r_a, collision.entity_b, pos_b, r_b)
-        } else {
-            // Can't determine, use as-is
-            (collision.entity_a, pos_a, r_a, collision.entity_b, pos_b, r_b)
-        };
-    
-    // Recalculate contact point on sphere surface using CURRENT position
-    let sphere_radius = sphere_r.magnitude();
-    let contact_point_corrected = sphere_pos - normal * sphere_radius;
-    let r_sphere = contact_point_corrected - sphere_pos;
-    let r_obb = contact_point_corrected - obb_pos;
-    
-    println!("DEBUG: pos_a: {:?}, pos_b: {:?}", pos_a, pos_b);
-    println!("DEBUG: old collision_point: {:?}", collision.collision_point);
-    println!("DEBUG: corrected contact_point: {:?}", contact_point_corrected);
-    println!("DEBUG: sphere_pos: {:?}, sphere_radius: {}", sphere_pos, sphere_radius);
-    println!("DEBUG: r_sphere magnitude: {}", r_sphere.magnitude());
-    
-    // Use corrected values
-    let (r_a_final, r_b_final) = if sphere_entity == collision.entity_a {
-        (r_sphere, r_obb)
-    } else {
-        (r_obb, r_sphere)
-    };
-    
-    // Get angular data - use corrected entity IDs
-    let (angular_vel_a, inv_inertia_a, can_rotate_a, is_sphere_a) = 
-        if inv_mass_a > 0.0 {
-            self.rigidbodies.get(collision.entity_a)
-                .map(|rb| {
-                    let is_sphere = sphere_entity == collision.entity_a;
-                    (rb.angular_velocity, rb.inverse_inertia, !rb.lock_rotation, is_sphere)
-                })
-                .unwrap_or((Vector3::zero(), Vector3::zero(), false, false))
-        } else {
-            (Vector3::zero(), Vector3::zero(), false, false)
-        };
-    
-    let (angular_vel_b, inv_inertia_b, can_rotate_b, is_sphere_b) = 
-        if inv_mass_b > 0.0 {
-            self.rigidbodies.get(collision.entity_b)
-                .map(|rb| {
-                    let is_sphere = sphere_entity == collision.entity_b;
-                    (rb.angular_velocity, rb.inverse_inertia, !rb.lock_rotation, is_sphere)
-                })
-                .unwrap_or((Vector3::zero(), Vector3::zero(), false, false))
-        } else {
-            (Vector3::zero(), Vector3::zero(), false, false)
-        };
-    
-    // Use corrected r vectors
-    let r_a = r_a_final;
-    let r_b = r_b_final;
-    
-    // Calculate velocity at contact point
-    let contact_vel_a = if can_rotate_a {
-        vel_a + angular_vel_a.cross(r_a)
-    } else {
-        vel_a
-    };
-    
-    let contact_vel_b = if can_rotate_b {
-        vel_b + angular_vel_b.cross(r_b)
-    } else {
-        vel_b
-    };
-    
-    let relative_contact_vel = contact_vel_a - contact_vel_b;
-    
-    // Get tangent (perpendicular to normal)
-    let tangent_vel = relative_contact_vel - normal * relative_contact_vel.dot(normal);
-    
-    if tangent_vel.magnitude() < 0.001 {
-        return; // No sliding
-    }
-    
-    let tangent = tangent_vel.normalize();
-    
-    // SIMPLIFIED: For sphere on plane, use basic friction model
-    // The friction impulse opposes sliding at the contact point
-    let friction_impulse_magnitude = -relative_contact_vel.dot(tangent);
-    
-    // Clamp to Coulomb friction limit
-    let max_friction = impulse_scalar.abs() * friction;
-    let friction_impulse_magnitude = friction_impulse_magnitude.clamp(-max_friction, max_friction);
-    
-    let friction_impulse = tangent * friction_impulse_magnitude;
-    
-    println!("=== FRICTION DEBUG ===");
-    println!("r_a: {:?}, magnitude: {}", r_a, r_a.magnitude());
-    println!("r_b: {:?}, magnitude: {}", r_b, r_b.magnitude());
-    println!("Contact velocity A: {:?}", contact_vel_a);
-    println!("Relative contact velocity: {:?}", relative_contact_vel);
-    println!("Tangent: {:?}", tangent);
-    println!("Friction impulse: {:?}", friction_impulse);
-    println!("r_a: {:?}, magnitude: {}", r_a, r_a.magnitude());
-    
-    // Apply linear friction
-    if inv_mass_a > 0.0 {
-        if let Some(vel) = movement_system.get_velocity_mut(collision.entity_a) {
-            let new_vel = vel.direction * vel.speed + friction_impulse * inv_mass_a;
-            let speed = new_vel.magnitude();
-            if speed > 0.001 {
-                vel.direction = new_vel.normalize();
-                vel.speed = speed;
-            } else {
-                vel.speed = 0.0;
-                vel.direction = Vector3::zero();
+
+        for island in islands {
+            let wake = island.iter().any(|entity_id| {
+                pushed.contains(entity_id) || self.rigidbodies.get(*entity_id).is_some_and(|rb| !rb.sleeping)
+            });
+            if !wake {
+                continue;
+            }
+            for entity_id in island {
+                self.wake_up(*entity_id);
             }
-            println!("New linear vel A: {:?}", vel.direction * vel.speed);
         }
     }
-    
-    if inv_mass_b > 0.0 {
-        if let Some(vel) = movement_system.get_velocity_mut(collision.entity_b) {
-            let new_vel = vel.direction * vel.speed - friction_impulse * inv_mass_b;
-            let speed = new_vel.magnitude();
-            if speed > 0.001 {
-                vel.direction = new_vel.normalize();
-                vel.speed = speed;
-            } else {
-                vel.speed = 0.0;
-                vel.direction = Vector3::zero();
-            }
+
+    fn is_mover(&self, movement_system: &MovementSystem, entity_id: u32) -> bool {
+        if self.rigidbodies.get(entity_id).is_some_and(|rb| is_island_body(rb) || rb.is_static()) {
+            return false;
         }
+        let linear = movement_system.get_velocity(entity_id).map_or(0.0, |v| v.speed);
+        let angular = self.rigidbodies.get(entity_id).map_or(0.0, |rb| rb.angular_velocity.magnitude());
+        linear > self.sleep.linear_threshold || angular > self.sleep.angular_threshold
     }
-    
-    // Apply angular friction (creates rolling)
-    if can_rotate_a {
-            println!("Applying: angular friction to entity A");
-        if let Some(rb_a) = self.rigidbodies.get_mut(collision.entity_a) {
-            // CRITICAL FIX: Torque direction
-            // For rolling, we want the friction to create rotation that matches linear motion
-            // The standard formula is τ = r × F
-            // But we need to check the sign carefully
-            let torque = r_a.cross(friction_impulse);
-            
-            // Check if this torque moves us toward or away from rolling
-            let angular_change = Vector3::new(
-                torque.x * inv_inertia_a.x,
-                torque.y * inv_inertia_a.y,
-                torque.z * inv_inertia_a.z,
-            );
-            
-            println!("Torque: {:?}", torque);
-            println!("Angular change: {:?}", angular_change);
-            println!("Old angular vel: {:?}", rb_a.angular_velocity);
-            
-            // CRITICAL: The friction should make us roll, not slide
-            // If the angular change is in the wrong direction, flip it
-            if is_sphere_a {
-                let sphere_r_mag = r_a.magnitude();
-                let vel_for_entity_a = if sphere_entity == collision.entity_a { vel_a } else { vel_b };
-                let expected_angular_vel = r_a.cross(vel_for_entity_a) / (sphere_r_mag * sphere_r_mag);
-                
-                // Check if the torque is moving us toward or away from expected
-                let current_diff = (rb_a.angular_velocity - expected_angular_vel).magnitude();
-                let new_diff = (rb_a.angular_velocity + angular_change - expected_angular_vel).magnitude();
-                
-                // If applying the torque makes us worse, flip it
-                if new_diff > current_diff {
-                    println!("WARNING: Torque is wrong direction! Flipping it.");
-                    rb_a.angular_velocity += angular_change;
+
+    /// Counts up how long each body has been resting, islands where every body has been resting long enough go to sleep
+    fn update_sleep(&mut self, movement_system: &mut MovementSystem, islands: &[Vec<u32>], delta_time: f32) {
+        for island in islands {
+            let mut ready = true;
+            for entity_id in island {
+                let Some(rigidbody) = self.rigidbodies.get_mut(*entity_id) else { continue };
+                if rigidbody.sleeping {
+                    continue;
+                }
+                let speed = movement_system.get_velocity(*entity_id).map_or(0.0, |v| v.speed);
+                if rigidbody.can_sleep
+                    && speed < self.sleep.linear_threshold
+                    && rigidbody.angular_velocity.magnitude() < self.sleep.angular_threshold
+                {
+                    rigidbody.sleep_timer += delta_time;
                 } else {
-                    rb_a.angular_velocity += angular_change;
+                    rigidbody.sleep_timer = 0.0;
                 }
-                
-                println!("Expected angular vel for rolling: {:?}", expected_angular_vel);
-            } else {
-                rb_a.angular_velocity += angular_change;
+                ready &= rigidbody.sleep_timer >= self.sleep.time_to_sleep;
+            }
+            if !ready {
+                continue;
+            }
+
+            for entity_id in island {
+                let Some(rigidbody) = self.rigidbodies.get_mut(*entity_id) else { continue };
+                if rigidbody.sleeping {
+                    continue;
+                }
+                rigidbody.sleeping = true;
+                rigidbody.angular_velocity = Vector3::zero();
+                if let Some(velocity) = movement_system.get_velocity_mut(*entity_id) {
+                    velocity.set_linear(Vector3::zero());
+                }
+                self.sleep_events.push(SleepEvent::FellAsleep(*entity_id));
             }
-            
-            println!("New angular vel: {:?}", rb_a.angular_velocity);
-        }
-    }
-    
-    if can_rotate_b {
-        println!("Applying: angular friction to entity B");
-        if let Some(rb_b) = self.rigidbodies.get_mut(collision.entity_b) {
-            let torque = r_b.cross(-friction_impulse);
-            let angular_change = Vector3::new(
-                torque.x * inv_inertia_b.x,
-                torque.y * inv_inertia_b.y,
-                torque.z * inv_inertia_b.z,
-            );
-            rb_b.angular_velocity += angular_change;
         }
     }
     
-    println!("=== END FRICTION ===\n");
-}
-    
     /// Simple position-based resolution (fallback for objects without rigidbodies)
-    fn simple_position_resolution(&self, movement_system: &mut MovementSystem, collision: &CollisionEvent) {
-        let separation = collision.normal * (collision.penetration / 2.0);
+    fn simple_position_resolution(&self, movement_system: &mut MovementSystem, manifold: &ContactManifold) {
+        let separation = manifold.normal * (manifold.deepest_penetration() / 2.0);
         
-        if let Some(coords_a) = movement_system.get_coords_mut(collision.entity_a) {
+        if let Some(coords_a) = movement_system.get_coords_mut(manifold.entity_a) {
             coords_a.position += separation;
         }
         
-        if let Some(coords_b) = movement_system.get_coords_mut(collision.entity_b) {
+        if let Some(coords_b) = movement_system.get_coords_mut(manifold.entity_b) {
             coords_b.position -= separation;
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use crate::ecs::collision_system::{Collider, CollisionSystem, LocalTransform};
+    use crate::ecs::components::Velocity;
+    use crate::ecs::joints::Joint;
+    use crate::ecs::physics::{PhysicsEntity, PhysicsSystem};
+    use crate::ecs::world::MovementSystem;
+    use crate::model::transform::WorldCoords;
+
+    fn moving(velocity: Vector3<f32>) -> Velocity {
+        let mut moving = Velocity { direction: Vector3::new(0.0, 0.0, 0.0), speed: 0.0 };
+        moving.set_linear(velocity);
+        moving
+    }
+
+    // floor, a stack, some thrown balls, a compound and a swinging chain, built from scratch so every HashMap is new
+    fn scene() -> (MovementSystem, PhysicsSystem, CollisionSystem) {
+        let mut movement = MovementSystem::new();
+        let mut physics = PhysicsSystem::new().with_deterministic(1.0 / 60.0);
+        let mut collision = CollisionSystem::new();
+
+        movement.add_coords(0, WorldCoords::new(0.0, -0.5, 0.0, 0.0));
+        collision.add_collider(0, Collider::bounding_box(40.0, 1.0, 40.0));
+        physics.add_rigidbody(0, PhysicsEntity::static_body());
+
+        for i in 1..=5 {
+            movement.add_coords(i, WorldCoords::new(0.0, i as f32 * 1.02 - 0.5, 0.0, 0.0));
+            movement.add_velocity(i, moving(Vector3::new(0.0, 0.0, 0.0)));
+            collision.add_collider(i, Collider::bounding_box(1.0, 1.0, 1.0));
+            physics.add_rigidbody(i, PhysicsEntity::box_shape(1.0, 1.0, 1.0, 1.0));
+        }
+
+        for i in 6..=12 {
+            let offset = i as f32 - 9.0;
+            movement.add_coords(i, WorldCoords::new(offset * 1.5, 3.0 + offset.abs(), 6.0, 0.0));
+            movement.add_velocity(i, moving(Vector3::new(-offset, 1.0, -6.0)));
+            collision.add_collider(i, Collider::sphere(0.4));
+            physics.add_rigidbody(i, PhysicsEntity::sphere(0.5, 0.4).with_restitution(0.3));
+        }
+
+        let chair = vec![
+            (Collider::bounding_box(1.0, 0.2, 1.0), LocalTransform::from_position(Vector3::new(0.0, 0.5, 0.0))),
+            (Collider::bounding_box(0.2, 1.0, 0.2), LocalTransform::from_position(Vector3::new(0.4, 0.0, 0.4))),
+            (Collider::bounding_box(0.2, 1.0, 0.2), LocalTransform::from_position(Vector3::new(-0.4, 0.0, -0.4))),
+        ];
+        movement.add_coords(13, WorldCoords::new(-4.0, 2.0, -3.0, 0.3));
+        movement.add_velocity(13, moving(Vector3::new(2.0, 0.0, 1.0)));
+        physics.add_rigidbody(13, PhysicsEntity::compound(3.0, &chair));
+        collision.add_collider(13, Collider::compound(chair));
+
+        for i in 14..=16 {
+            movement.add_coords(i, WorldCoords::new(5.0 + (i - 14) as f32, 6.0, 0.0, 0.0));
+            movement.add_velocity(i, moving(Vector3::new(0.0, 0.0, 0.0)));
+            collision.add_collider(i, Collider::sphere(0.3));
+            physics.add_rigidbody(i, PhysicsEntity::sphere(1.0, 0.3));
+        }
+        physics.add_joint(Joint::ball_socket(14, None, Vector3::new(0.0, 0.0, 0.0), Vector3::new(4.0, 6.0, 0.0)));
+        physics.add_joint(Joint::ball_socket(14, Some(15), Vector3::new(0.5, 0.0, 0.0), Vector3::new(-0.5, 0.0, 0.0)));
+        physics.add_joint(Joint::ball_socket(15, Some(16), Vector3::new(0.5, 0.0, 0.0), Vector3::new(-0.5, 0.0, 0.0)));
+
+        (movement, physics, collision)
+    }
+
+    fn run(movement: &mut MovementSystem, physics: &mut PhysicsSystem, collision: &mut CollisionSystem, steps: u32) -> u64 {
+        let delta_time = physics.fixed_timestep;
+        for _ in 0..steps {
+            physics.update(movement, delta_time);
+            movement.update(delta_time);
+            collision.update(movement, physics, delta_time);
+        }
+        physics.state_hash(movement)
+    }
+
+    fn simulate(steps: u32) -> u64 {
+        let (mut movement, mut physics, mut collision) = scene();
+        run(&mut movement, &mut physics, &mut collision, steps)
+    }
+
+    #[test]
+    fn same_inputs_same_hash() {
+        assert_eq!(simulate(1000), simulate(1000));
+        // the whole scene has gone to sleep well before 1000 steps, so check while its still moving
+        assert_ne!(simulate(500), simulate(499), "the hash should actually see the state changing");
+    }
+
+    // a 2kg ball hanging off `joint` (anchored at its center to the world at the origin), twisted by `torque` every step
+    fn hang(joint: Joint, torque: f32, steps: u32) -> (PhysicsSystem, MovementSystem, u32) {
+        let mut movement = MovementSystem::new();
+        let mut physics = PhysicsSystem::new();
+        let mut collision = CollisionSystem::new();
+        movement.add_coords(1, WorldCoords::new(0.0, 0.0, 0.0, 0.0));
+        movement.add_velocity(1, moving(Vector3::new(0.0, 0.0, 0.0)));
+        physics.add_rigidbody(1, PhysicsEntity::sphere(2.0, 0.5));
+        let joint_id = physics.add_joint(joint);
+
+        let delta_time = 1.0 / 60.0;
+        for _ in 0..steps {
+            physics.get_rigidbody_mut(1).unwrap().apply_torque(Vector3::new(0.0, 0.0, torque));
+            physics.update(&mut movement, delta_time);
+            movement.update(delta_time);
+            collision.update(&mut movement, &mut physics, delta_time);
+        }
+        (physics, movement, joint_id)
+    }
+
+    #[test]
+    fn joints_break_past_their_threshold_and_stay_broken() {
+        let origin = Vector3::new(0.0, 0.0, 0.0);
+        // holding up 2kg is about 19.6N
+        let (physics, movement, joint) = hang(Joint::ball_socket(1, None, origin, origin).with_break_force(25.0), 0.0, 120);
+        assert!(!physics.get_joint(joint).unwrap().broken);
+        assert!(movement.get_coords(1).unwrap().position.y > -0.05);
+
+        let (physics, movement, joint) = hang(Joint::ball_socket(1, None, origin, origin).with_break_force(15.0), 0.0, 120);
+        assert!(physics.get_joint(joint).unwrap().broken);
+        assert!(physics.get_broken_joints().is_empty(), "only reported the step it snapped");
+        assert!(movement.get_coords(1).unwrap().position.y < -1.0, "the ball should have fallen once it let go");
+
+        // twisting a weld, the force threshold never sees it
+        let weld = || Joint::fixed(1, None, origin, origin).with_break_force(1000.0);
+        let (physics, _, joint) = hang(weld().with_break_torque(60.0), 40.0, 120);
+        assert!(!physics.get_joint(joint).unwrap().broken);
+        let (physics, _, joint) = hang(weld(), 40.0, 120);
+        assert!(!physics.get_joint(joint).unwrap().broken);
+        let (physics, movement, joint) = hang(weld().with_break_torque(20.0), 40.0, 120);
+        assert!(physics.get_joint(joint).unwrap().broken);
+        let spin = movement.get_coords(1).unwrap().rotation;
+        assert!(spin.s < 0.99, "should be spinning freely once broken, rotation {:?}", spin);
+    }
+
+    #[test]
+    fn restoring_a_snapshot_replays_exactly() {
+        let (mut movement, mut physics, mut collision) = scene();
+        // mid fall with contacts warm and the chain swinging
+        run(&mut movement, &mut physics, &mut collision, 120);
+        let snapshot = physics.snapshot(&movement, &collision);
+        let first = run(&mut movement, &mut physics, &mut collision, 150);
+
+        physics.restore(&snapshot, &mut movement, &mut collision);
+        let restored = physics.state_hash(&movement);
+        assert_eq!(restored, simulate(120), "restore puts every body back where it was");
+        assert_ne!(restored, first);
+        assert_eq!(run(&mut movement, &mut physics, &mut collision, 150), first);
+
+        // a ball dropped on the stack and the floor set moving after the snapshot, both have to be undone
+        physics.restore(&snapshot, &mut movement, &mut collision);
+        movement.add_coords(20, WorldCoords::new(0.2, 8.0, 0.0, 0.0));
+        movement.add_velocity(20, moving(Vector3::new(0.0, -5.0, 0.0)));
+        collision.add_collider(20, Collider::sphere(0.5));
+        physics.add_rigidbody(20, PhysicsEntity::sphere(2.0, 0.5));
+        movement.add_velocity(0, moving(Vector3::new(1.0, 0.0, 0.0)));
+        run(&mut movement, &mut physics, &mut collision, 60);
+
+        assert_eq!(physics.restore(&snapshot, &mut movement, &mut collision), vec![20]);
+        assert!(movement.get_coords(20).is_none() && movement.get_velocity(20).is_none());
+        assert!(collision.get_collider(20).is_none());
+        assert!(movement.get_velocity(0).is_none(), "the floor had no velocity in the snapshot");
+        assert_eq!(physics.state_hash(&movement), restored);
+        assert_eq!(run(&mut movement, &mut physics, &mut collision, 150), first);
+    }
+
+    #[test]
+    fn fixed_steps_carry_the_leftover() {
+        let mut physics = PhysicsSystem::new().with_deterministic(0.01);
+        assert_eq!(physics.fixed_steps(0.025), 2);
+        assert_eq!(physics.fixed_steps(0.005), 1);
+        assert_eq!(physics.fixed_steps(0.0), 0);
+    }
+}
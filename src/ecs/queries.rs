@@ -0,0 +1,152 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Zero};
+
+use super::collision_system::CollisionShape;
+
+//ray tests against single shapes, the collision system runs these over everything that passes the query filter
+//rays that start inside a shape dont hit it, same as the discrete pass leaves overlaps to the solver
+
+#[derive(Debug, Clone, Copy)]
+pub struct RaycastHit {
+    pub entity_id: u32,
+    pub point: Vector3<f32>,
+    pub normal: Vector3<f32>, // surface normal at the hit, points back out towards where the ray came from
+    pub distance: f32,
+    pub child: Option<usize>, // which child of a compound got hit
+}
+
+/// Distance along `direction` (normalized) to where the ray first enters `shape`, and the surface normal there
+pub fn ray_shape(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+    shape: &CollisionShape,
+    position: Vector3<f32>,
+    rotation: Quaternion<f32>,
+) -> Option<(f32, Vector3<f32>)> {
+    match shape {
+        CollisionShape::Sphere { radius } => ray_sphere(origin - position, direction, max_distance, *radius),
+        CollisionShape::Box { width, height, depth } => {
+            let half_extents = Vector3::new(width / 2.0, height / 2.0, depth / 2.0);
+            ray_box(origin, direction, max_distance, half_extents, position, rotation)
+        },
+        CollisionShape::OBB { half_extents, .. } => ray_box(origin, direction, max_distance, *half_extents, position, rotation),
+        CollisionShape::Capsule { radius, half_height } => {
+            let to_local = rotation.invert();
+            let local_origin = to_local.rotate_vector(origin - position);
+            let local_direction = to_local.rotate_vector(direction);
+            let (distance, normal) = ray_capsule(local_origin, local_direction, max_distance, *radius, *half_height)?;
+            Some((distance, rotation.rotate_vector(normal)))
+        },
+        // flat 2d shapes, no ray queries for those
+        CollisionShape::Circle { .. } | CollisionShape::Rectangle { .. } => None,
+        // the collision system rays each child on its own so it can say which one got hit
+        CollisionShape::Compound(..) => None,
+        CollisionShape::Heightfield(heightfield) => {
+            let to_local = rotation.invert();
+            let (distance, normal) = heightfield.raycast(to_local.rotate_vector(origin - position), to_local.rotate_vector(direction), max_distance)?;
+            Some((distance, rotation.rotate_vector(normal)))
+        },
+        CollisionShape::ConvexHull(hull) => {
+            let to_local = rotation.invert();
+            let (distance, normal) = hull.raycast(to_local.rotate_vector(origin - position), to_local.rotate_vector(direction), max_distance)?;
+            Some((distance, rotation.rotate_vector(normal)))
+        },
+    }
+}
+
+//`origin` is relative to the spheres center
+fn ray_sphere(origin: Vector3<f32>, direction: Vector3<f32>, max_distance: f32, radius: f32) -> Option<(f32, Vector3<f32>)> {
+    let c = origin.magnitude2() - radius * radius;
+    let b = origin.dot(direction);
+    if c <= 0.0 || b >= 0.0 {
+        return None;
+    }
+    let discriminant = b * b - c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let distance = -b - discriminant.sqrt();
+    (distance <= max_distance).then(|| (distance, (origin + direction * distance).normalize()))
+}
+
+//slab test in the boxes own space, the slab we went in through last is the face we hit
+fn ray_box(
+    origin: Vector3<f32>,
+    direction: Vector3<f32>,
+    max_distance: f32,
+    half_extents: Vector3<f32>,
+    box_position: Vector3<f32>,
+    box_rotation: Quaternion<f32>,
+) -> Option<(f32, Vector3<f32>)> {
+    let to_local = box_rotation.invert();
+    let origin = to_local.rotate_vector(origin - box_position);
+    let direction = to_local.rotate_vector(direction);
+
+    let mut enter: f32 = f32::NEG_INFINITY;
+    let mut exit: f32 = max_distance;
+    let mut normal = Vector3::zero();
+    for axis in 0..3 {
+        if direction[axis].abs() < 1e-8 {
+            if origin[axis].abs() > half_extents[axis] {
+                return None;
+            }
+            continue;
+        }
+        let t1 = (-half_extents[axis] - origin[axis]) / direction[axis];
+        let t2 = (half_extents[axis] - origin[axis]) / direction[axis];
+        if t1.min(t2) > enter {
+            enter = t1.min(t2);
+            normal = Vector3::zero();
+            normal[axis] = -direction[axis].signum();
+        }
+        exit = exit.min(t1.max(t2));
+        if enter > exit {
+            return None;
+        }
+    }
+
+    // started inside (or every axis was parallel and inside)
+    if enter < 0.0 {
+        return None;
+    }
+    Some((enter, box_rotation.rotate_vector(normal)))
+}
+
+//capsule stood up along y in its own space, its the tube in the middle and a sphere on each end, whichever gets hit first
+fn ray_capsule(origin: Vector3<f32>, direction: Vector3<f32>, max_distance: f32, radius: f32, half_height: f32) -> Option<(f32, Vector3<f32>)> {
+    let closest_y = origin.y.clamp(-half_height, half_height);
+    if (origin - Vector3::new(0.0, closest_y, 0.0)).magnitude2() <= radius * radius {
+        return None;
+    }
+
+    let mut best: Option<(f32, Vector3<f32>)> = None;
+    let mut keep = |hit: Option<(f32, Vector3<f32>)>| {
+        if let Some(hit) = hit {
+            if best.is_none_or(|(distance, _)| hit.0 < distance) {
+                best = Some(hit);
+            }
+        }
+    };
+
+    // the tube, only counts between the two caps
+    let a = direction.x * direction.x + direction.z * direction.z;
+    if a > 1e-8 {
+        let b = origin.x * direction.x + origin.z * direction.z;
+        let c = origin.x * origin.x + origin.z * origin.z - radius * radius;
+        let discriminant = b * b - a * c;
+        if discriminant >= 0.0 {
+            let distance = (-b - discriminant.sqrt()) / a;
+            let hit = origin + direction * distance;
+            if distance >= 0.0 && distance <= max_distance && hit.y.abs() <= half_height {
+                keep(Some((distance, Vector3::new(hit.x, 0.0, hit.z).normalize())));
+            }
+        }
+    }
+
+    for cap in [half_height, -half_height] {
+        let center = Vector3::new(0.0, cap, 0.0);
+        keep(ray_sphere(origin - center, direction, max_distance, radius));
+    }
+
+    best
+}
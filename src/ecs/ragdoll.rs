@@ -0,0 +1,423 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Zero};
+
+use super::collision_system::{Collider, CollisionSystem};
+use super::components::Velocity;
+use super::joints::Joint;
+use super::physics::{PhysicsEntity, PhysicsSystem};
+use super::world::MovementSystem;
+use crate::model::transform::WorldCoords;
+
+//ragdolls are just a capsule rigidbody per bone with a joint between each bone and its parent, nothing special
+//happens in the solver for them. Each bone gets built standing along its direction in the skeletons model space
+//(y up, forward down -z) so the rest pose is whatever the skeleton describes and the joint limits are measured from it
+//while animated every body is kinematic and gets dragged to the animation, going limp hands them back to physics
+//with whatever velocity they had so the fall picks up the motion
+
+/// How a bone can turn relative to its parent
+#[derive(Debug, Clone, Copy)]
+pub enum BoneJoint {
+    /// tips up to `swing` radians away from its rest direction any way, twisting around itself between the twist limits
+    ConeTwist { swing: f32, twist: (f32, f32) },
+    /// turns around `axis` (model space) only, `limits` is how far the bone can go around it from the rest pose, right hand rule
+    Hinge { axis: Vector3<f32>, limits: (f32, f32) },
+}
+
+#[derive(Debug, Clone)]
+pub struct Bone {
+    pub name: String,
+    pub parent: Option<String>,
+    pub length: f32,
+    pub radius: f32,
+    pub mass: f32,
+    pub direction: Vector3<f32>, // which way it points in the rest pose, model space
+    pub offset: Vector3<f32>,    // where it starts, from the end of its parent (or the ragdolls origin for a root)
+    pub joint: BoneJoint,
+}
+
+impl Bone {
+    /// Bone pointing up out of the end of its parent, loose 45 degree cone until `with_joint` says otherwise
+    pub fn new(name: &str, parent: Option<&str>, length: f32, radius: f32, mass: f32) -> Self {
+        Self {
+            name: name.to_string(),
+            parent: parent.map(|parent| parent.to_string()),
+            length,
+            radius,
+            mass,
+            direction: Vector3::unit_y(),
+            offset: Vector3::zero(),
+            joint: BoneJoint::ConeTwist { swing: std::f32::consts::FRAC_PI_4, twist: (-0.5, 0.5) },
+        }
+    }
+
+    pub fn with_direction(mut self, direction: Vector3<f32>) -> Self {
+        self.direction = direction.normalize();
+        self
+    }
+
+    pub fn with_offset(mut self, offset: Vector3<f32>) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    pub fn with_joint(mut self, joint: BoneJoint) -> Self {
+        self.joint = joint;
+        self
+    }
+
+    pub fn with_cone(self, swing: f32, twist: (f32, f32)) -> Self {
+        self.with_joint(BoneJoint::ConeTwist { swing, twist })
+    }
+
+    pub fn with_hinge(self, axis: Vector3<f32>, lower: f32, upper: f32) -> Self {
+        self.with_joint(BoneJoint::Hinge { axis: axis.normalize(), limits: (lower, upper) })
+    }
+}
+
+/// Bones in order, parents have to come before their children
+#[derive(Debug, Clone, Default)]
+pub struct Skeleton {
+    pub bones: Vec<Bone>,
+}
+
+impl Skeleton {
+    pub fn new() -> Self {
+        Self { bones: Vec::new() }
+    }
+
+    pub fn with_bone(mut self, bone: Bone) -> Self {
+        self.bones.push(bone);
+        self
+    }
+
+    /// A person `height` tall standing with their feet at the origin, arms hanging down. Weighs about 70kg at 1.8m
+    pub fn humanoid(height: f32) -> Self {
+        let scale = height / 1.8;
+        let mass = |kg: f32| kg * scale * scale * scale;
+        let down = Vector3::new(0.0, -1.0, 0.0);
+        let mut skeleton = Self::new()
+            .with_bone(Bone::new("pelvis", None, 0.15 * scale, 0.12 * scale, mass(12.0)).with_offset(Vector3::new(0.0, 0.92 * scale, 0.0)))
+            .with_bone(Bone::new("spine", Some("pelvis"), 0.35 * scale, 0.13 * scale, mass(20.0)).with_cone(0.5, (-0.4, 0.4)))
+            .with_bone(Bone::new("head", Some("spine"), 0.22 * scale, 0.1 * scale, mass(5.0)).with_cone(0.6, (-0.8, 0.8)));
+
+        for (side, x) in [("left", -1.0), ("right", 1.0)] {
+            skeleton = skeleton
+                .with_bone(
+                    Bone::new(&format!("{}_upper_arm", side), Some("spine"), 0.3 * scale, 0.05 * scale, mass(2.5))
+                        .with_direction(down)
+                        .with_offset(Vector3::new(0.23 * x * scale, -0.05 * scale, 0.0))
+                        .with_cone(1.4, (-1.0, 1.0)),
+                )
+                .with_bone(
+                    Bone::new(&format!("{}_forearm", side), Some(&format!("{}_upper_arm", side)), 0.28 * scale, 0.045 * scale, mass(1.8))
+                        .with_direction(down)
+                        .with_hinge(Vector3::unit_x(), 0.0, 2.4), // forward
+                )
+                .with_bone(
+                    Bone::new(&format!("{}_thigh", side), Some("pelvis"), 0.45 * scale, 0.07 * scale, mass(8.0))
+                        .with_direction(down)
+                        .with_offset(Vector3::new(0.1 * x * scale, -0.15 * scale, 0.0))
+                        .with_cone(1.0, (-0.5, 0.5)),
+                )
+                .with_bone(
+                    Bone::new(&format!("{}_shin", side), Some(&format!("{}_thigh", side)), 0.45 * scale, 0.055 * scale, mass(4.0))
+                        .with_direction(down)
+                        .with_hinge(-Vector3::unit_x(), 0.0, 2.4), // backwards
+                );
+        }
+        skeleton
+    }
+
+    pub fn bone_index(&self, name: &str) -> Option<usize> {
+        self.bones.iter().position(|bone| bone.name == name)
+    }
+
+    /// Errors on duplicate names, missing parents, parents listed after their children and bones with no size
+    pub fn validate(&self) -> Result<(), String> {
+        if self.bones.is_empty() {
+            return Err("skeleton has no bones".to_string());
+        }
+        for (index, bone) in self.bones.iter().enumerate() {
+            if self.bones[..index].iter().any(|other| other.name == bone.name) {
+                return Err(format!("skeleton has two bones called {}", bone.name));
+            }
+            if let Some(parent) = &bone.parent {
+                if !self.bones[..index].iter().any(|other| &other.name == parent) {
+                    return Err(format!("bone {} has parent {} which isnt listed before it", bone.name, parent));
+                }
+            }
+            if bone.length <= 0.0 || bone.radius <= 0.0 || bone.mass <= 0.0 {
+                return Err(format!("bone {} needs a positive length, radius and mass", bone.name));
+            }
+        }
+        Ok(())
+    }
+
+    //where each bone starts and how its rotated in model space, the capsule stands along its local y
+    fn rest_pose(&self) -> Vec<BonePose> {
+        let mut pose: Vec<BonePose> = Vec::with_capacity(self.bones.len());
+        for bone in &self.bones {
+            let parent_end = bone.parent.as_ref()
+                .and_then(|parent| self.bone_index(parent))
+                .map(|parent| pose[parent].position + self.bones[parent].direction * self.bones[parent].length)
+                .unwrap_or(Vector3::zero());
+            pose.push(BonePose {
+                position: parent_end + bone.offset,
+                rotation: Quaternion::from_arc(Vector3::unit_y(), bone.direction, None),
+            });
+        }
+        pose
+    }
+}
+
+/// One bone of a pose, `position` is where the bone starts and `rotation` turns local +y onto the way it points,
+/// the same as the capsules rigidbody
+#[derive(Debug, Clone, Copy)]
+pub struct BonePose {
+    pub position: Vector3<f32>,
+    pub rotation: Quaternion<f32>,
+}
+
+impl BonePose {
+    pub fn new(position: Vector3<f32>, rotation: Quaternion<f32>) -> Self {
+        Self { position, rotation }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RagdollBone {
+    pub name: String,
+    pub entity_id: u32,
+    pub joint_id: Option<u32>, // the joint to its parent, none for the root
+    pub length: f32,
+}
+
+/// The bodies and joints one skeleton turned into. Starts out simulated, `follow_pose` puts it back under animation
+#[derive(Debug, Clone)]
+pub struct Ragdoll {
+    pub bones: Vec<RagdollBone>,
+    blend: f32,       // 0 is all animation, 1 is all physics
+    blend_speed: f32, // how much blend goes up per second after going limp
+    simulated: bool,
+}
+
+impl Ragdoll {
+    /// Makes a capsule body for every bone on `entity_ids` (one per bone, same order) and joins each to its parent.
+    /// The skeleton gets turned by `rotation` and its origin put at `position`. The entities shouldnt have
+    /// colliders or rigidbodies yet, this adds them
+    pub fn new(
+        skeleton: &Skeleton,
+        entity_ids: &[u32],
+        position: Vector3<f32>,
+        rotation: Quaternion<f32>,
+        movement_system: &mut MovementSystem,
+        collision_system: &mut CollisionSystem,
+        physics_system: &mut PhysicsSystem,
+    ) -> Result<Self, String> {
+        skeleton.validate()?;
+        if entity_ids.len() != skeleton.bones.len() {
+            return Err(format!("skeleton has {} bones but got {} entities", skeleton.bones.len(), entity_ids.len()));
+        }
+
+        let rest = skeleton.rest_pose();
+        let world: Vec<BonePose> = rest.iter()
+            .map(|bone| BonePose::new(position + rotation.rotate_vector(bone.position), rotation * bone.rotation))
+            .collect();
+
+        let mut bones = Vec::with_capacity(skeleton.bones.len());
+        for (index, bone) in skeleton.bones.iter().enumerate() {
+            let entity_id = entity_ids[index];
+            let pose = world[index];
+            let mut coords = WorldCoords::new_empty();
+            coords.position = pose.position + pose.rotation.rotate_vector(Vector3::unit_y() * (bone.length / 2.0));
+            coords.set_rotation_from_quaternion(pose.rotation);
+            movement_system.add_coords(entity_id, coords);
+            movement_system.add_velocity(entity_id, Velocity { direction: Vector3::zero(), speed: 0.0 });
+
+            // a bone shorter than it is wide is just a ball
+            let collider = Collider::capsule(bone.radius, bone.length.max(bone.radius * 2.0));
+            physics_system.add_rigidbody(entity_id, PhysicsEntity::from_collider(bone.mass, &collider));
+            collision_system.add_collider(entity_id, collider);
+
+            //the joint sits where the child starts, anchors are from each bodies center in its own space
+            let parent = bone.parent.as_ref().and_then(|parent| skeleton.bone_index(parent));
+            let joint_id = parent.map(|parent| {
+                let parent_pose = world[parent];
+                let parent_center = parent_pose.position + parent_pose.rotation.rotate_vector(Vector3::unit_y() * (skeleton.bones[parent].length / 2.0));
+                let anchor_a = parent_pose.rotation.invert().rotate_vector(pose.position - parent_center);
+                let anchor_b = Vector3::unit_y() * (-bone.length / 2.0);
+                let parent_id = entity_ids[parent];
+                let joint = match bone.joint {
+                    BoneJoint::ConeTwist { swing, twist } => {
+                        let axis = parent_pose.rotation.invert().rotate_vector(pose.rotation.rotate_vector(Vector3::unit_y()));
+                        Joint::cone_twist(parent_id, Some(entity_id), anchor_a, anchor_b, axis, swing).with_limits(twist.0, twist.1)
+                    },
+                    BoneJoint::Hinge { axis, limits } => {
+                        // the joint measures the parent turning against the child so the limits flip
+                        let axis = parent_pose.rotation.invert().rotate_vector(rotation.rotate_vector(axis));
+                        Joint::hinge(parent_id, Some(entity_id), anchor_a, anchor_b, axis).with_limits(-limits.1, -limits.0)
+                    },
+                };
+                physics_system.add_joint(
+                    joint
+                        .with_collide_connected(false)
+                        .with_reference_rotation(parent_pose.rotation.invert() * pose.rotation),
+                )
+            });
+
+            bones.push(RagdollBone { name: bone.name.clone(), entity_id, joint_id, length: bone.length });
+        }
+
+        Ok(Self { bones, blend: 1.0, blend_speed: 0.0, simulated: true })
+    }
+
+    pub fn bone_entity(&self, name: &str) -> Option<u32> {
+        self.bones.iter().find(|bone| bone.name == name).map(|bone| bone.entity_id)
+    }
+
+    pub fn is_simulated(&self) -> bool {
+        self.simulated
+    }
+
+    /// 0 while animated, climbs to 1 after going limp, `blended_pose` uses it
+    pub fn blend(&self) -> f32 {
+        self.blend
+    }
+
+    /// Drags every bone to `pose` (world space, one per bone in order) over `delta_time`, call it each frame before
+    /// the physics step. The bodies turn kinematic so they shove things around but nothing shoves them
+    pub fn follow_pose(&mut self, pose: &[BonePose], delta_time: f32, movement_system: &mut MovementSystem, physics_system: &mut PhysicsSystem) {
+        self.simulated = false;
+        self.blend = 0.0;
+        if delta_time <= 0.0 {
+            return;
+        }
+        for (bone, target) in self.bones.iter().zip(pose) {
+            let center = target.position + target.rotation.rotate_vector(Vector3::unit_y() * (bone.length / 2.0));
+            let Some(coords) = movement_system.get_coords_mut(bone.entity_id) else { continue };
+
+            // the rotation gets set straight away, the spin is only remembered so contacts and going limp see it
+            let mut turn = target.rotation * coords.rotation.invert();
+            if turn.s < 0.0 {
+                turn = -turn;
+            }
+            let angular = if turn.v.magnitude2() > 1e-12 {
+                turn.v.normalize() * (2.0 * turn.v.magnitude().atan2(turn.s) / delta_time)
+            } else {
+                Vector3::zero()
+            };
+            let linear = (center - coords.position) / delta_time;
+            coords.set_rotation_from_quaternion(target.rotation);
+
+            if let Some(velocity) = movement_system.get_velocity_mut(bone.entity_id) {
+                velocity.set_linear(linear);
+            }
+            if let Some(rigidbody) = physics_system.get_rigidbody_mut(bone.entity_id) {
+                rigidbody.is_kinematic = true;
+                rigidbody.angular_velocity = angular;
+            }
+        }
+    }
+
+    /// Hands every bone over to physics, keeping the speed the animation had them moving at.
+    /// `blend_time` is how long `blended_pose` takes to go from the last animated pose to the simulated one
+    pub fn go_limp(&mut self, blend_time: f32, physics_system: &mut PhysicsSystem) {
+        for bone in &self.bones {
+            if let Some(rigidbody) = physics_system.get_rigidbody_mut(bone.entity_id) {
+                rigidbody.is_kinematic = false;
+            }
+            physics_system.wake_up(bone.entity_id);
+        }
+        self.simulated = true;
+        if blend_time > 0.0 {
+            self.blend_speed = 1.0 / blend_time;
+        } else {
+            self.blend = 1.0;
+        }
+    }
+
+    /// Moves the blend along, once per frame
+    pub fn update(&mut self, delta_time: f32) {
+        if self.simulated {
+            self.blend = (self.blend + self.blend_speed * delta_time).min(1.0);
+        }
+    }
+
+    /// Where the bodies are right now, one per bone in order
+    pub fn pose(&self, movement_system: &MovementSystem) -> Vec<BonePose> {
+        self.bones.iter().map(|bone| {
+            movement_system.get_coords(bone.entity_id)
+                .map(|coords| BonePose::new(coords.position - coords.rotation.rotate_vector(Vector3::unit_y() * (bone.length / 2.0)), coords.rotation))
+                .unwrap_or(BonePose::new(Vector3::zero(), Quaternion::new(1.0, 0.0, 0.0, 0.0)))
+        }).collect()
+    }
+
+    /// `animated` mixed into the simulated pose by how far the blend has got, what to draw while going limp
+    pub fn blended_pose(&self, animated: &[BonePose], movement_system: &MovementSystem) -> Vec<BonePose> {
+        let t = self.blend;
+        self.pose(movement_system).into_iter().zip(animated).map(|(simulated, animated)| {
+            // same hemisphere or the nlerp goes the long way round
+            let target = if animated.rotation.dot(simulated.rotation) < 0.0 { -simulated.rotation } else { simulated.rotation };
+            BonePose::new(
+                animated.position + (simulated.position - animated.position) * t,
+                (animated.rotation * (1.0 - t) + target * t).normalize(),
+            )
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Quaternion, Rad, Rotation, Rotation3, Vector3};
+
+    use crate::ecs::collision_system::{Collider, CollisionSystem};
+    use crate::ecs::physics::{PhysicsEntity, PhysicsSystem};
+    use crate::ecs::ragdoll::{Ragdoll, Skeleton};
+    use crate::ecs::world::MovementSystem;
+    use crate::model::transform::WorldCoords;
+
+    #[test]
+    fn dropped_ragdoll_settles() {
+        let mut movement = MovementSystem::new();
+        let mut physics = PhysicsSystem::new();
+        let mut collision = CollisionSystem::new();
+
+        movement.add_coords(0, WorldCoords::new(0.0, -0.5, 0.0, 0.0));
+        collision.add_collider(0, Collider::bounding_box(20.0, 1.0, 20.0));
+        physics.add_rigidbody(0, PhysicsEntity::static_body());
+
+        // tipped over a bit and dropped from 2m so it lands on a shoulder and has to fold up,
+        // capsules roll for a while before they stop so it gets 15 seconds
+        let skeleton = Skeleton::humanoid(1.8);
+        let entity_ids: Vec<u32> = (1..=skeleton.bones.len() as u32).collect();
+        let rotation = Quaternion::from_angle_z(Rad(0.6)) * Quaternion::from_angle_x(Rad(0.3));
+        let ragdoll = Ragdoll::new(&skeleton, &entity_ids, Vector3::new(0.0, 2.0, 0.0), rotation, &mut movement, &mut collision, &mut physics)
+            .expect("humanoid skeleton should be valid");
+
+        let delta_time = 1.0 / 60.0;
+        for _ in 0..900 {
+            physics.update(&mut movement, delta_time);
+            movement.update(delta_time);
+            collision.update(&mut movement, &mut physics, delta_time);
+        }
+
+        let pose = ragdoll.pose(&movement);
+        for ((bone, ragdoll_bone), bone_pose) in skeleton.bones.iter().zip(&ragdoll.bones).zip(&pose) {
+            let position = movement.get_coords(ragdoll_bone.entity_id).unwrap().position;
+            assert!(position.x.is_finite() && position.y.is_finite() && position.z.is_finite(), "{} blew up", bone.name);
+            assert!(position.y > 0.0 && position.y < 0.5, "{} ended up at height {}", bone.name, position.y);
+            assert!(position.x.abs() < 3.0 && position.z.abs() < 3.0, "{} flew off to {:?}", bone.name, position);
+            assert!(movement.get_velocity(ragdoll_bone.entity_id).unwrap().speed < 0.05, "{} is still moving", bone.name);
+            assert!(physics.get_rigidbody(ragdoll_bone.entity_id).unwrap().is_sleeping(), "{} never went to sleep", bone.name);
+
+            // every bone should still start where its parent says it does
+            if let Some(parent) = bone.parent.as_ref().and_then(|parent| skeleton.bone_index(parent)) {
+                let parent_end = pose[parent].position + pose[parent].rotation.rotate_vector(Vector3::unit_y() * skeleton.bones[parent].length);
+                let joint = parent_end + pose[parent].rotation.rotate_vector(
+                    Quaternion::from_arc(Vector3::unit_y(), skeleton.bones[parent].direction, None).invert().rotate_vector(bone.offset),
+                );
+                let gap = (bone_pose.position - joint).magnitude();
+                assert!(gap < 0.05, "{} came {} away from its parent", bone.name, gap);
+            }
+        }
+    }
+}
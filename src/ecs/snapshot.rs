@@ -0,0 +1,106 @@
+use std::collections::VecDeque;
+
+use cgmath::{Quaternion, Vector3};
+
+use super::contact_manifold::ContactManifold;
+use super::joints::Joint;
+use super::physics::PhysicsEntity;
+
+//everything the physics step reads that it also wrote last step, copy it out and put it back and the next step
+//comes out exactly like it did the first time (with deterministic mode on anyway)
+//its all plain copies in flat sorted vecs so taking one every frame is just a few allocations
+
+/// One rigidbody and the transform/velocity of its entity
+#[derive(Debug, Clone)]
+pub(crate) struct BodyState {
+    pub entity_id: u32,
+    pub rigidbody: PhysicsEntity, // has the angular velocity, accumulated force/impulse and sleep state in it
+    pub coords: Option<(Vector3<f32>, Quaternion<f32>, Vector3<f32>)>, // position, rotation, scale
+    pub velocity: Option<(Vector3<f32>, f32)>,                           // direction, speed
+}
+
+/// Cached contacts and overlapping pairs from the collision system, the warm starting and enter/exit events depend on them
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ContactCache {
+    pub manifolds: Vec<((u32, u32), ContactManifold)>,
+    pub overlapping: Vec<((u32, u32), Option<u32>)>,
+}
+
+/// The whole physics world at one moment, from `PhysicsSystem::snapshot`, handed back to `PhysicsSystem::restore` to rewind
+#[derive(Debug, Clone)]
+pub struct PhysicsSnapshot {
+    pub(crate) bodies: Vec<BodyState>,
+    pub(crate) joints: Vec<(u32, Joint)>,
+    pub(crate) next_joint_id: u32,
+    pub(crate) accumulator: f32,
+    pub(crate) time: f32,
+    pub(crate) water_time: Option<f32>,
+    pub(crate) contacts: ContactCache,
+}
+
+impl PhysicsSnapshot {
+    pub fn body_count(&self) -> usize {
+        self.bodies.len()
+    }
+
+    pub fn contains(&self, entity_id: u32) -> bool {
+        self.bodies.binary_search_by_key(&entity_id, |body| body.entity_id).is_ok()
+    }
+}
+
+/// The last few snapshots, oldest get dropped once its full. 8 frames is enough for most rollback and undo
+pub struct SnapshotHistory {
+    snapshots: VecDeque<PhysicsSnapshot>,
+    capacity: usize,
+}
+
+impl Default for SnapshotHistory {
+    fn default() -> Self {
+        Self::new(8)
+    }
+}
+
+impl SnapshotHistory {
+    pub fn new(capacity: usize) -> Self {
+        assert!(capacity > 0, "snapshot history needs room for at least one snapshot");
+        Self { snapshots: VecDeque::with_capacity(capacity), capacity }
+    }
+
+    pub fn push(&mut self, snapshot: PhysicsSnapshot) {
+        if self.snapshots.len() == self.capacity {
+            self.snapshots.pop_front();
+        }
+        self.snapshots.push_back(snapshot);
+    }
+
+    /// `frames_back` 0 is the newest one
+    pub fn get(&self, frames_back: usize) -> Option<&PhysicsSnapshot> {
+        self.snapshots.len().checked_sub(frames_back + 1).and_then(|index| self.snapshots.get(index))
+    }
+
+    /// Throws away the `frames_back` newest snapshots and hands back the one before them, which stays in the history
+    /// so stepping forward again from it can push on top
+    pub fn rewind(&mut self, frames_back: usize) -> Option<&PhysicsSnapshot> {
+        if frames_back >= self.snapshots.len() {
+            return None;
+        }
+        self.snapshots.truncate(self.snapshots.len() - frames_back);
+        self.snapshots.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.snapshots.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.snapshots.is_empty()
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    pub fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
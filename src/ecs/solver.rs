@@ -0,0 +1,550 @@
+use std::collections::HashMap;
+use cgmath::{InnerSpace, Matrix3, One, Quaternion, Rad, Rotation3, Vector3, Zero};
+
+use super::contact_manifold::ContactManifold;
+use super::joints::{Joint, JointFrame, JointRow};
+use super::physics::PhysicsEntity;
+use super::stats::PhysicsStats;
+use super::world::{ComponentStorage, MovementSystem};
+
+//sequential impulses, the same idea box2d uses
+//every contact of the step gets solved a little bit at a time over a bunch of iterations so the impulses
+//can travel up and down a stack, solving one pair at a time just makes the bottom box eat everything
+
+#[derive(Debug, Clone, Copy)]
+pub struct SolverSettings {
+    pub velocity_iterations: u32,
+    pub position_iterations: u32,
+    pub correction_percent: f32, // how much of the remaining penetration each position iteration removes
+    pub slop: f32,               // penetration we let slide so resting contacts dont flicker in and out
+    pub max_correction: f32,     // biggest push a single contact gets per position iteration
+    pub restitution_threshold: f32, // below this approach speed contacts dont bounce
+    pub static_friction_threshold: f32, // contacts sliding slower than this use static friction, faster ones dynamic
+    pub joint_correction: f32,   // how much of a joints remaining position error each position iteration removes
+    pub warm_starting: bool,
+}
+
+impl Default for SolverSettings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SolverSettings {
+    pub fn new() -> Self {
+        Self {
+            velocity_iterations: 10,
+            // the position pass turns bodies as well as moving them, so part of every push goes into spin and a
+            // stack needs more (and stronger) iterations to climb back out. At 4 and 0.4 a ten box stack sinks about 0.1
+            position_iterations: 10,
+            correction_percent: 0.8,
+            slop: 0.005,
+            max_correction: 0.2,
+            restitution_threshold: 0.5,
+            static_friction_threshold: 0.1,
+            joint_correction: 0.2,
+            warm_starting: true,
+        }
+    }
+
+    pub fn with_velocity_iterations(mut self, iterations: u32) -> Self {
+        self.velocity_iterations = iterations;
+        self
+    }
+
+    pub fn with_position_iterations(mut self, iterations: u32) -> Self {
+        self.position_iterations = iterations;
+        self
+    }
+
+    pub fn with_correction(mut self, percent: f32, slop: f32) -> Self {
+        self.correction_percent = percent;
+        self.slop = slop;
+        self
+    }
+
+    pub fn with_warm_starting(mut self, enabled: bool) -> Self {
+        self.warm_starting = enabled;
+        self
+    }
+}
+
+//one side of a contact while the solver runs, velocities live here until everything is done
+//bodies without a rigidbody (or static ones) act like immovable walls
+struct SolverBody {
+    entity_id: u32,
+    position: Vector3<f32>,
+    rotation: Quaternion<f32>,
+    inv_mass: f32,
+    inv_inertia: Matrix3<f32>, // world space, already turned with the body
+    linear: Vector3<f32>,
+    angular: Vector3<f32>,
+    can_rotate: bool,
+    shift: Vector3<f32>, // how far the position pass has moved this body
+    turn: Vector3<f32>,  // and how far it has turned it (axis * angle)
+}
+
+impl SolverBody {
+    fn new(entity_id: u32, rigidbody: Option<&PhysicsEntity>, movement_system: &MovementSystem) -> Self {
+        let position = movement_system.get_coords(entity_id).map(|c| c.position).unwrap_or(Vector3::zero());
+        let rotation = movement_system.get_coords(entity_id).map(|c| c.rotation).unwrap_or(Quaternion::one());
+        let linear = movement_system.get_velocity(entity_id).map(|v| v.linear()).unwrap_or(Vector3::zero());
+
+        let mut body = Self {
+            entity_id,
+            position,
+            rotation,
+            inv_mass: 0.0,
+            inv_inertia: Matrix3::zero(),
+            linear: Vector3::zero(),
+            angular: Vector3::zero(),
+            can_rotate: false,
+            shift: Vector3::zero(),
+            turn: Vector3::zero(),
+        };
+
+        if let Some(rb) = rigidbody.filter(|rb| !rb.is_static()) {
+            body.inv_mass = rb.inverse_mass;
+            body.inv_inertia = rb.world_inverse_inertia(rotation);
+            body.linear = linear;
+            body.angular = rb.angular_velocity;
+            body.can_rotate = !rb.lock_rotation && !rb.is_kinematic;
+        }
+        body
+    }
+
+    fn point_velocity(&self, r: Vector3<f32>) -> Vector3<f32> {
+        if self.can_rotate {
+            self.linear + self.angular.cross(r)
+        } else {
+            self.linear
+        }
+    }
+
+    fn angular_change(&self, r: Vector3<f32>, impulse: Vector3<f32>) -> Vector3<f32> {
+        if !self.can_rotate {
+            return Vector3::zero();
+        }
+        self.inv_inertia * r.cross(impulse)
+    }
+
+    //how much of a unit impulse along `direction` at `r` goes into spin instead of moving the point
+    fn angular_mass(&self, r: Vector3<f32>, direction: Vector3<f32>) -> f32 {
+        self.angular_change(r, direction).cross(r).dot(direction)
+    }
+
+    //an impulse at r off the center of mass pushes and spins, this is what gets a box hit on the corner tumbling
+    fn apply_impulse(&mut self, r: Vector3<f32>, impulse: Vector3<f32>) {
+        self.linear += impulse * self.inv_mass;
+        self.angular += self.angular_change(r, impulse);
+    }
+
+    //joint rows come with their own angular part already worked out
+    fn apply_row(&mut self, linear: Vector3<f32>, angular: Vector3<f32>) {
+        self.linear += linear * self.inv_mass;
+        if self.can_rotate {
+            self.angular += self.inv_inertia * angular;
+        }
+    }
+
+    fn row_mass(&self, linear: Vector3<f32>, angular: Vector3<f32>) -> f32 {
+        let rotational = if self.can_rotate { angular.dot(self.inv_inertia * angular) } else { 0.0 };
+        linear.magnitude2() * self.inv_mass + rotational
+    }
+
+    fn frame(&self) -> JointFrame {
+        JointFrame { position: self.position, rotation: self.rotation }
+    }
+
+    //same thing as apply_impulse but for the position pass, moves and turns the body instead of changing velocity
+    fn apply_push(&mut self, r: Vector3<f32>, push: Vector3<f32>) {
+        self.shift += push * self.inv_mass;
+        self.turn += self.angular_change(r, push);
+    }
+
+    //apply_row for the position pass
+    fn apply_row_push(&mut self, linear: Vector3<f32>, angular: Vector3<f32>) {
+        self.shift += linear * self.inv_mass;
+        if self.can_rotate {
+            self.turn += self.inv_inertia * angular;
+        }
+    }
+
+    //how far the point at r has moved so far in the position pass (small angle so turn x r is close enough)
+    fn displacement(&self, r: Vector3<f32>) -> Vector3<f32> {
+        self.shift + self.turn.cross(r)
+    }
+}
+
+//a single contact point prepared for solving, the impulses get copied back onto the manifold at the end
+struct ContactConstraint {
+    manifold: usize,
+    point: usize,
+    body_a: usize,
+    body_b: usize,
+    r_a: Vector3<f32>,
+    r_b: Vector3<f32>,
+    normal: Vector3<f32>,
+    tangents: [Vector3<f32>; 2],
+    surface: Vector3<f32>, // the sliding speed friction aims for instead of zero, from a contact modifier
+    normal_mass: f32,
+    tangent_mass: [f32; 2],
+    bounce: f32,
+    friction: f32,
+    normal_impulse: f32,
+    tangent_impulse: [f32; 2],
+}
+
+//a joint row ready to go, same idea as ContactConstraint
+struct JointConstraint {
+    joint: usize,
+    body_a: usize,
+    body_b: usize,
+    row: JointRow,
+    mass: f32,
+    push: f32, // accumulated over the position pass, separate from the impulse so the bounds apply to it on its own
+}
+
+impl JointConstraint {
+    fn solve(&mut self, bodies: &mut [SolverBody]) {
+        let (a, b) = (&bodies[self.body_a], &bodies[self.body_b]);
+        let row = &mut self.row;
+        let velocity = row.linear_a.dot(a.linear) + row.angular_a.dot(a.angular)
+            + row.linear_b.dot(b.linear) + row.angular_b.dot(b.angular);
+        let lambda = -(velocity + row.bias + row.softness * row.impulse) * self.mass;
+
+        let old_impulse = row.impulse;
+        row.impulse = (old_impulse + lambda).clamp(row.lower, row.upper);
+        let applied = row.impulse - old_impulse;
+
+        self.apply(bodies, applied);
+    }
+
+    fn apply(&self, bodies: &mut [SolverBody], impulse: f32) {
+        bodies[self.body_a].apply_row(self.row.linear_a * impulse, self.row.angular_a * impulse);
+        bodies[self.body_b].apply_row(self.row.linear_b * impulse, self.row.angular_b * impulse);
+    }
+
+    //the position pass version, the error is worked out again from how far the bodies have been moved so far
+    fn correct(&mut self, bodies: &mut [SolverBody], settings: &SolverSettings) {
+        let Some(error) = self.row.error else { return };
+        let (a, b) = (&bodies[self.body_a], &bodies[self.body_b]);
+        let row = &self.row;
+        let moved = row.linear_a.dot(a.shift) + row.angular_a.dot(a.turn) + row.linear_b.dot(b.shift) + row.angular_b.dot(b.turn);
+        let correction = ((error + moved) * settings.joint_correction).clamp(-settings.max_correction, settings.max_correction);
+
+        let old_push = self.push;
+        self.push = (old_push - correction * self.mass).clamp(row.lower, row.upper);
+        let applied = self.push - old_push;
+
+        bodies[self.body_a].apply_row_push(self.row.linear_a * applied, self.row.angular_a * applied);
+        bodies[self.body_b].apply_row_push(self.row.linear_b * applied, self.row.angular_b * applied);
+    }
+}
+
+// stands in for the world on joints that are pinned to it
+const WORLD_BODY: u32 = u32::MAX;
+
+/// Solve every contact and joint of the step together
+/// velocities are iterated first and written back, then contacts get pushed apart in their own pass
+/// (joints get moved back together in the same pass so their drift doesnt end up in the velocities)
+pub fn solve_constraints(
+    settings: &SolverSettings,
+    rigidbodies: &mut ComponentStorage<PhysicsEntity>,
+    movement_system: &mut MovementSystem,
+    manifolds: &mut [&mut ContactManifold],
+    joints: &mut [&mut Joint],
+    delta_time: f32,
+    stats: &mut PhysicsStats,
+) {
+    let mut bodies: Vec<SolverBody> = Vec::new();
+    let mut body_lookup: HashMap<u32, usize> = HashMap::new();
+    let mut constraints: Vec<ContactConstraint> = Vec::new();
+    let mut joint_constraints: Vec<JointConstraint> = Vec::new();
+
+    for (joint_index, joint) in joints.iter_mut().enumerate() {
+        let entity_b = joint.entity_b.unwrap_or(WORLD_BODY);
+        let body_a = *body_lookup.entry(joint.entity_a).or_insert_with(|| {
+            bodies.push(SolverBody::new(joint.entity_a, rigidbodies.get(joint.entity_a), movement_system));
+            bodies.len() - 1
+        });
+        let body_b = *body_lookup.entry(entity_b).or_insert_with(|| {
+            // the world sits at the origin with no rotation so world anchors come out as is
+            let mut body = SolverBody::new(entity_b, rigidbodies.get(entity_b), movement_system);
+            if entity_b == WORLD_BODY {
+                body.position = Vector3::zero();
+                body.rotation = Quaternion::one();
+            }
+            bodies.push(body);
+            bodies.len() - 1
+        });
+
+        let (frame_a, frame_b) = (bodies[body_a].frame(), bodies[body_b].frame());
+        for mut row in joint.build_rows(frame_a, frame_b, delta_time) {
+            let effective_mass = bodies[body_a].row_mass(row.linear_a, row.angular_a)
+                + bodies[body_b].row_mass(row.linear_b, row.angular_b)
+                + row.softness;
+            if effective_mass <= 0.0 {
+                continue;
+            }
+            if !settings.warm_starting {
+                row.impulse = 0.0;
+            }
+            joint_constraints.push(JointConstraint { joint: joint_index, body_a, body_b, row, mass: 1.0 / effective_mass, push: 0.0 });
+        }
+    }
+
+    for (manifold_index, manifold) in manifolds.iter().enumerate() {
+        let body_a = *body_lookup.entry(manifold.entity_a).or_insert_with(|| {
+            bodies.push(SolverBody::new(manifold.entity_a, rigidbodies.get(manifold.entity_a), movement_system));
+            bodies.len() - 1
+        });
+        let body_b = *body_lookup.entry(manifold.entity_b).or_insert_with(|| {
+            bodies.push(SolverBody::new(manifold.entity_b, rigidbodies.get(manifold.entity_b), movement_system));
+            bodies.len() - 1
+        });
+
+        let total_inv_mass = bodies[body_a].inv_mass + bodies[body_b].inv_mass;
+        if total_inv_mass == 0.0 {
+            continue;
+        }
+
+        let normal = manifold.solver_normal().normalize();
+        let tangents = tangent_basis(normal);
+        let material = manifold.material;
+        let surface = manifold.surface_velocity();
+
+        for (point_index, point) in manifold.points.iter().enumerate() {
+            let (a, b) = (&bodies[body_a], &bodies[body_b]);
+            let r_a = point.position - a.position;
+            let r_b = point.position - b.position;
+
+            // how fast things were coming together before we touched anything, this is what bounces
+            let relative = a.point_velocity(r_a) - b.point_velocity(r_b);
+            let approach = relative.dot(normal);
+            let bounce = if approach < -settings.restitution_threshold { -material.restitution * approach } else { 0.0 };
+
+            // things that are holding still have to be pushed past static friction before they get going
+            let sliding = (relative - normal * approach - surface).magnitude();
+            let friction = if sliding < settings.static_friction_threshold { material.static_friction } else { material.dynamic_friction };
+
+            // spinning soaks up part of every impulse, leaving it out makes things overshoot and go unstable
+            let normal_mass = 1.0 / (total_inv_mass + a.angular_mass(r_a, normal) + b.angular_mass(r_b, normal));
+            let tangent_mass = tangents.map(|tangent| {
+                1.0 / (total_inv_mass + a.angular_mass(r_a, tangent) + b.angular_mass(r_b, tangent))
+            });
+
+            let (normal_impulse, tangent_impulse) = if settings.warm_starting {
+                (point.normal_impulse, point.tangent_impulse)
+            } else {
+                (0.0, [0.0, 0.0])
+            };
+
+            constraints.push(ContactConstraint {
+                manifold: manifold_index,
+                point: point_index,
+                body_a,
+                body_b,
+                r_a,
+                r_b,
+                normal,
+                tangents,
+                surface,
+                normal_mass,
+                tangent_mass,
+                bounce,
+                friction,
+                normal_impulse,
+                tangent_impulse,
+            });
+        }
+    }
+
+    stats.solver_bodies = bodies.len();
+    stats.contact_constraints = constraints.len();
+    stats.joint_rows = joint_constraints.len();
+    stats.velocity_iterations = settings.velocity_iterations;
+    stats.position_iterations = settings.position_iterations;
+
+    // === WARM START ===
+    // reapply what worked last frame so resting contacts start out already holding the weight
+    for constraint in joint_constraints.iter() {
+        constraint.apply(&mut bodies, constraint.row.impulse);
+    }
+    for constraint in constraints.iter() {
+        let impulse = constraint.normal * constraint.normal_impulse
+            + constraint.tangents[0] * constraint.tangent_impulse[0]
+            + constraint.tangents[1] * constraint.tangent_impulse[1];
+
+        bodies[constraint.body_a].apply_impulse(constraint.r_a, impulse);
+        bodies[constraint.body_b].apply_impulse(constraint.r_b, -impulse);
+    }
+
+    // === VELOCITY ITERATIONS ===
+    for _ in 0..settings.velocity_iterations {
+        // joints first so contacts get the last word, things shouldnt get pulled through the floor
+        for constraint in joint_constraints.iter_mut() {
+            constraint.solve(&mut bodies);
+        }
+
+        for constraint in constraints.iter_mut() {
+            // friction first, the normal impulse is the one we want to be the most correct at the end
+            let max_friction = constraint.normal_impulse * constraint.friction;
+            for axis in 0..2 {
+                let tangent = constraint.tangents[axis];
+                let relative = bodies[constraint.body_a].point_velocity(constraint.r_a)
+                    - bodies[constraint.body_b].point_velocity(constraint.r_b);
+                let lambda = -(relative - constraint.surface).dot(tangent) * constraint.tangent_mass[axis];
+
+                let old_impulse = constraint.tangent_impulse[axis];
+                constraint.tangent_impulse[axis] = (old_impulse + lambda).clamp(-max_friction, max_friction);
+                let impulse = tangent * (constraint.tangent_impulse[axis] - old_impulse);
+
+                bodies[constraint.body_a].apply_impulse(constraint.r_a, impulse);
+                bodies[constraint.body_b].apply_impulse(constraint.r_b, -impulse);
+            }
+
+            // normal, clamped on the total so a point can only ever push
+            let relative = bodies[constraint.body_a].point_velocity(constraint.r_a)
+                - bodies[constraint.body_b].point_velocity(constraint.r_b);
+            let lambda = (constraint.bounce - relative.dot(constraint.normal)) * constraint.normal_mass;
+
+            let old_impulse = constraint.normal_impulse;
+            constraint.normal_impulse = (old_impulse + lambda).max(0.0);
+            let impulse = constraint.normal * (constraint.normal_impulse - old_impulse);
+
+            bodies[constraint.body_a].apply_impulse(constraint.r_a, impulse);
+            bodies[constraint.body_b].apply_impulse(constraint.r_b, -impulse);
+        }
+    }
+
+    // keep the accumulated impulses for next frames warm start
+    for constraint in joint_constraints.iter() {
+        joints[constraint.joint].impulses[constraint.row.slot] = constraint.row.impulse;
+    }
+    for joint in joints.iter_mut() {
+        if joint.overloaded(delta_time) {
+            joint.broken = true;
+        }
+    }
+    for constraint in constraints.iter() {
+        let point = &mut manifolds[constraint.manifold].points[constraint.point];
+        point.normal_impulse = constraint.normal_impulse;
+        point.tangent_impulse = constraint.tangent_impulse;
+    }
+
+    // === POSITION CORRECTION ===
+    // penetration is recomputed from how far each body has already been pushed and turned so a 4 point manifold
+    // doesnt get corrected 4 times over, turning as well is what lets a slightly tilted box sit back down flat
+    for _ in 0..settings.position_iterations {
+        // joints first again so contacts get the last word
+        for constraint in joint_constraints.iter_mut() {
+            constraint.correct(&mut bodies, settings);
+        }
+        for constraint in constraints.iter() {
+            let (a, b) = (&bodies[constraint.body_a], &bodies[constraint.body_b]);
+            let moved = a.displacement(constraint.r_a) - b.displacement(constraint.r_b);
+            let penetration = manifolds[constraint.manifold].points[constraint.point].penetration - moved.dot(constraint.normal);
+
+            let correction = (settings.correction_percent * (penetration - settings.slop))
+                .clamp(0.0, settings.max_correction) * constraint.normal_mass;
+            let push = constraint.normal * correction;
+
+            bodies[constraint.body_a].apply_push(constraint.r_a, push);
+            bodies[constraint.body_b].apply_push(constraint.r_b, -push);
+        }
+    }
+
+    // === WRITE BACK ===
+    for body in bodies.iter() {
+        if body.inv_mass == 0.0 {
+            continue;
+        }
+        if let Some(velocity) = movement_system.get_velocity_mut(body.entity_id) {
+            velocity.set_linear(body.linear);
+        }
+        if let Some(coords) = movement_system.get_coords_mut(body.entity_id) {
+            coords.position += body.shift;
+
+            let angle = body.turn.magnitude();
+            if body.can_rotate && angle > 0.00001 {
+                let turn = Quaternion::from_axis_angle(body.turn / angle, Rad(angle));
+                coords.set_rotation_from_quaternion((turn * coords.rotation).normalize());
+            }
+        }
+        if body.can_rotate {
+            if let Some(rb) = rigidbodies.get_mut(body.entity_id) {
+                rb.angular_velocity = body.angular;
+            }
+        }
+    }
+}
+
+//two directions perpendicular to the normal, always picked the same way so cached friction impulses line up next frame
+fn tangent_basis(normal: Vector3<f32>) -> [Vector3<f32>; 2] {
+    let helper = if normal.x.abs() < 0.57735 {
+        Vector3::new(1.0, 0.0, 0.0)
+    } else {
+        Vector3::new(0.0, 1.0, 0.0)
+    };
+    let tangent_1 = normal.cross(helper).normalize();
+    let tangent_2 = normal.cross(tangent_1);
+    [tangent_1, tangent_2]
+}
+
+#[cfg(test)]
+mod tests {
+    use cgmath::{InnerSpace, Vector3};
+
+    use crate::ecs::collision_system::{Collider, CollisionSystem};
+    use crate::ecs::components::Velocity;
+    use crate::ecs::physics::{PhysicsEntity, PhysicsSystem};
+    use crate::ecs::world::MovementSystem;
+    use crate::model::transform::WorldCoords;
+
+    fn at_rest() -> Velocity {
+        Velocity { direction: Vector3::new(0.0, 0.0, 0.0), speed: 0.0 }
+    }
+
+    #[test]
+    fn ten_box_stack_settles() {
+        let mut movement = MovementSystem::new();
+        let mut physics = PhysicsSystem::new();
+        let mut collision = CollisionSystem::new();
+
+        movement.add_coords(0, WorldCoords::new(0.0, -0.5, 0.0, 0.0));
+        collision.add_collider(0, Collider::bounding_box(20.0, 1.0, 20.0));
+        physics.add_rigidbody(0, PhysicsEntity::static_body());
+
+        // dropped with a small gap between each box so the stack has to actually land and settle
+        for i in 1..=10 {
+            movement.add_coords(i, WorldCoords::new(0.0, i as f32 * 1.02 - 0.5, 0.0, 0.0));
+            movement.add_velocity(i, at_rest());
+            collision.add_collider(i, Collider::bounding_box(1.0, 1.0, 1.0));
+            physics.add_rigidbody(i, PhysicsEntity::box_shape(1.0, 1.0, 1.0, 1.0).with_restitution(0.0));
+        }
+
+        let delta_time = 1.0 / 60.0;
+        for _ in 0..600 {
+            physics.update(&mut movement, delta_time);
+            movement.update(delta_time);
+            collision.update(&mut movement, &mut physics, delta_time);
+        }
+
+        // each box sits on the one below it, so sinking shows up as a gap under 1 (every contact keeps about slop of overlap)
+        let mut below = 0.0;
+        for i in 1..=10 {
+            let position = movement.get_coords(i).unwrap().position;
+            let gap = position.y - below - if i == 1 { 0.5 } else { 1.0 };
+            assert!(gap.abs() < 0.01, "box {} is {} off from resting on whats under it", i, gap);
+            below = position.y;
+            // landing rocks the stack a little now that contacts can turn the boxes, the top one ends up ~0.035 off.
+            // it all goes to sleep after that so this is settling, not creep
+            assert!(position.x.abs() < 0.05 && position.z.abs() < 0.05, "box {} slid to {:?}", i, position);
+            let rigidbody = physics.get_rigidbody(i).unwrap();
+            assert!(movement.get_velocity(i).unwrap().speed < 0.05, "box {} is still moving", i);
+            assert!(rigidbody.angular_velocity.magnitude() < 0.05, "box {} is still spinning", i);
+            assert!(rigidbody.is_sleeping(), "box {} never settled enough to sleep", i);
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use std::fmt;
+use std::time::Duration;
+
+//what the last physics step did and how long each part of it took, so when a scene gets slow you can see if its the
+//broad phase (too many things near each other), the narrow phase (expensive shapes) or the solver (big piles)
+//everything is for the last step only, in deterministic mode thats the last of however many fixed steps ran that frame
+
+/// Filled in by `PhysicsSystem::update` and the solver
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PhysicsStats {
+    pub dynamic_bodies: usize,
+    pub kinematic_bodies: usize,
+    pub static_bodies: usize,
+    pub sleeping_bodies: usize, // dynamic ones that got skipped this step
+    pub islands: usize,
+    pub joints: usize,              // awake and unbroken, the ones that actually went to the solver
+    pub solver_bodies: usize,       // bodies touched by a constraint, static ones included
+    pub contact_constraints: usize, // one per contact point
+    pub joint_rows: usize,
+    pub velocity_iterations: u32,
+    pub position_iterations: u32,
+    pub integrate_time: Duration, // buoyancy, forces and moving everything along
+    pub solver_time: Duration,    // islands, sleeping and the constraint solve
+}
+
+/// Filled in by `CollisionSystem::update`
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CollisionStats {
+    pub colliders: usize,
+    pub triggers: usize,
+    pub ccd_bodies: usize,        // fast bodies that got swept
+    pub broad_phase_pairs: usize, // every pair of colliders the broad phase looked at
+    pub candidate_pairs: usize,   // boxes overlapped and the layers allowed it, these went on to the narrow phase
+    pub colliding_pairs: usize,   // actually touching, triggers included
+    pub manifolds: usize,
+    pub contact_points: usize,
+    pub ccd_time: Duration,
+    pub broad_phase_time: Duration,
+    pub narrow_phase_time: Duration, // the shape checks plus building the manifolds
+}
+
+/// Both halves of one step, from `World::physics_stats`. Prints as a few lines for logging
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct StepStats {
+    pub physics: PhysicsStats,
+    pub collision: CollisionStats,
+}
+
+impl StepStats {
+    pub fn total_time(&self) -> Duration {
+        self.physics.integrate_time + self.physics.solver_time
+            + self.collision.ccd_time + self.collision.broad_phase_time + self.collision.narrow_phase_time
+    }
+}
+
+impl fmt::Display for StepStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (physics, collision) = (&self.physics, &self.collision);
+        let ms = |time: Duration| time.as_secs_f64() * 1000.0;
+        writeln!(
+            f,
+            "bodies: {} dynamic ({} asleep), {} kinematic, {} static, {} islands",
+            physics.dynamic_bodies, physics.sleeping_bodies, physics.kinematic_bodies, physics.static_bodies, physics.islands
+        )?;
+        writeln!(
+            f,
+            "collision: {} colliders ({} triggers), {} pairs -> {} candidates -> {} colliding, {} manifolds, {} points, {} ccd",
+            collision.colliders, collision.triggers, collision.broad_phase_pairs, collision.candidate_pairs,
+            collision.colliding_pairs, collision.manifolds, collision.contact_points, collision.ccd_bodies
+        )?;
+        writeln!(
+            f,
+            "solver: {} bodies, {} contacts, {} joints ({} rows), {}/{} iterations",
+            physics.solver_bodies, physics.contact_constraints, physics.joints, physics.joint_rows,
+            physics.velocity_iterations, physics.position_iterations
+        )?;
+        write!(
+            f,
+            "time: integrate {:.3}ms, ccd {:.3}ms, broad {:.3}ms, narrow {:.3}ms, solve {:.3}ms, total {:.3}ms",
+            ms(physics.integrate_time), ms(collision.ccd_time), ms(collision.broad_phase_time),
+            ms(collision.narrow_phase_time), ms(physics.solver_time), ms(self.total_time())
+        )
+    }
+}
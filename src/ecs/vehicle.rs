@@ -0,0 +1,386 @@
+use cgmath::{InnerSpace, Quaternion, Rad, Rotation, Rotation3, Vector3, Zero};
+
+use super::collision_system::CollisionSystem;
+use super::layers::QueryFilter;
+use super::physics::PhysicsSystem;
+use super::world::{ComponentStorage, MovementSystem};
+
+//raycast cars, the chassis is a normal dynamic rigidbody and each wheel is just a ray shot down from where its
+//suspension is mounted. Whatever the ray hits is the road, the spring, grip and drive forces all get pushed into the
+//chassis at the contact points so it pitches under braking and rolls in corners on its own
+//the chassis is forward down -z and right along +x, same as WorldCoords::get_forward_vector
+
+/// Grip against slip, rises in a straight line to `peak` at `peak_slip` then falls off to `sliding` once its past
+/// `slide_slip`. The forces are these times the load on the wheel
+#[derive(Debug, Clone, Copy)]
+pub struct TireCurve {
+    pub peak_slip: f32,
+    pub peak: f32,
+    pub slide_slip: f32,
+    pub sliding: f32,
+}
+
+impl TireCurve {
+    pub fn new(peak_slip: f32, peak: f32, slide_slip: f32, sliding: f32) -> Self {
+        Self { peak_slip, peak, slide_slip, sliding }
+    }
+
+    /// Grip for a slip amount (ratio for longitudinal, radians for lateral), same sign as `slip`
+    pub fn force(&self, slip: f32) -> f32 {
+        let amount = slip.abs();
+        let grip = if amount <= self.peak_slip {
+            self.peak * amount / self.peak_slip
+        } else if amount >= self.slide_slip {
+            self.sliding
+        } else {
+            let t = (amount - self.peak_slip) / (self.slide_slip - self.peak_slip);
+            self.peak + (self.sliding - self.peak) * t
+        };
+        grip * slip.signum()
+    }
+
+    //grip per unit of slip at `slip`, the wheel spin uses this to stay stable at low speed
+    fn stiffness(&self, slip: f32) -> f32 {
+        if slip.abs() < 1e-4 {
+            self.peak / self.peak_slip
+        } else {
+            self.force(slip) / slip
+        }
+    }
+}
+
+/// Where a wheel is touching the ground this step
+#[derive(Debug, Clone, Copy)]
+pub struct WheelContact {
+    pub entity_id: u32,
+    pub point: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub load: f32,             // how hard the suspension is pushing, newtons
+    pub longitudinal_slip: f32,
+    pub lateral_slip: f32,     // slip angle in radians
+}
+
+#[derive(Debug, Clone)]
+pub struct Wheel {
+    pub mount: Vector3<f32>, // where the top of the suspension is in the chassis space
+    pub radius: f32,
+    pub rest_length: f32,    // suspension length with nothing pushing on it
+    pub stiffness: f32,
+    pub damping: f32,
+    pub inertia: f32,        // how hard it is to spin up, bigger is less wheelspin
+    pub steered: bool,
+    pub driven: bool,
+
+    compression: f32,
+    spin: f32,       // radians per second, positive rolls forward
+    spin_angle: f32, // for drawing
+    steer_angle: f32,
+    contact: Option<WheelContact>,
+}
+
+impl Wheel {
+    pub fn new(mount: Vector3<f32>, radius: f32) -> Self {
+        Self {
+            mount,
+            radius,
+            rest_length: 0.4,
+            stiffness: 30000.0,
+            damping: 3000.0,
+            inertia: 1.0,
+            steered: false,
+            driven: false,
+            compression: 0.0,
+            spin: 0.0,
+            spin_angle: 0.0,
+            steer_angle: 0.0,
+            contact: None,
+        }
+    }
+
+    pub fn with_suspension(mut self, rest_length: f32, stiffness: f32, damping: f32) -> Self {
+        self.rest_length = rest_length;
+        self.stiffness = stiffness;
+        self.damping = damping;
+        self
+    }
+
+    pub fn with_inertia(mut self, inertia: f32) -> Self {
+        self.inertia = inertia;
+        self
+    }
+
+    pub fn steered(mut self) -> Self {
+        self.steered = true;
+        self
+    }
+
+    pub fn driven(mut self) -> Self {
+        self.driven = true;
+        self
+    }
+
+    pub fn contact(&self) -> Option<&WheelContact> {
+        self.contact.as_ref()
+    }
+
+    pub fn spin(&self) -> f32 {
+        self.spin
+    }
+
+    /// Where the wheels center is and how its turned (steering then rolling) in the chassis space, for drawing it
+    pub fn local_transform(&self) -> (Vector3<f32>, Quaternion<f32>) {
+        let length = self.rest_length - self.compression;
+        let position = self.mount - Vector3::unit_y() * length;
+        let rotation = Quaternion::from_angle_y(Rad(self.steer_angle)) * Quaternion::from_angle_x(Rad(-self.spin_angle));
+        (position, rotation)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Vehicle {
+    pub wheels: Vec<Wheel>,
+    pub engine_torque: f32,   // at full throttle, split over the driven wheels
+    pub brake_torque: f32,    // per wheel
+    pub max_steer_angle: f32, // radians
+    pub longitudinal: TireCurve,
+    pub lateral: TireCurve,
+
+    throttle: f32, // -1 (reverse) to 1
+    brake: f32,    // 0 to 1
+    steering: f32, // -1 (left) to 1 (right)
+}
+
+impl Vehicle {
+    pub fn new(wheels: Vec<Wheel>) -> Self {
+        Self {
+            wheels,
+            engine_torque: 1500.0,
+            brake_torque: 3000.0,
+            max_steer_angle: 35f32.to_radians(),
+            longitudinal: TireCurve::new(0.1, 1.0, 0.4, 0.75),
+            lateral: TireCurve::new(0.15, 1.0, 0.5, 0.7),
+            throttle: 0.0,
+            brake: 0.0,
+            steering: 0.0,
+        }
+    }
+
+    /// Four wheels at the corners of a `width` by `length` rectangle under the chassis, `height` down from its center.
+    /// Front wheels steer and the back ones get driven
+    pub fn four_wheels(width: f32, length: f32, height: f32, radius: f32) -> Self {
+        let (x, z) = (width / 2.0, length / 2.0);
+        Self::new(vec![
+            Wheel::new(Vector3::new(-x, -height, -z), radius).steered(),
+            Wheel::new(Vector3::new(x, -height, -z), radius).steered(),
+            Wheel::new(Vector3::new(-x, -height, z), radius).driven(),
+            Wheel::new(Vector3::new(x, -height, z), radius).driven(),
+        ])
+    }
+
+    pub fn with_engine(mut self, engine_torque: f32, brake_torque: f32) -> Self {
+        self.engine_torque = engine_torque;
+        self.brake_torque = brake_torque;
+        self
+    }
+
+    pub fn with_max_steer(mut self, degrees: f32) -> Self {
+        self.max_steer_angle = degrees.to_radians();
+        self
+    }
+
+    pub fn with_tires(mut self, longitudinal: TireCurve, lateral: TireCurve) -> Self {
+        self.longitudinal = longitudinal;
+        self.lateral = lateral;
+        self
+    }
+
+    pub fn set_throttle(&mut self, throttle: f32) {
+        self.throttle = throttle.clamp(-1.0, 1.0);
+    }
+
+    pub fn set_brake(&mut self, brake: f32) {
+        self.brake = brake.clamp(0.0, 1.0);
+    }
+
+    pub fn set_steering(&mut self, steering: f32) {
+        self.steering = steering.clamp(-1.0, 1.0);
+    }
+
+    pub fn is_grounded(&self) -> bool {
+        self.wheels.iter().any(|wheel| wheel.contact.is_some())
+    }
+
+    /// How fast its going forward (negative in reverse), going by the wheels on the ground
+    pub fn wheel_speed(&self) -> f32 {
+        let grounded: Vec<&Wheel> = self.wheels.iter().filter(|wheel| wheel.contact.is_some()).collect();
+        if grounded.is_empty() {
+            return 0.0;
+        }
+        grounded.iter().map(|wheel| wheel.spin * wheel.radius).sum::<f32>() / grounded.len() as f32
+    }
+}
+
+pub struct VehicleSystem {
+    vehicles: ComponentStorage<Vehicle>,
+}
+
+impl Default for VehicleSystem {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VehicleSystem {
+    pub fn new() -> Self {
+        Self {
+            vehicles: ComponentStorage::new(),
+        }
+    }
+
+    pub fn add_vehicle(&mut self, entity_id: u32, vehicle: Vehicle) {
+        self.vehicles.insert(entity_id, vehicle);
+    }
+
+    pub fn get_vehicle(&self, entity_id: u32) -> Option<&Vehicle> {
+        self.vehicles.get(entity_id)
+    }
+
+    pub fn get_vehicle_mut(&mut self, entity_id: u32) -> Option<&mut Vehicle> {
+        self.vehicles.get_mut(entity_id)
+    }
+
+    pub fn remove_vehicle(&mut self, entity_id: u32) {
+        self.vehicles.remove(entity_id);
+    }
+
+    /// Casts the wheel rays and pushes the suspension, grip and drive forces into each chassis, goes right before
+    /// the physics update so they get integrated in the same step
+    pub fn update(&mut self, movement_system: &MovementSystem, collision_system: &CollisionSystem, physics_system: &mut PhysicsSystem, delta_time: f32) {
+        if delta_time <= 0.0 {
+            return;
+        }
+
+        for entity_id in self.vehicles.sorted_ids() {
+            let Some(vehicle) = self.vehicles.get_mut(entity_id) else { continue };
+            let Some(coords) = movement_system.get_coords(entity_id) else { continue };
+            let Some(chassis) = physics_system.get_rigidbody(entity_id) else { continue };
+            if chassis.is_static() || chassis.is_kinematic {
+                continue;
+            }
+            let (position, rotation) = (coords.position, coords.rotation);
+            let linear = movement_system.get_velocity(entity_id).map_or(Vector3::zero(), |velocity| velocity.linear());
+            let angular = chassis.angular_velocity;
+            let inverse_mass = chassis.inverse_mass;
+            let inverse_inertia = chassis.world_inverse_inertia(rotation);
+            let idle = vehicle.throttle == 0.0 && vehicle.brake == 0.0;
+            if chassis.is_sleeping() && idle {
+                continue;
+            }
+
+            let up = rotation.rotate_vector(Vector3::unit_y());
+            let filter = QueryFilter::new().excluding(entity_id);
+            let driven = vehicle.wheels.iter().filter(|wheel| wheel.driven).count().max(1) as f32;
+            let drive_torque = vehicle.throttle * vehicle.engine_torque / driven;
+
+            // cast every wheel first, how much weight each one carries depends on how many are down
+            let hits: Vec<_> = vehicle.wheels.iter().map(|wheel| {
+                let mount = position + rotation.rotate_vector(wheel.mount);
+                collision_system.raycast(movement_system, mount, -up, wheel.rest_length + wheel.radius, &filter)
+            }).collect();
+            let grounded = hits.iter().filter(|hit| hit.is_some()).count().max(1) as f32;
+            // how much the chassis resists being pushed along `direction` at `point`, spin included, split between the wheels
+            let mass_share = |point: Vector3<f32>, direction: Vector3<f32>| {
+                let arm = (point - position).cross(direction);
+                1.0 / ((inverse_mass + arm.dot(inverse_inertia * arm)) * grounded)
+            };
+
+            let mut forces: Vec<(Vector3<f32>, Vector3<f32>)> = Vec::new();
+            for (wheel, hit) in vehicle.wheels.iter_mut().zip(hits) {
+                wheel.steer_angle = if wheel.steered { -vehicle.steering * vehicle.max_steer_angle } else { 0.0 };
+
+                let Some(hit) = hit else {
+                    // up in the air, the suspension drops all the way and the wheel just spins
+                    wheel.compression = 0.0;
+                    wheel.contact = None;
+                    if wheel.driven {
+                        wheel.spin += drive_torque / wheel.inertia * delta_time;
+                    }
+                    wheel.spin = brake_spin(wheel.spin, vehicle.brake * vehicle.brake_torque / wheel.inertia * delta_time);
+                    wheel.spin_angle += wheel.spin * delta_time;
+                    continue;
+                };
+
+                // spring and damper, the damper goes off how fast its compressing
+                let compression = (wheel.rest_length + wheel.radius - hit.distance).max(0.0);
+                let compression_speed = (compression - wheel.compression) / delta_time;
+                wheel.compression = compression;
+                let load = (wheel.stiffness * compression + wheel.damping * compression_speed).max(0.0);
+
+                // the wheels own directions flattened onto the ground
+                let heading = rotation * Quaternion::from_angle_y(Rad(wheel.steer_angle));
+                let forward = heading.rotate_vector(-Vector3::unit_z());
+                let forward = (forward - hit.normal * forward.dot(hit.normal)).normalize();
+                let right = forward.cross(hit.normal);
+
+                let point_velocity = linear + angular.cross(hit.point - position);
+                let forward_speed = point_velocity.dot(forward);
+                let side_speed = point_velocity.dot(right);
+                let reference = forward_speed.abs().max(1.0); // keeps the slip sane when nearly stopped
+
+                // wheel spin, solved implicitly against the grip so it stays stable at low speed and big loads
+                let slip = (wheel.spin * wheel.radius - forward_speed) / reference;
+                let grip = load * vehicle.longitudinal.stiffness(slip) / reference;
+                let torque = if wheel.driven { drive_torque } else { 0.0 };
+                let step = delta_time / wheel.inertia;
+                wheel.spin = (wheel.spin + step * (torque + wheel.radius * grip * forward_speed))
+                    / (1.0 + step * wheel.radius * wheel.radius * grip);
+                wheel.spin = brake_spin(wheel.spin, vehicle.brake * vehicle.brake_torque * step);
+                wheel.spin_angle += wheel.spin * delta_time;
+
+                let longitudinal_slip = (wheel.spin * wheel.radius - forward_speed) / reference;
+                let lateral_slip = side_speed.atan2(reference);
+                let mut longitudinal = load * vehicle.longitudinal.force(longitudinal_slip);
+                let mut lateral = -load * vehicle.lateral.force(lateral_slip);
+
+                // no more than it would take to stop the slipping this step, or parked cars jitter
+                let slipping = (wheel.spin * wheel.radius - forward_speed).abs() * mass_share(hit.point, forward) / delta_time;
+                let sliding = side_speed.abs() * mass_share(hit.point, right) / delta_time;
+                longitudinal = longitudinal.clamp(-slipping, slipping);
+                lateral = lateral.clamp(-sliding, sliding);
+
+                // friction circle, a tire cant give its full grip both ways at once
+                let most = load * vehicle.longitudinal.peak.max(vehicle.lateral.peak);
+                let total = (longitudinal * longitudinal + lateral * lateral).sqrt();
+                if total > most && total > 0.0 {
+                    longitudinal *= most / total;
+                    lateral *= most / total;
+                }
+
+                wheel.contact = Some(WheelContact {
+                    entity_id: hit.entity_id,
+                    point: hit.point,
+                    normal: hit.normal,
+                    load,
+                    longitudinal_slip,
+                    lateral_slip,
+                });
+                forces.push((hit.point, up * load + forward * longitudinal + right * lateral));
+            }
+
+            let Some(chassis) = physics_system.get_rigidbody_mut(entity_id) else { continue };
+            for (point, force) in forces {
+                chassis.apply_force(force);
+                chassis.apply_torque((point - position).cross(force));
+            }
+        }
+    }
+}
+
+//brakes slow the spin down to nothing but never spin it backwards
+fn brake_spin(spin: f32, amount: f32) -> f32 {
+    if spin.abs() <= amount {
+        0.0
+    } else {
+        spin - amount * spin.signum()
+    }
+}
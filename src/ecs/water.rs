@@ -0,0 +1,218 @@
+use cgmath::{InnerSpace, Quaternion, Rotation, Vector3, Zero};
+
+use super::aabb::Aabb;
+use super::collision_system::{compound_children, Collider, CollisionShape};
+use super::physics::unit_mass_properties;
+
+//the water the physics can see, its the same waves water_vertex_shader.glsl draws worked out on the cpu so anything
+//floating bobs along with what's on screen. Feed the renderer the uniforms from here (and `time`) to keep them in sync
+
+/// Same wave parameters as the water shader. `base_height` is the y of the flat water before any waves
+#[derive(Debug, Clone, Copy)]
+pub struct WaterSurface {
+    pub base_height: f32,
+    pub wave_speed: f32,
+    pub wave_scale: f32,
+    pub wave_height: f32,
+    pub time: f32,       // the shaders timeFactor
+    pub time_scale: f32, // how fast `time` runs compared to the physics clock, the tester passes time * 0.1
+    pub density: f32,    // kg per cubic unit, 1000 is real water
+    pub bounds: Option<(f32, f32, f32, f32)>, // min x, min z, max x, max z, None for an endless ocean
+}
+
+impl Default for WaterSurface {
+    fn default() -> Self {
+        // what engine_tester's WaterRender sets its uniforms to
+        Self {
+            base_height: 0.0,
+            wave_speed: 1.0,
+            wave_scale: 0.1,
+            wave_height: 0.5,
+            time: 0.0,
+            time_scale: 0.1,
+            density: 1000.0,
+            bounds: None,
+        }
+    }
+}
+
+impl WaterSurface {
+    pub fn new(base_height: f32) -> Self {
+        Self { base_height, ..Self::default() }
+    }
+
+    pub fn with_waves(mut self, wave_speed: f32, wave_scale: f32, wave_height: f32) -> Self {
+        self.wave_speed = wave_speed;
+        self.wave_scale = wave_scale;
+        self.wave_height = wave_height;
+        self
+    }
+
+    pub fn with_time_scale(mut self, time_scale: f32) -> Self {
+        self.time_scale = time_scale;
+        self
+    }
+
+    pub fn with_density(mut self, density: f32) -> Self {
+        self.density = density;
+        self
+    }
+
+    pub fn with_bounds(mut self, min_x: f32, min_z: f32, max_x: f32, max_z: f32) -> Self {
+        self.bounds = Some((min_x, min_z, max_x, max_z));
+        self
+    }
+
+    /// Moves the waves along by `delta_time` of physics time, the physics system does this every step
+    pub fn advance(&mut self, delta_time: f32) {
+        self.time += delta_time * self.time_scale;
+    }
+
+    pub fn contains(&self, x: f32, z: f32) -> bool {
+        self.bounds.is_none_or(|(min_x, min_z, max_x, max_z)| x >= min_x && x <= max_x && z >= min_z && z <= max_z)
+    }
+
+    /// The water height at (x, z), straight from the shader
+    pub fn height_at(&self, x: f32, z: f32) -> f32 {
+        let phase = self.time * self.wave_speed;
+        let wave = (x * self.wave_scale + phase).sin()
+            + (z * self.wave_scale * 0.5 + phase).sin() * 0.5
+            + (z * self.wave_scale * 0.25 + phase).sin() * 0.25
+            + shader_noise(x * 0.1, z * 0.1) * 0.5;
+        self.base_height + wave * self.wave_height
+    }
+
+    /// Surface normal at (x, z). This is the real slope of the waves, the shader uses a rougher one for lighting.
+    /// The noise bit is a fixed bump per spot so it gets left out
+    pub fn normal_at(&self, x: f32, z: f32) -> Vector3<f32> {
+        let phase = self.time * self.wave_speed;
+        let scale = self.wave_scale;
+        let slope_x = self.wave_height * scale * (x * scale + phase).cos();
+        let slope_z = self.wave_height * scale
+            * ((z * scale * 0.5 + phase).cos() * 0.25 + (z * scale * 0.25 + phase).cos() * 0.0625);
+        Vector3::new(-slope_x, 1.0, -slope_z).normalize()
+    }
+
+    /// How far under the surface `point` is, negative when its above the water or outside the bounds
+    pub fn depth_at(&self, point: Vector3<f32>) -> f32 {
+        if !self.contains(point.x, point.z) {
+            return f32::NEG_INFINITY;
+        }
+        self.height_at(point.x, point.z) - point.y
+    }
+}
+
+//glsl's `fract(sin(dot(p, vec2(12.9898, 78.233))) * 43758.5453)`, fract there is x - floor(x) so negatives wrap the same way
+fn shader_noise(x: f32, z: f32) -> f32 {
+    let value = (x * 12.9898 + z * 78.233).sin() * 43_758.547; // 43758.5453 in the shader, this is that as an f32
+    value - value.floor()
+}
+
+/// Lets a rigidbody float. Its volume is split over a grid of sample points and each one under the surface pushes up
+/// where it is, so a half sunk crate tips itself level and a long one rocks over the waves
+#[derive(Debug, Clone)]
+pub struct Buoyancy {
+    pub points: Vec<Vector3<f32>>, // in the entities space
+    pub volume: f32,               // all the points together
+    pub cell_height: f32,          // how tall each points slice is, a point goes under gradually over this
+    pub linear_drag: f32,          // per second, times how much of the body is under
+    pub angular_drag: f32,
+}
+
+impl Buoyancy {
+    /// Samples points inside the collider on a `resolution` per side grid (3 or 4 is plenty for crates).
+    /// 2d shapes and heightfields dont float so they get no points
+    pub fn for_collider(collider: &Collider, resolution: u32) -> Self {
+        let resolution = resolution.max(1);
+        let identity = Quaternion::new(1.0, 0.0, 0.0, 0.0);
+        let shape = &collider.shape;
+        let volume = shape_volume(shape);
+
+        let mut points = Vec::new();
+        let mut cell_height = 0.0;
+        if volume > 0.0 {
+            // of_shape leaves an OBBs own rotation to the caller
+            let rotation = match shape {
+                CollisionShape::OBB { rotation, .. } => *rotation,
+                _ => identity,
+            };
+            let bounds = Aabb::of_shape(shape, Vector3::zero(), rotation);
+            let cell = (bounds.max - bounds.min) / resolution as f32;
+            cell_height = cell.y;
+            for i in 0..resolution {
+                for j in 0..resolution {
+                    for k in 0..resolution {
+                        let point = bounds.min + Vector3::new(
+                            cell.x * (i as f32 + 0.5),
+                            cell.y * (j as f32 + 0.5),
+                            cell.z * (k as f32 + 0.5),
+                        );
+                        if contains_point(shape, point) {
+                            points.push(point + collider.offset);
+                        }
+                    }
+                }
+            }
+            // thin shapes can slip between the grid points, the middle always counts
+            if points.is_empty() {
+                points.push(collider.offset);
+            }
+        }
+
+        Self { points, volume, cell_height, linear_drag: 1.0, angular_drag: 1.0 }
+    }
+
+    pub fn with_drag(mut self, linear_drag: f32, angular_drag: f32) -> Self {
+        self.linear_drag = linear_drag;
+        self.angular_drag = angular_drag;
+        self
+    }
+
+    /// How much of each points volume is under `water`, 0 to 1, for a body at `position`/`rotation`
+    pub(crate) fn submerged(&self, water: &WaterSurface, position: Vector3<f32>, rotation: Quaternion<f32>) -> Vec<(Vector3<f32>, f32)> {
+        self.points.iter().map(|local| {
+            let point = position + rotation.rotate_vector(*local);
+            let depth = water.depth_at(point);
+            let fraction = if self.cell_height > 0.0 {
+                (depth / self.cell_height + 0.5).clamp(0.0, 1.0)
+            } else if depth > 0.0 {
+                1.0
+            } else {
+                0.0
+            };
+            (point, fraction)
+        }).collect()
+    }
+}
+
+fn shape_volume(shape: &CollisionShape) -> f32 {
+    match shape {
+        CollisionShape::Compound(children) => children.iter().map(|(child, _)| shape_volume(&child.shape)).sum(),
+        _ => unit_mass_properties(shape).map_or(0.0, |(volume, ..)| volume),
+    }
+}
+
+//is a point (in the shapes own space) inside it
+fn contains_point(shape: &CollisionShape, point: Vector3<f32>) -> bool {
+    match shape {
+        CollisionShape::Sphere { radius } => point.magnitude2() <= radius * radius,
+        CollisionShape::Box { width, height, depth } => {
+            point.x.abs() <= width / 2.0 && point.y.abs() <= height / 2.0 && point.z.abs() <= depth / 2.0
+        },
+        CollisionShape::OBB { half_extents, rotation } => {
+            let local = rotation.invert().rotate_vector(point);
+            local.x.abs() <= half_extents.x && local.y.abs() <= half_extents.y && local.z.abs() <= half_extents.z
+        },
+        CollisionShape::Capsule { radius, half_height } => {
+            let closest = Vector3::new(0.0, point.y.clamp(-half_height, *half_height), 0.0);
+            (point - closest).magnitude2() <= radius * radius
+        },
+        CollisionShape::Compound(children) => {
+            compound_children(children, Vector3::zero(), Quaternion::new(1.0, 0.0, 0.0, 0.0)).any(|(_, position, rotation, child)| {
+                contains_point(&child.shape, rotation.invert().rotate_vector(point - position))
+            })
+        },
+        CollisionShape::ConvexHull(hull) => hull.contains(point),
+        CollisionShape::Circle { .. } | CollisionShape::Rectangle { .. } | CollisionShape::Heightfield(..) => false,
+    }
+}
@@ -13,6 +13,19 @@ use crate::ecs::components::Renderable;
 // use crate::ecs::player::Player;
 use crate::user_interface::text_render::TextRenderer;
 use super::collision_system::{CollisionSystem, Collider, CollisionShape, CollisionEvent};
+use super::character::{CharacterController, CharacterSystem};
+use super::vehicle::{Vehicle, VehicleSystem};
+use super::ragdoll::{Ragdoll, Skeleton};
+use super::cloth::{Cloth, ClothSystem};
+use super::particles::{ParticleEmitter, ParticleSystem};
+use super::navmesh::{NavMeshBuilder, NavMeshSettings, NavigationSystem};
+use super::layers::{CollisionLayers, QueryFilter, ALL_LAYERS, DEFAULT_LAYER};
+use super::queries::RaycastHit;
+use super::snapshot::PhysicsSnapshot;
+use super::contact_manifold::ModifiableContact;
+use super::stats::StepStats;
+use super::force_fields::ForceField;
+use super::materials::{ContactMaterial, MaterialId, PhysicsMaterial};
 // use glfw::RenderContext;
 
 use super::components::Velocity;
@@ -69,6 +82,14 @@ impl<T: Component> ComponentStorage<T> {
     pub fn iter_mut(&mut self) -> impl Iterator<Item = (&u32, &mut T)> {
         self.components.iter_mut()
     }
+
+    /// Every entity with this component, smallest id first. The HashMap hands them out in a different order every
+    /// run so anything that has to come out the same every time (physics) walks these instead
+    pub fn sorted_ids(&self) -> Vec<u32> {
+        let mut ids: Vec<u32> = self.components.keys().copied().collect();
+        ids.sort_unstable();
+        ids
+    }
 }
 
 // Registry for all entities bro again who cares about this shit
@@ -163,11 +184,20 @@ impl MovementSystem {
         self.coords.get_mut(entity_id)
     }
 
+    pub fn remove_coords(&mut self, entity_id: u32) {
+        self.coords.remove(entity_id);
+    }
+
+    pub fn remove_velocity(&mut self, entity_id: u32) {
+        self.velocities.remove(entity_id);
+    }
+
     pub fn update(&mut self, delta_time: f32) {
         // Safe to iterate and modify because we own all the data
-        for (entity_id, velocity) in self.velocities.iter() {
+        for entity_id in self.velocities.sorted_ids() {
+            let Some(velocity) = self.velocities.get(entity_id) else { continue };
             //println!("first: {}", entity_id);
-            if let Some(coords) = self.coords.get_mut(*entity_id) {
+            if let Some(coords) = self.coords.get_mut(entity_id) {
                 //println!("second: {}", entity_id);
                 coords.position += velocity.direction * velocity.speed * delta_time;
             }
@@ -243,6 +273,59 @@ pub struct World {
     pub ui: UISystem,
     pub collision: CollisionSystem,
     pub physics: PhysicsSystem,
+    pub characters: CharacterSystem,
+    pub vehicles: VehicleSystem,
+    pub cloths: ClothSystem,
+    pub particles: ParticleSystem,
+    pub navigation: NavigationSystem,
+    pub layers: CollisionLayers,
+}
+
+// every system that moves during a physics step, borrowed out of the world so a step doesnt need the ui or renderer
+pub(crate) struct Simulation<'a> {
+    pub movement: &'a mut MovementSystem,
+    pub collision: &'a mut CollisionSystem,
+    pub physics: &'a mut PhysicsSystem,
+    pub characters: &'a mut CharacterSystem,
+    pub vehicles: &'a mut VehicleSystem,
+    pub navigation: &'a mut NavigationSystem,
+    pub cloths: &'a mut ClothSystem,
+    pub particles: &'a mut ParticleSystem,
+}
+
+impl Simulation<'_> {
+    // deterministic physics only ever moves in fixed steps, a slow frame runs a few and a fast one might run none
+    pub fn step_frame(&mut self, delta_time: f32) {
+        if self.physics.deterministic {
+            let step = self.physics.fixed_timestep;
+            for _ in 0..self.physics.fixed_steps(delta_time) {
+                self.step(step);
+            }
+        } else {
+            self.step(delta_time);
+        }
+    }
+
+    pub fn step(&mut self, delta_time: f32) {
+        // 1. Apply forces and integrate physics
+        self.physics.apply_force_fields(self.movement, self.collision);
+        self.vehicles.update(self.movement, self.collision, self.physics, delta_time);
+        self.physics.update(self.movement, delta_time);
+
+        // characters plan their moves against where everything is now, the movement update carries them out
+        self.characters.update(self.movement, self.collision, self.physics, delta_time);
+        self.navigation.update(self.movement, delta_time);
+
+        // 2. Update movement (velocity -> position)
+        self.movement.update(delta_time);
+
+        // 3. Check and resolve collisions
+        self.collision.update(self.movement, self.physics, delta_time);
+
+        // cloth only gets pushed around by the bodies so it goes once theyre settled for the step
+        self.cloths.update(self.movement, self.collision, self.physics, delta_time);
+        self.particles.update(self.movement, self.collision, self.physics, delta_time);
+    }
 }
 
 impl World {
@@ -272,6 +355,12 @@ impl World {
             ui: UISystem::new(screen_width, screen_height, text_renderer),
             collision: CollisionSystem::new(),
             physics: PhysicsSystem::new(),
+            characters: CharacterSystem::new(),
+            vehicles: VehicleSystem::new(),
+            cloths: ClothSystem::new(),
+            particles: ParticleSystem::new(),
+            navigation: NavigationSystem::new(),
+            layers: CollisionLayers::new(),
         }
     }
 
@@ -342,6 +431,13 @@ impl World {
         entity
     }    
 
+    /// A trigger zone that pushes on whatever is in it, gravity rooms, jump pads, wind, see `ForceField`
+    pub fn create_force_field(&mut self, name: &str, x: f32, y: f32, z: f32, collider: Collider, field: ForceField) -> Entity {
+        let entity = self.create_trigger_zone(name, x, y, z, collider);
+        self.physics.add_force_field(entity.id, field);
+        entity
+    }
+
 
     pub fn update(&mut self, delta_time: f32) {
         // Update physics first
@@ -508,24 +604,15 @@ impl World {
     // }
 
     pub fn update_with_physics(&mut self, delta_time: f32) {
-        // 1. Apply forces and integrate physics
-        self.physics.update(&mut self.movement, delta_time);
-        
-        // 2. Update movement (velocity -> position)
-        self.movement.update(delta_time);
-        
-        // 3. Check and resolve collisions
-        self.collision.update(&mut self.movement, &mut self.physics, delta_time);
-        
+        self.step_physics_frame(delta_time);
+
         // 4. Update renderables
         self.render.update_transforms(&self.movement);
     }
 
     pub fn update_with_physics_and_ui(&mut self, delta_time: f32, input_system: &mut crate::input::input::InputSystem) {
         // Physics and collision
-        self.physics.update(&mut self.movement, delta_time);
-        self.movement.update(delta_time);
-        self.collision.update(&mut self.movement, &mut self.physics, delta_time);
+        self.step_physics_frame(delta_time);
         self.render.update_transforms(&self.movement);
         
         // UI
@@ -539,11 +626,26 @@ impl World {
         self.ui.update_layout();
     }
 
+    fn step_physics_frame(&mut self, delta_time: f32) {
+        self.simulation().step_frame(delta_time);
+    }
+
+    fn simulation(&mut self) -> Simulation<'_> {
+        Simulation {
+            movement: &mut self.movement,
+            collision: &mut self.collision,
+            physics: &mut self.physics,
+            characters: &mut self.characters,
+            vehicles: &mut self.vehicles,
+            navigation: &mut self.navigation,
+            cloths: &mut self.cloths,
+            particles: &mut self.particles,
+        }
+    }
+
     pub fn update_ui_with_text_input_and_collision(&mut self, delta_time: f32, input_system: &mut crate::input::input::InputSystem) {
         // Update movement and collision
-        self.movement.update(delta_time);
-        self.physics.update(&mut self.movement, delta_time);
-        self.collision.update(&mut self.movement,  &mut self.physics, delta_time);
+        self.step_physics_frame(delta_time);
         //self.collision.update_no_physics(&mut self.movement, delta_time);
         self.render.update_transforms(&self.movement);
 
@@ -757,8 +859,53 @@ impl World {
         self.collision.get_collider(entity_id)
     }
     
-    pub fn set_collision_layers(&mut self, layer_a: u32, layer_b: u32, can_collide: bool) {
-        self.collision.set_collision_layers(layer_a, layer_b, can_collide);
+    /// Gives a name to the next free layer bit and returns the mask for it, see `CollisionLayers::register`
+    pub fn register_layer(&mut self, name: &str) -> Option<u32> {
+        self.layers.register(name)
+    }
+
+    pub fn layer_mask(&self, name: &str) -> Option<u32> {
+        self.layers.mask(name)
+    }
+
+    /// Adds a material colliders can use with `Collider::with_material(name)`
+    pub fn register_material(&mut self, name: &str, material: PhysicsMaterial) -> MaterialId {
+        self.collision.materials.register(name, material)
+    }
+
+    /// Exact friction and bounce for when `a` touches `b`, for pairs the combine modes dont get right
+    pub fn set_material_pair(&mut self, a: &str, b: &str, contact: ContactMaterial) {
+        self.collision.materials.set_pair(a, b, contact);
+    }
+
+    /// See `CollisionSystem::add_contact_modifier`
+    pub fn add_contact_modifier(&mut self, modifier: impl Fn(&mut ModifiableContact) + 'static) {
+        self.collision.add_contact_modifier(modifier);
+    }
+
+    pub fn raycast(&self, origin: Vector3<f32>, direction: Vector3<f32>, max_distance: f32, filter: &QueryFilter) -> Option<RaycastHit> {
+        self.collision.raycast(&self.movement, origin, direction, max_distance, filter)
+    }
+
+    pub fn overlap_sphere(&self, center: Vector3<f32>, radius: f32, filter: &QueryFilter) -> Vec<u32> {
+        self.collision.overlap_sphere(&self.movement, center, radius, filter)
+    }
+
+    /// See `PhysicsSystem::snapshot`, push these into a `SnapshotHistory` every frame to be able to roll back
+    pub fn snapshot_physics(&self) -> PhysicsSnapshot {
+        self.physics.snapshot(&self.movement, &self.collision)
+    }
+
+    /// See `PhysicsSystem::restore`, hands back the bodies spawned after the snapshot that got taken out
+    pub fn restore_physics(&mut self, snapshot: &PhysicsSnapshot) -> Vec<u32> {
+        let spawned = self.physics.restore(snapshot, &mut self.movement, &mut self.collision);
+        self.render.update_transforms(&self.movement);
+        spawned
+    }
+
+    /// What the last physics step did and how long each phase took, `println!("{}", world.physics_stats())` to log it
+    pub fn physics_stats(&self) -> StepStats {
+        StepStats { physics: *self.physics.stats(), collision: *self.collision.stats() }
     }
     
     // Move an entity by a specific amount and handle collisions
@@ -794,7 +941,7 @@ impl World {
         self.movement.add_coords(entity.id, coords);
         
         let collider = data.collider;
-        self.collision.add_collider(entity.id, collider);
+        self.collision.add_collider(entity.id, collider.clone());
         
         match data.phys_type {
             PhysicsType::Static => {
@@ -812,7 +959,7 @@ impl World {
                 };
                 self.movement.add_velocity(entity.id, velocity);
                 
-                let rb = PhysicsEntity::new(data.mass.unwrap_or(1.0))
+                let rb = PhysicsEntity::from_collider(data.mass.unwrap_or(1.0), &collider)
                     .with_restitution(data.restitution.unwrap_or(0.3))
                     .with_friction(data.friction.unwrap_or(0.5));
                 self.physics.add_rigidbody(entity.id, rb);
@@ -849,8 +996,10 @@ impl World {
                     depth: size.z,
                 },
                 is_trigger: false,
-                layer: 0,
+                membership: DEFAULT_LAYER,
+                filter: ALL_LAYERS,
                 offset: Vector3::new(0.0, 0.0, 0.0),
+                material: None,
             });
             
             self.physics.add_rigidbody(entity.id, PhysicsEntity::static_body());
@@ -872,11 +1021,13 @@ impl World {
         self.collision.add_collider(entity.id, Collider {
             shape: CollisionShape::Sphere { radius },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         });
         
-        self.physics.add_rigidbody(entity.id, PhysicsEntity::new(mass));
+        self.physics.add_rigidbody(entity.id, PhysicsEntity::sphere(mass, radius));
         
         entity
     }
@@ -899,11 +1050,13 @@ impl World {
                 depth: size.z,
             },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         });
         
-        self.physics.add_rigidbody(entity.id, PhysicsEntity::new(mass));
+        self.physics.add_rigidbody(entity.id, PhysicsEntity::box_shape(mass, size.x, size.y, size.z));
         
         entity
     }
@@ -922,19 +1075,91 @@ impl World {
         self.collision.add_collider(entity.id, Collider {
             shape: CollisionShape::Sphere { radius },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         });
         
         self.physics.add_rigidbody(entity.id, 
-            PhysicsEntity::new(mass)
+            PhysicsEntity::sphere(mass, radius)
                 .with_restitution(0.9)  // Very bouncy
                 .with_friction(0.1)     // Low friction
+                .with_ccd(true)         // these get thrown around fast enough to go through walls
         );
         
         entity
     }
     
+    /// Spawn a capsule that walks around with a character controller, `height` includes the round ends
+    pub fn spawn_character(&mut self, name: &str, position: Vector3<f32>, radius: f32, height: f32, controller: CharacterController) -> Entity {
+        let entity = self.create_entity(name);
+
+        self.movement.add_coords(entity.id, WorldCoords::new(position.x, position.y, position.z, 0.0));
+        self.movement.add_velocity(entity.id, Velocity {
+            direction: Vector3::new(0.0, 0.0, 0.0),
+            speed: 0.0,
+        });
+
+        self.collision.add_collider(entity.id, Collider::capsule(radius, height));
+        // kinematic so it pushes dynamic things out of the way but nothing pushes it
+        self.physics.add_rigidbody(entity.id, PhysicsEntity::kinematic());
+        self.characters.add_controller(entity.id, controller);
+
+        entity
+    }
+
+    /// Spawn a car, `size` is the chassis box and the wheels hang off it wherever `vehicle` says
+    pub fn spawn_vehicle(&mut self, name: &str, position: Vector3<f32>, size: Vector3<f32>, mass: f32, vehicle: Vehicle) -> Entity {
+        let entity = self.create_entity(name);
+
+        self.movement.add_coords(entity.id, WorldCoords::new(position.x, position.y, position.z, 0.0));
+        self.movement.add_velocity(entity.id, Velocity {
+            direction: Vector3::new(0.0, 0.0, 0.0),
+            speed: 0.0,
+        });
+
+        self.collision.add_collider(entity.id, Collider::bounding_box(size.x, size.y, size.z));
+        self.physics.add_rigidbody(entity.id, PhysicsEntity::box_shape(mass, size.x, size.y, size.z));
+        self.vehicles.add_vehicle(entity.id, vehicle);
+
+        entity
+    }
+
+    /// Spawn a ragdoll, one entity per bone called "{name}.{bone}". `position` is where the skeletons origin goes
+    pub fn spawn_ragdoll(&mut self, name: &str, skeleton: &Skeleton, position: Vector3<f32>, rotation: Quaternion<f32>) -> Result<Ragdoll, String> {
+        // check first so a bad skeleton doesnt leave half a ragdoll of empty entities behind
+        skeleton.validate()?;
+        let entity_ids: Vec<u32> = skeleton.bones.iter()
+            .map(|bone| self.create_entity(&format!("{}.{}", name, bone.name)).id)
+            .collect();
+        Ragdoll::new(skeleton, &entity_ids, position, rotation, &mut self.movement, &mut self.collision, &mut self.physics)
+    }
+
+    /// Spawn a cloth or rope, the particles are already in world space so the entity is just something to hang it on
+    pub fn spawn_cloth(&mut self, name: &str, cloth: Cloth) -> Entity {
+        let entity = self.create_entity(name);
+        self.cloths.add_cloth(entity.id, cloth);
+        entity
+    }
+
+    /// Spawn a particle emitter at `position`, move the entity around to move where new particles come from
+    pub fn spawn_emitter(&mut self, name: &str, position: Vector3<f32>, emitter: ParticleEmitter) -> Entity {
+        let entity = self.create_entity(name);
+        self.movement.add_coords(entity.id, WorldCoords::new(position.x, position.y, position.z, 0.0));
+        self.particles.add_emitter(entity.id, emitter);
+        entity
+    }
+
+    /// Bake a navmesh from every static collider and hand it to the navigation system, level meshes that arent
+    /// colliders go through a `NavMeshBuilder` and `navigation.set_navmesh` instead
+    pub fn bake_navmesh(&mut self, settings: NavMeshSettings) -> Result<(), String> {
+        let mut builder = NavMeshBuilder::new(settings);
+        builder.add_static_colliders(&self.movement, &self.collision, &self.physics);
+        self.navigation.set_navmesh(builder.build()?);
+        Ok(())
+    }
+
     /// Spawn a kinematic platform (moves but not affected by physics)
     pub fn spawn_kinematic_platform(&mut self, name: &str, position: Vector3<f32>, size: Vector3<f32>, velocity: Vector3<f32>) -> Entity {
         let entity = self.create_entity(name);
@@ -957,8 +1182,10 @@ impl World {
                 depth: size.z,
             },
             is_trigger: false,
-            layer: 0,
+            membership: DEFAULT_LAYER,
+            filter: ALL_LAYERS,
             offset: Vector3::new(0.0, 0.0, 0.0),
+            material: None,
         });
         
         self.physics.add_rigidbody(entity.id, PhysicsEntity::kinematic());
@@ -1032,4 +1259,58 @@ impl World {
     //     self.ui.layouts.remove(entity_id);
     //     self.ui.parents.remove(entity_id);
     // }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use cgmath::Vector3;
+
+    use super::*;
+    use crate::ecs::components::Velocity;
+    use crate::ecs::navmesh::{NavAgent, NavStatus};
+
+    // a falling ball and a nav agent with no navmesh, run through `frames` and then the hash and whether the agent gave up
+    fn simulate(frames: &[f32]) -> (u64, NavStatus) {
+        let mut movement = MovementSystem::new();
+        let mut collision = CollisionSystem::new();
+        let mut physics = PhysicsSystem::new().with_deterministic(1.0 / 60.0);
+        let (mut characters, mut vehicles, mut navigation) = (CharacterSystem::new(), VehicleSystem::new(), NavigationSystem::new());
+        let (mut cloths, mut particles) = (ClothSystem::new(), ParticleSystem::new());
+
+        movement.add_coords(1, WorldCoords::new(0.0, 10.0, 0.0, 0.0));
+        movement.add_velocity(1, Velocity { direction: Vector3::new(0.0, 0.0, 0.0), speed: 0.0 });
+        collision.add_collider(1, Collider::sphere(0.5));
+        physics.add_rigidbody(1, PhysicsEntity::sphere(1.0, 0.5));
+        movement.add_coords(2, WorldCoords::new(5.0, 0.0, 0.0, 0.0));
+        let mut agent = NavAgent::new(2.0);
+        agent.set_destination(Vector3::new(8.0, 0.0, 0.0));
+        navigation.add_agent(2, agent);
+
+        let mut simulation = Simulation {
+            movement: &mut movement,
+            collision: &mut collision,
+            physics: &mut physics,
+            characters: &mut characters,
+            vehicles: &mut vehicles,
+            navigation: &mut navigation,
+            cloths: &mut cloths,
+            particles: &mut particles,
+        };
+        for frame in frames {
+            simulation.step_frame(*frame);
+        }
+        (physics.state_hash(&movement), navigation.get_agent(2).unwrap().status())
+    }
+
+    #[test]
+    fn deterministic_frames_only_step_whole_timesteps() {
+        // too short for a step, nothing runs
+        assert_eq!(simulate(&[0.01]), simulate(&[]));
+        assert_eq!(simulate(&[0.01]).1, NavStatus::Moving);
+
+        // uneven frames adding up to 6 and a bit steps, the debug_assert in physics.update catches a frame sized step
+        let uneven = simulate(&[0.01, 0.03, 0.004, 0.0393, 0.0187]);
+        let fixed = simulate(&[1.0 / 60.0; 6]);
+        assert_eq!(uneven, fixed);
+        assert_eq!(uneven.1, NavStatus::NoPath, "nav agents step with the rest");
+    }
+}
@@ -142,6 +142,12 @@ impl Camera {
         &self.view
     }
 
+    /// Where the view matrix has the eye, unlike transform.position this follows the parent in first/third person
+    pub fn get_eye_position(&self) -> Vector3<f32> {
+        let translation = self.view.w.truncate();
+        -Vector3::new(self.view.x.truncate().dot(translation), self.view.y.truncate().dot(translation), self.view.z.truncate().dot(translation))
+    }
+
     pub fn get_forward_vector(&self) -> Vector3<f32> {
         self.transform.get_forward_vector()
     }
@@ -1809,7 +1809,7 @@ impl ForwardPlusRenderer {
         
         ShaderProgram::unbind();
 
-        if !transparent_models.is_empty() {
+        if !transparent_models.is_empty() || self.weighted_oit.has_particles() {
             // Resize OIT buffers if needed
             //self.weighted_oit.resize(width, height);
 
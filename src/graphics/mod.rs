@@ -8,4 +8,5 @@ pub mod lightmanager;
 // pub mod lightManager;//idk why tf is this like imma crashout basically this is not the file name
 pub mod api_trait;
 pub mod vlk_wrapper;
-pub mod weighted_oit;
\ No newline at end of file
+pub mod weighted_oit;
+pub mod particle_renderer;
\ No newline at end of file
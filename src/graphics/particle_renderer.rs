@@ -0,0 +1,122 @@
+use std::ptr;
+use gl;
+use gl::types::GLuint;
+
+use super::camera::Camera;
+use super::gl_wrapper::ShaderProgram;
+use crate::ecs::particles::PARTICLE_INSTANCE_FLOATS;
+
+//draws the instance buffer from ParticleSystem::instances as camera facing quads, one instanced draw for the lot
+//it only writes into whatever framebuffer is bound so WeightedOIT calls it inside its transparent pass
+
+pub struct ParticleRenderer {
+    shader: ShaderProgram,
+    vao: GLuint,
+    quad_vbo: GLuint,
+    instance_vbo: GLuint,
+    count: i32,
+}
+
+impl ParticleRenderer {
+    /// Must be called when GL context is valid.
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let mut shader = ShaderProgram::new(
+            "shaders/particle_billboard.vert",
+            "shaders/particle_billboard.frag"
+        );
+        shader.bind();
+        shader.create_uniforms(vec!["view", "projection"]);
+        ShaderProgram::unbind();
+
+        let stride = (PARTICLE_INSTANCE_FLOATS * std::mem::size_of::<f32>()) as i32;
+        let mut vao = 0;
+        let mut quad_vbo = 0;
+        let mut instance_vbo = 0;
+        unsafe {
+            let corners: [f32; 8] = [
+                -0.5, -0.5,
+                 0.5, -0.5,
+                -0.5,  0.5,
+                 0.5,  0.5,
+            ];
+            gl::GenVertexArrays(1, &mut vao);
+            gl::GenBuffers(1, &mut quad_vbo);
+            gl::GenBuffers(1, &mut instance_vbo);
+            gl::BindVertexArray(vao);
+
+            gl::BindBuffer(gl::ARRAY_BUFFER, quad_vbo);
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                (corners.len() * std::mem::size_of::<f32>()) as isize,
+                corners.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+            gl::EnableVertexAttribArray(0);
+            gl::VertexAttribPointer(0, 2, gl::FLOAT, gl::FALSE, 2 * 4, ptr::null());
+
+            // position + size then color, stepped once per particle instead of per corner
+            gl::BindBuffer(gl::ARRAY_BUFFER, instance_vbo);
+            gl::EnableVertexAttribArray(1);
+            gl::VertexAttribPointer(1, 4, gl::FLOAT, gl::FALSE, stride, ptr::null());
+            gl::VertexAttribDivisor(1, 1);
+            gl::EnableVertexAttribArray(2);
+            gl::VertexAttribPointer(2, 4, gl::FLOAT, gl::FALSE, stride, (4 * 4) as *const _);
+            gl::VertexAttribDivisor(2, 1);
+
+            gl::BindVertexArray(0);
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+
+        Self { shader, vao, quad_vbo, instance_vbo, count: 0 }
+    }
+
+    /// Replace what gets drawn, call once a frame with `ParticleSystem::instances`
+    pub fn upload(&mut self, instances: &[f32]) {
+        self.count = (instances.len() / PARTICLE_INSTANCE_FLOATS) as i32;
+        if self.count == 0 {
+            return;
+        }
+        unsafe {
+            gl::BindBuffer(gl::ARRAY_BUFFER, self.instance_vbo);
+            // new storage every frame so the driver doesnt wait on last frames draw
+            gl::BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(instances) as isize,
+                instances.as_ptr() as *const _,
+                gl::STREAM_DRAW,
+            );
+            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    /// Blend state and depth are left to the caller, the OIT pass already has them how it wants
+    pub fn draw(&self, camera: &Camera) {
+        if self.count == 0 {
+            return;
+        }
+        self.shader.bind();
+        self.shader.set_matrix4fv_uniform("view", camera.get_view());
+        self.shader.set_matrix4fv_uniform("projection", camera.get_p_matrix());
+        unsafe {
+            gl::BindVertexArray(self.vao);
+            gl::DrawArraysInstanced(gl::TRIANGLE_STRIP, 0, 4, self.count);
+            gl::BindVertexArray(0);
+        }
+        ShaderProgram::unbind();
+    }
+}
+
+impl Drop for ParticleRenderer {
+    fn drop(&mut self) {
+        unsafe {
+            gl::DeleteVertexArrays(1, &self.vao);
+            gl::DeleteBuffers(1, &self.quad_vbo);
+            gl::DeleteBuffers(1, &self.instance_vbo);
+        }
+    }
+}
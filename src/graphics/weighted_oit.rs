@@ -8,6 +8,7 @@ use std::rc::Rc;
 use super::gl_wrapper::{Framebuffer, ShaderProgram, depthTexture};//lol depth texture like being just used bc i am 2 lazy
 //todo fix depth texture and text manager to be all one love or make frame buffer take in texture not just depth texture
 use super::camera::Camera;
+use super::particle_renderer::ParticleRenderer;
 use crate::graphics::gl_wrapper::LightManager;
 use crate::graphics::texture_manager::RenderTexture;
 use crate::model::mesh::Mesh;
@@ -29,6 +30,7 @@ pub struct WeightedOIT {
     fs_quad_vao: GLuint,
     fs_quad_vbo: GLuint,
     depth_attached: bool,
+    particles: ParticleRenderer,
 }
 
 impl WeightedOIT {
@@ -68,6 +70,7 @@ impl WeightedOIT {
         ShaderProgram::unbind();
 
         let (fs_quad_vao, fs_quad_vbo) = Self::create_fullscreen_quad();
+        let particles = ParticleRenderer::new();
 
         Self {
             fbo,
@@ -80,6 +83,7 @@ impl WeightedOIT {
             fs_quad_vao,
             fs_quad_vbo,
             depth_attached: false,
+            particles,
         }
     }
 
@@ -109,6 +113,16 @@ impl WeightedOIT {
         //println!("Attached depth texture {} to OIT FBO", depth_texture_id);
     }
 
+    /// Particles to draw in the next transparent pass, from `ParticleSystem::instances`. They go in with the
+    /// transparent models so smoke in front of glass blends right either way round
+    pub fn set_particles(&mut self, instances: &[f32]) {
+        self.particles.upload(instances);
+    }
+
+    pub fn has_particles(&self) -> bool {
+        !self.particles.is_empty()
+    }
+
     // pub fn resize(&mut self, width: u32, height: u32) {
     //     if self.width == width && self.height == height { return; }
     //     self.width = width; self.height = height;
@@ -186,6 +200,9 @@ impl WeightedOIT {
             model.get_mesh().draw();
         }
 
+        // same targets and blending, the billboards just bring their own shader
+        self.particles.draw(camera);
+
         // 4) Restore GL state
         unsafe {
             gl::Disable(gl::DEPTH_TEST);//TODO i removed this as a test but this might have been a mistake
@@ -27,11 +27,29 @@ pub struct Mesh {
 //should all meshes use the same VAO... PROBABLY BRO, especially if all the attributes they hold are the same so yes that means we should make a mesh manager TODO so wholesum im so excited to do that
 impl Mesh {
     pub fn new(vertices: &[f32], indices: &[i32]) -> Self {
+        Self::with_usage(vertices, indices, gl::STATIC_DRAW)
+    }
+
+    /// Same layout as `new` but the vertices are meant to be replaced every frame with `update_vertices`, cloth and the like
+    pub fn new_dynamic(vertices: &[f32], indices: &[i32]) -> Self {
+        Self::with_usage(vertices, indices, gl::DYNAMIC_DRAW)
+    }
+
+    /// Swap in new vertex data, the indices stay the same so it has to be the same shape of mesh
+    pub fn update_vertices(&self, vertices: &[f32]) {
+        if vertices.is_empty() {
+            return;
+        }
+        self.vbo.store_f32_data(vertices);
+        self.vbo.unbind();
+    }
+
+    fn with_usage(vertices: &[f32], indices: &[i32], usage: gl::types::GLenum) -> Self {
         let vao = Vao::new();
         vao.bind();//
 
         //verticies buffer object
-        let vbo = BufferObject::new(gl::ARRAY_BUFFER, gl::STATIC_DRAW);
+        let vbo = BufferObject::new(gl::ARRAY_BUFFER, usage);
         vbo.bind();
         vbo.store_f32_data(vertices);
 